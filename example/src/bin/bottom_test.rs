@@ -6,8 +6,8 @@ use core::ffi::c_void;
 use embassy_preempt_executor::{OSInit, OSStart};
 use embassy_preempt_executor::AsyncOSTaskCreate;
 use embassy_preempt_log::task_log;
-use embassy_preempt_platform::get_platform;
 use embassy_preempt_platform::driver::button::future::wait_for_button;
+use embassy_preempt_platform::driver::led::driver::led;
 
 // #[embassy_preempt_macros::entry]
 #[embassy_preempt_macros::entry]
@@ -22,7 +22,7 @@ fn test_hardware() -> ! {
 
 async fn task1(_args: *mut c_void) {
     loop {
-        critical_section::with(|cs| get_platform().led.borrow(cs).toggle());
+        led().toggle();
         task_log!(info, "waiting for button");
         wait_for_button().await;
         task_log!(info, "button pressed");
@@ -4,32 +4,45 @@
 
 // FFI接口
 use core::ffi::c_void;
-use core::arch::asm;
 
-use embassy_preempt_executor::SyncOSTaskCreate;
+use embassy_preempt_executor::{AsyncOSTaskCreate, SyncOSTaskCreate};
 use embassy_preempt_executor::{OSInit, OSStart};
 use embassy_preempt_executor::os_time::OSTimeDly;
-use embassy_preempt_platform::pac::{gpio, GPIOA, GPIOC, RCC};
+use embassy_preempt_executor::os_time::timer::Timer;
+use embassy_preempt_platform::driver::i2c::driver::{i2c1, Config, Error};
 use embassy_preempt_log::task_log;
+use embedded_hal_async::i2c::I2c;
+
+/// MPU6050 的地址（AD0 接地）和 WHO_AM_I 寄存器
+const MPU6050_ADDR: u8 = 0x68;
+const WHO_AM_I: u8 = 0x75;
 
 #[embassy_preempt_macros::entry]
 fn iic_test() -> ! {
-        iic_init();
-
-    // os初始化
+    // os初始化，同时配置时钟，I2C 要在它之后初始化
     OSInit();
-    // 
-    SyncOSTaskCreate(task1, 0 as *mut c_void, 0 as *mut usize, 10);
+    //
+    AsyncOSTaskCreate(task1, 0 as *mut c_void, 0 as *mut usize, 10);
     SyncOSTaskCreate(task2, 0 as *mut c_void, 0 as *mut usize, 11);
     // 启动os
     OSStart();
 }
 
-fn task1(_args: *mut c_void) {
+/// 异步任务：每秒读一次 WHO_AM_I，等待总线事件时让出 CPU，超时由内核定时器唤醒
+async fn task1(_args: *mut c_void) {
+    // PB8 (SCL) / PB9 (SDA)，400 kHz
+    let mut i2c = i2c1(Config {
+        frequency: 400_000,
+        ..Config::default()
+    });
     loop {
-        task_log!(info, "iic_test");
-       
-       OSTimeDly(400 * 100);
+        let mut id = [0u8];
+        match i2c.write_read(MPU6050_ADDR, &[WHO_AM_I], &mut id).await {
+            Ok(()) => task_log!(info, "WHO_AM_I = {}", id[0]),
+            Err(Error::Nack(_)) => task_log!(info, "no device at the address"),
+            Err(_) => task_log!(error, "i2c error"),
+        }
+        Timer::after_millis(1000).await;
     }
 }
 
@@ -41,145 +54,3 @@ fn task2(_args: *mut c_void) {
         OSTimeDly(500 * 100);
     }
 }
-
-
-// 向scl线写
-pub fn iic_w_scl(value: u8) {
-    if value > 0 {
-        GPIOC.odr().modify(|f| {
-            f.set_odr(2, gpio::vals::Odr::HIGH);
-        });
-    } else {
-        GPIOC.odr().modify(|f| {
-            f.set_odr(2, gpio::vals::Odr::LOW);
-        });
-    }
-    delay(1);
-}
-
-// 向sda线写
-pub fn iic_w_sda(value: u8) {
-    if value > 0 {
-        GPIOC.odr().modify(|f| {
-            f.set_odr(3, gpio::vals::Odr::HIGH);
-        });
-    } else {
-        GPIOC.odr().modify(|f| {
-            f.set_odr(3, gpio::vals::Odr::LOW);
-        });
-    }
-    delay(1);
-}
-
-// 读sda线
-pub fn iic_r_sda() -> u8 {
-    let value = GPIOC.idr().read().idr(3).to_bits();
-    delay(1);
-    value
-}
-
-pub fn iic_init() {
-    RCC.ahb1enr().modify(|f| {
-        f.set_gpiocen(true);
-    });
-
-    GPIOC.moder().modify(|f| {
-       f.set_moder(2, gpio::vals::Moder::OUTPUT);
-       f.set_moder(3, gpio::vals::Moder::OUTPUT); 
-    });
-
-    GPIOC.otyper().modify(|f| {
-        f.set_ot(2, gpio::vals::Ot::OPENDRAIN);
-        f.set_ot(3, gpio::vals::Ot::OPENDRAIN);
-    });
-
-    GPIOC.pupdr().modify(|f| {
-        f.set_pupdr(2, gpio::vals::Pupdr::PULLUP);
-        f.set_pupdr(3, gpio::vals::Pupdr::PULLUP);
-    });
-
-    GPIOC.ospeedr().modify(|f| {
-        f.set_ospeedr(2, gpio::vals::Ospeedr::HIGHSPEED);
-        f.set_ospeedr(3, gpio::vals::Ospeedr::HIGHSPEED);
-    });
-
-    // Set GPIOC pins 2 and 3 high
-    GPIOC.odr().modify(|f| {
-        f.set_odr(2, gpio::vals::Odr::HIGH);
-        f.set_odr(3, gpio::vals::Odr::HIGH);
-    });
-}
-
-pub fn iic_start() {
-    iic_w_sda(1);
-    iic_w_scl(1);
-    iic_w_sda(0);
-    iic_w_scl(0);
-}
-
-pub fn iic_stop() {
-    iic_w_sda(0);
-    iic_w_scl(1);
-    iic_w_sda(1);
-}
-
-pub fn iic_sendbyte(_byte: u8) {
-    for i in 0..8 {
-        iic_w_sda(_byte & (0x80 >> i));
-        iic_w_scl(1);
-        iic_w_scl(0);
-    }
-}
-
-pub fn iic_receive_byte() -> u8 {
-    let mut byte = 0x00;
-    iic_w_sda(1);
-
-    for i in 0..8 {
-        iic_w_scl(1);
-        if iic_r_sda() == 1 {
-            byte |= 0x80 >> i;
-        }
-        iic_w_scl(0);
-    }
-    byte
-}
-
-pub fn iic_send_ack(ack_bit: u8) {
-    iic_w_sda(ack_bit);
-    iic_w_scl(1);
-    iic_w_scl(0);
-}
-
-pub fn iic_receive_ack() -> u8 {
-    iic_w_sda(1);
-
-    iic_w_scl(1);
-    let ack_bit = iic_r_sda();
-    iic_w_scl(0);
-    ack_bit
-}
-
-#[inline(never)]
-pub fn delay(time: usize) {
-    // 延时函数,time的单位约为0.5s，使用汇编编写从而不会被优化
-    unsafe {
-        asm!(
-            // 先来个循环（总共是两层循环，内层循环次数8000000）
-            "mov r0, #0",
-            "1:",
-            // 内层循环
-            "mov r1, #0",
-            "2:",
-            "add r1, r1, #1",
-            "cmp r1, r3",
-            "blt 2b",
-            // 外层循环
-            "add r0, r0, #1",
-            "cmp r0, r2",
-            "blt 1b",
-            in("r2") time,
-            in("r3") 800000/8,
-        )
-    }
-}
\ No newline at end of file
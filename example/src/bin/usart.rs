@@ -5,104 +5,52 @@
 // FFI接口
 use core::ffi::c_void;
 
-use embassy_preempt_executor::SyncOSTaskCreate;
+use embassy_preempt_executor::{AsyncOSTaskCreate, SyncOSTaskCreate};
 use embassy_preempt_executor::os_core::{OSInit, OSStart};
 use embassy_preempt_executor::os_time::OSTimeDly;
-use embassy_preempt_platform::pac::{usart, gpio, GPIOA, RCC, USART1};
+use embassy_preempt_platform::driver::uart::driver::{usart2, Config};
 
-
-use embassy_preempt_log::{os_log, task_log};
+use embassy_preempt_log::task_log;
 
 #[embassy_preempt_macros::entry]
 fn usart_test() -> ! {
-    #[cfg(feature = "alarm_test")]
-    os_log!(info, "OS Start");
-
-      usart_init();
-
+    // os初始化，同时配置时钟，串口要在它之后初始化
     OSInit();
-    
-    SyncOSTaskCreate(task1, 0 as *mut c_void, 0 as *mut usize, 10);
-    SyncOSTaskCreate(task2, 0 as *mut c_void, 0 as *mut usize, 11);
+    // USART2 连接 ST-LINK 虚拟串口，115200 8N1
+    usart2(&Config::default());
+
+    AsyncOSTaskCreate(echo_task, 0 as *mut c_void, 0 as *mut usize, 10);
+    SyncOSTaskCreate(hello_task, 0 as *mut c_void, 0 as *mut usize, 11);
 
     OSStart();
 }
 
-fn task1(_args: *mut c_void) {
-    loop {
-        #[cfg(feature = "alarm_test")]
-        task_log!(info, "usart_test");
-       usart_send_byte(b'A');
-       OSTimeDly(400 * 100);
-    }
-}
+/// 异步任务：回显收到的字节，接收中断唤醒后立即抢占 hello_task
+async fn echo_task(_args: *mut c_void) {
+    use embedded_io_async::{Read, Write};
 
-fn task2(_args: *mut c_void) {
+    let mut uart = usart2(&Config::default());
+    let mut buf = [0u8; 32];
     loop {
-        task_log!(info, "task2 running");
-        OSTimeDly(500 * 100);
-        task_log!(info, "task2 waiting");
-        OSTimeDly(500 * 100);
+        match uart.read(&mut buf).await {
+            Ok(n) => {
+                let _ = uart.write_all(&buf[..n]).await;
+            }
+            // 溢出等接收错误只报告一次，之后继续接收
+            Err(_) => task_log!(error, "usart read error"),
+        }
     }
 }
 
+/// 同步任务：阻塞写，等待发送缓冲区时任务离开就绪表
+fn hello_task(_args: *mut c_void) {
+    use embedded_io::Write;
 
-// 波特率、时钟
-const BAUD_RATE: u64 = 115200;
-const CLOCK: u64 = 84_000_000;
-static USART_DIV: u16 = (CLOCK / BAUD_RATE) as u16;
-
-#[allow(dead_code)]
-fn usart_init() {
-    #[cfg(feature = "defmt")]
-    os_log!(info, "usart_init");
-
-    // 启用 GPIOA 和 USART1 的时钟
-    RCC.ahb1enr().modify(|f| {
-        f.set_gpioaen(true);
-    });
-    RCC.apb2enr().modify(|f| {
-        f.set_usart1en(true);
-    });
-
-    // 配置 GPIOA 的引脚 PA9 (TX) 和 PA10 (RX) 为复用功能模式
-    GPIOA.moder().modify(|f| {
-        f.set_moder(9, gpio::vals::Moder::ALTERNATE);
-        f.set_moder(10, gpio::vals::Moder::ALTERNATE);
-    });
-    
-    GPIOA.afr(1).modify(|f| {
-        f.set_afr(1, 7);    // PA9 复用为 USART1_TX
-        f.set_afr(2, 7);    // PA10 复用为 USART1_RX
-    });
-
-    USART1.brr().write(|f| {
-        f.set_brr(USART_DIV);   // 设置波特率
-    });
-
-    USART1.cr1().modify(|f| {
-        f.set_ue(true);         // 启用 USART1
-        f.set_m0(usart::vals::M0::BIT8);    // 8 位数据位
-        f.set_te(true);         // 启用发送
-        f.set_re(true);         // 启用接收
-    });
-
-    USART1.cr2().modify(|f| {
-        f.set_stop(usart::vals::Stop::STOP1);   // 1 位停止位
-    });
-}
-
-#[allow(dead_code)]
-fn usart_send_byte(data: u8) {
-    #[cfg(feature = "defmt")]
-    os_log!(trace, "usart_test_send");
-    while !USART1.sr().read().txe() {}
-    USART1.dr().write(|f| f.set_dr(data as u16 & 0x01FF));
-}
-
-#[allow(dead_code)]
-fn usart_receive_byte() -> u8{
-    // while !USART1.sr().read().rxne() {}
-    (USART1.dr().read().dr() & 0x01FF) as u8
+    let mut uart = usart2(&Config::default());
+    loop {
+        task_log!(info, "usart hello");
+        let _ = uart.write_all(b"hello from embassy_preempt\r\n");
+        let _ = uart.flush();
+        OSTimeDly(1000 * 100);
+    }
 }
-
@@ -0,0 +1,340 @@
+//! Generate the kernel configuration constants from `embassy-preempt.toml`.
+//!
+//! The config file is taken from `EMBASSY_PREEMPT_CONFIG` if it is set, otherwise it is searched in the ancestors
+//! of the build directory (which contain the application's workspace) and then of this crate. Every key can be
+//! overridden with an `EMBASSY_PREEMPT_<KEY>` environment variable, e.g. `EMBASSY_PREEMPT_LOWEST_PRIO=31`.
+//!
+//! The constants are written to `$OUT_DIR/config.rs`. The derived kernel features are enabled for this crate and
+//! passed to the other kernel crates through the `links` metadata (`DEP_EMBASSY_PREEMPT_CFG_CFGS`).
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "embassy-preempt.toml";
+const CONFIG_ENV: &str = "EMBASSY_PREEMPT_CONFIG";
+
+#[derive(Clone)]
+enum Value {
+    Int(i64),
+    Bool(bool),
+    /// `[max_prio, stack_size, max_stacks]` of each stack band
+    Bands(Vec<[i64; 3]>),
+}
+
+/// the fixed-size block classes of the stack allocator (`BLOCK_SIZES` of `embassy-preempt-mem`), a band stack takes
+/// a whole block
+const STACK_BLOCK_SIZES: &[i64] = &[128, 256, 512, 1024, 2048, 4096, 8192, 16384];
+
+/// (section, key, default value)
+const KEYS: &[(&str, &str, Value)] = &[
+    ("kernel", "lowest_prio", Value::Int(63)),
+    ("kernel", "max_events", Value::Int(20)),
+    ("kernel", "arena_size", Value::Int(10240)),
+    ("kernel", "task_reg_tbl_size", Value::Int(1)),
+    ("kernel", "max_mem_part", Value::Int(5)),
+    ("kernel", "task_name_size", Value::Int(32)),
+    ("kernel", "kernel_interrupt_prio", Value::Int(0)),
+    ("kernel", "tickless", Value::Bool(false)),
+    ("kernel", "tickless_min_sleep_us", Value::Int(1000)),
+    ("event", "sem", Value::Bool(false)),
+    ("event", "mbox", Value::Bool(false)),
+    ("event", "mutex", Value::Bool(false)),
+    ("event", "q", Value::Bool(false)),
+    ("event", "max_qs", Value::Int(0)),
+    ("event", "name", Value::Bool(false)),
+    ("event", "name_size", Value::Int(16)),
+    ("stack", "bands", Value::Bands(Vec::new())),
+];
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed={CONFIG_ENV}");
+
+    let mut values: BTreeMap<&str, Value> = KEYS.iter().map(|(_, key, default)| (*key, default.clone())).collect();
+
+    if let Some(path) = find_config() {
+        println!("cargo::rerun-if-changed={}", path.display());
+        read_config(&path, &mut values);
+    }
+    for (_, key, _) in KEYS {
+        let var = format!("EMBASSY_PREEMPT_{}", key.to_uppercase());
+        println!("cargo::rerun-if-env-changed={var}");
+        if let Ok(raw) = env::var(&var) {
+            let value = match values[key] {
+                Value::Int(_) => raw.trim().parse().map(Value::Int).ok(),
+                Value::Bool(_) => match raw.trim() {
+                    "1" | "true" => Some(Value::Bool(true)),
+                    "0" | "false" => Some(Value::Bool(false)),
+                    _ => None,
+                },
+                // e.g. `15:4096:2,63:1024:8`
+                Value::Bands(_) => raw
+                    .split(',')
+                    .filter(|band| !band.trim().is_empty())
+                    .map(|band| {
+                        let fields: Vec<i64> = band.split(':').map(|v| v.trim().parse().ok()).collect::<Option<_>>()?;
+                        fields.try_into().ok()
+                    })
+                    .collect::<Option<_>>()
+                    .map(Value::Bands),
+            };
+            let Some(value) = value else {
+                fail(&format!("`{var}={raw}` is not {}", kind(&values[key])));
+            };
+            values.insert(*key, value);
+        }
+    }
+
+    let int = |key: &str| match values[key] {
+        Value::Int(v) => v,
+        _ => unreachable!(),
+    };
+    let flag = |key: &str| match values[key] {
+        Value::Bool(v) => v,
+        _ => unreachable!(),
+    };
+
+    let lowest_prio = int("lowest_prio");
+    let max_events = int("max_events");
+    let arena_size = int("arena_size");
+    let task_reg_tbl_size = int("task_reg_tbl_size");
+    let max_mem_part = int("max_mem_part");
+    let max_qs = int("max_qs");
+    let task_name_size = int("task_name_size");
+    let event_name_size = int("name_size");
+    let kernel_interrupt_prio = int("kernel_interrupt_prio");
+    let tickless_min_sleep_us = int("tickless_min_sleep_us");
+    let Value::Bands(bands) = &values["bands"] else {
+        unreachable!()
+    };
+
+    // ranges
+    check(
+        (2..=254).contains(&lowest_prio),
+        format!("kernel.lowest_prio = {lowest_prio}: must be in 2..=254 (the idle and stat tasks use the two lowest priorities)"),
+    );
+    check(
+        (0..=65535).contains(&max_events),
+        format!("kernel.max_events = {max_events}: must be in 0..=65535"),
+    );
+    check(arena_size > 0, format!("kernel.arena_size = {arena_size}: must be greater than 0"));
+    check(
+        (0..=255).contains(&task_reg_tbl_size),
+        format!("kernel.task_reg_tbl_size = {task_reg_tbl_size}: must be in 0..=255"),
+    );
+    check(
+        (0..=65535).contains(&max_mem_part),
+        format!("kernel.max_mem_part = {max_mem_part}: must be in 0..=65535"),
+    );
+    check((0..=65535).contains(&max_qs), format!("event.max_qs = {max_qs}: must be in 0..=65535"));
+    check(
+        (1..=255).contains(&task_name_size),
+        format!("kernel.task_name_size = {task_name_size}: must be in 1..=255"),
+    );
+    check(
+        (0..=255).contains(&kernel_interrupt_prio),
+        format!("kernel.kernel_interrupt_prio = {kernel_interrupt_prio}: must be in 0..=255 (an NVIC priority byte)"),
+    );
+    check(
+        (0..=1_000_000_000).contains(&tickless_min_sleep_us),
+        format!("kernel.tickless_min_sleep_us = {tickless_min_sleep_us}: must be in 0..=1000000000"),
+    );
+    check(
+        (1..=255).contains(&event_name_size),
+        format!("event.name_size = {event_name_size}: must be in 1..=255"),
+    );
+    for (i, &[max_prio, stack_size, max_stacks]) in bands.iter().enumerate() {
+        check(
+            (0..=lowest_prio).contains(&max_prio),
+            format!("stack.bands[{i}]: max_prio = {max_prio} must be in 0..=kernel.lowest_prio ({lowest_prio})"),
+        );
+        check(
+            i == 0 || bands[i - 1][0] < max_prio,
+            format!("stack.bands[{i}]: the bands must be sorted by max_prio, without duplicates"),
+        );
+        check(
+            (1..=1 << 20).contains(&stack_size),
+            format!("stack.bands[{i}]: stack_size = {stack_size} must be in 1..=1048576"),
+        );
+        check(
+            (1..=255).contains(&max_stacks),
+            format!("stack.bands[{i}]: max_stacks = {max_stacks} must be in 1..=255"),
+        );
+    }
+
+    // combinations
+    check(
+        !flag("q") || max_qs > 0,
+        "event.q = true needs event.max_qs > 0".into(),
+    );
+    let event_en = flag("sem") || flag("mbox") || flag("mutex") || (flag("q") && max_qs > 0);
+    check(
+        !event_en || max_events > 0,
+        "kernel.max_events must be greater than 0 when a kernel object (sem, mbox, mutex or q) is enabled".into(),
+    );
+    check(
+        !flag("name") || event_en,
+        "event.name = true has no effect without a kernel object (sem, mbox, mutex or q)".into(),
+    );
+    let prio_64 = env::var_os("CARGO_FEATURE_OS_PRIO_LESS_THAN_64").is_some();
+    let prio_256 = env::var_os("CARGO_FEATURE_OS_PRIO_LESS_THAN_256").is_some();
+    check(
+        !(prio_64 && lowest_prio > 63),
+        format!("kernel.lowest_prio = {lowest_prio} needs the `OS_PRIO_LESS_THAN_256` feature, but `OS_PRIO_LESS_THAN_64` is enabled"),
+    );
+
+    // derived features
+    let mut cfgs = Vec::new();
+    if !prio_64 && !prio_256 {
+        cfgs.push(if lowest_prio <= 63 { "OS_PRIO_LESS_THAN_64" } else { "OS_PRIO_LESS_THAN_256" });
+    }
+    for (key, feature) in [("sem", "OS_SEM_EN"), ("mbox", "OS_MBOX_EN"), ("mutex", "OS_MUTEX_EN"), ("q", "OS_Q_EN")] {
+        if flag(key) {
+            cfgs.push(feature);
+        }
+    }
+    if event_en {
+        cfgs.push("OS_EVENT_EN");
+    }
+    if flag("name") {
+        cfgs.push("OS_EVENT_NAME_EN");
+    }
+    if max_qs > 0 {
+        cfgs.push("OS_MAX_QS");
+    }
+    if max_mem_part > 0 {
+        cfgs.push("OS_MAX_MEM_PART_EN");
+    }
+    if task_reg_tbl_size > 0 {
+        cfgs.push("OS_TASK_REG_TBL_SIZE");
+    }
+    if flag("tickless") {
+        cfgs.push("OS_TICKLESS_EN");
+    }
+    for cfg in &cfgs {
+        println!("cargo::rustc-cfg=feature=\"{cfg}\"");
+    }
+    println!("cargo::metadata=cfgs={}", cfgs.join(","));
+
+    let mut out = String::from("// generated by build.rs from embassy-preempt.toml, do not edit\n\n");
+    let _ = writeln!(out, "/// the const val define the lowest prio\npub const OS_LOWEST_PRIO: OS_PRIO = {lowest_prio};");
+    let _ = writeln!(out, "/// Size of task variables array (#of INT32U entries)\npub const OS_TASK_REG_TBL_SIZE: usize = {task_reg_tbl_size};");
+    let _ = writeln!(out, "/// Max. number of memory partitions\npub const OS_MAX_MEM_PART: usize = {max_mem_part};");
+    let _ = writeln!(out, "/// Max. number of event control blocks in your application\npub const OS_MAX_EVENTS: usize = {max_events};");
+    let _ = writeln!(out, "/// Max. number of queue control blocks in your application\npub const OS_MAX_QS: usize = {max_qs};");
+    let _ = writeln!(out, "/// Max. size of a task name in bytes when names are stored inline (`no_alloc`)\npub const OS_TASK_NAME_SIZE: usize = {task_name_size};");
+    let _ = writeln!(out, "/// Max. size of an event name in bytes when names are stored inline (`no_alloc`)\npub const OS_EVENT_NAME_SIZE: usize = {event_name_size};");
+    let _ = writeln!(
+        out,
+        "/// The highest (numerically lowest) NVIC priority of an interrupt that calls the kernel, 0 to mask every\n\
+         /// interrupt in the kernel's critical sections. Interrupts above it are never masked by the kernel and must not\n\
+         /// call it.\n\
+         pub const OS_KERNEL_INTERRUPT_PRIO: u8 = {kernel_interrupt_prio};"
+    );
+    let _ = writeln!(
+        out,
+        "/// The shortest time to the next timer expiration for which the tickless idle task puts the core to sleep, in us\n\
+         pub const OS_TICKLESS_MIN_SLEEP_US: u64 = {tickless_min_sleep_us};"
+    );
+    let mut bands_out = String::new();
+    for &[max_prio, stack_size, max_stacks] in bands {
+        // round up to the block class the stack allocator takes it from, or to a word above the largest class
+        let stack_size = STACK_BLOCK_SIZES
+            .iter()
+            .copied()
+            .find(|&class| class >= stack_size)
+            .unwrap_or((stack_size + 7) & !7);
+        let _ = write!(
+            bands_out,
+            "\n    StackBand {{ max_prio: {max_prio}, stack_size: {stack_size}, max_stacks: {max_stacks} }},"
+        );
+    }
+    let _ = writeln!(
+        out,
+        "/// The stack size bands of the task priorities, the sizes rounded up to the stack block classes\n\
+         pub const OS_STACK_BANDS: &[StackBand] = &[{bands_out}\n];"
+    );
+    let _ = writeln!(
+        out,
+        "/// This const val is used to config the size of ARENA.\n\
+         /// You can set it refer to the number of tasks in your application and the number of system tasks.\n\
+         pub const OS_ARENA_SIZE: usize = {arena_size};"
+    );
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out_dir.join("config.rs"), out).unwrap();
+}
+
+/// Locate the config file, see the module doc
+fn find_config() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        let path = PathBuf::from(path);
+        if !path.is_file() {
+            fail(&format!("{CONFIG_ENV} points to `{}`, which is not a file", path.display()));
+        }
+        return Some(path);
+    }
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    out_dir
+        .ancestors()
+        .chain(manifest_dir.ancestors())
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Read the known keys of the config file into `values`, rejecting unknown keys and wrong types
+fn read_config(path: &Path, values: &mut BTreeMap<&str, Value>) {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("cannot read `{}`: {e}", path.display())));
+    let table: toml::Table = text
+        .parse()
+        .unwrap_or_else(|e| fail(&format!("`{}` is not valid TOML: {e}", path.display())));
+    for (section, items) in &table {
+        let Some(items) = items.as_table() else {
+            fail(&format!("`{section}` in `{}` must be a table, e.g. `[{section}]`", path.display()));
+        };
+        for (key, item) in items {
+            let Some((_, name, default)) = KEYS.iter().find(|(s, k, _)| s == section && k == key) else {
+                fail(&format!("unknown key `{section}.{key}` in `{}`", path.display()));
+            };
+            let value = match default {
+                Value::Int(_) => item.as_integer().map(Value::Int),
+                Value::Bool(_) => item.as_bool().map(Value::Bool),
+                Value::Bands(_) => item.as_array().and_then(|bands| {
+                    bands
+                        .iter()
+                        .map(|band| {
+                            let fields: Vec<i64> = band.as_array()?.iter().map(toml::Value::as_integer).collect::<Option<_>>()?;
+                            fields.try_into().ok()
+                        })
+                        .collect::<Option<_>>()
+                        .map(Value::Bands)
+                }),
+            };
+            let Some(value) = value else {
+                fail(&format!("`{section}.{key}` in `{}` must be {}", path.display(), kind(default)));
+            };
+            values.insert(*name, value);
+        }
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "an integer",
+        Value::Bool(_) => "a boolean",
+        Value::Bands(_) => "a list of `[max_prio, stack_size, max_stacks]` bands",
+    }
+}
+
+fn check(ok: bool, msg: String) {
+    if !ok {
+        fail(&msg);
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    panic!("invalid embassy-preempt kernel configuration: {msg}");
+}
@@ -0,0 +1,18 @@
+// Shared by the build scripts of the crates depending on embassy-preempt-cfg, through `include!`
+
+/// Turn the kernel features derived from embassy-preempt.toml by embassy-preempt-cfg on for the crate being built
+///
+/// They come in the `links` metadata of embassy-preempt-cfg (`DEP_EMBASSY_PREEMPT_CFG_CFGS`) and add to the Cargo
+/// features of the crate. The `no_alloc` feature of embassy-preempt-mem (`DEP_EMBASSY_PREEMPT_MEM_NO_ALLOC`) is
+/// turned on the same way, so that the crates depending on it allocate nothing either.
+fn forward_cfgs() {
+    println!("cargo::rerun-if-changed=../embassy-preempt-cfg/forward_cfgs.rs");
+    if let Ok(cfgs) = std::env::var("DEP_EMBASSY_PREEMPT_CFG_CFGS") {
+        for cfg in cfgs.split(',').filter(|cfg| !cfg.is_empty()) {
+            println!("cargo::rustc-cfg=feature=\"{cfg}\"");
+        }
+    }
+    if std::env::var_os("DEP_EMBASSY_PREEMPT_MEM_NO_ALLOC").is_some() {
+        println!("cargo::rustc-cfg=feature=\"no_alloc\"");
+    }
+}
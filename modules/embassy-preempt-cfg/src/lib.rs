@@ -11,21 +11,27 @@ mod tick;
 
 use ucosii::OS_PRIO;
 use embassy_preempt_structs::cell::UPSafeCell;
-// TODO: Make all the config to be feature!!!
-
-/// the const val define the lowest prio
-pub const OS_LOWEST_PRIO: OS_PRIO = 63;
-/// Size of task variables array (#of INT32U entries)
-pub const OS_TASK_REG_TBL_SIZE: usize = 1;
-/// Max. number of memory partitions
-pub const OS_MAX_MEM_PART: usize = 5;
-/// Max. number of tasks in your application, MUST be >= 2
-// pub const OS_MAX_TASKS: usize = 20;
-// Max. number of event control blocks in your application
-pub const OS_MAX_EVENTS: usize = 20;
-/// This const val is used to config the size of ARENA.
-/// You can set it refer to the number of tasks in your application(OS_MAX_TASKS) and the number of system tasks(OS_N_SYS_TASKS).
-pub const OS_ARENA_SIZE: usize = 10240;
+
+// OS_LOWEST_PRIO, OS_TASK_REG_TBL_SIZE, OS_MAX_MEM_PART, OS_MAX_EVENTS, OS_MAX_QS, OS_TASK_NAME_SIZE,
+// OS_EVENT_NAME_SIZE, OS_KERNEL_INTERRUPT_PRIO, OS_TICKLESS_MIN_SLEEP_US, OS_STACK_BANDS and OS_ARENA_SIZE, generated by
+// build.rs from embassy-preempt.toml
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
+
+/// A band of task priorities sharing the same stack size
+///
+/// A task of the band gets a stack of `stack_size` bytes when it is preempted (unless it was created with its own
+/// stack size). The stack region is sized for `max_stacks` such stacks.
+#[derive(Debug, Clone, Copy)]
+pub struct StackBand {
+    /// The lowest priority (largest number) of the band. The bands are sorted by this field, a band starts right
+    /// after the previous one.
+    pub max_prio: u8,
+    /// The stack size of the tasks of the band in bytes, a class of the stack allocator
+    pub stack_size: usize,
+    /// The number of stacks of this size the stack region must hold
+    pub max_stacks: usize,
+}
+
 /// Ticks per second of the global timebase. Output frequency of the Timer. Frequency of the Systick(run on Timer)
 /// the default one tick is 10us
 /// 
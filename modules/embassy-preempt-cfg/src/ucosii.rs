@@ -386,6 +386,8 @@ pub enum OS_ERR_STATE {
     OS_ERR_TASK_SUSPEND_PRIO,
     /// The task is waiting
     OS_ERR_TASK_WAITING,
+    /// No stack could be allocated for a task
+    OS_ERR_STK_ALLOC,
 
     /// The time is not a delay
     OS_ERR_TIME_NOT_DLY,
@@ -582,12 +584,12 @@ pub struct OS_MBOX_DATA {
 /// the data of the os mem part
 #[allow(unused)]
 pub struct OS_MEM_DATA {
-    OSAddr: *mut (),       /* Ptr to the beginning address of the memory partition    */
-    OSFreeList: *mut (),   /* Ptr to the beginning of the free list of memory blocks  */
-    OSBlkSize: u32, /* Size (in bytes) of each memory block                    */
-    OSNBlks: u32,   /* Total number of blocks in the partition                 */
-    OSNFree: u32,   /* Number of memory blocks free                            */
-    OSNUsed: u32,   /* Number of memory blocks used                            */
+    pub OSAddr: *mut (),       /* Ptr to the beginning address of the memory partition    */
+    pub OSFreeList: *mut (),   /* Ptr to the beginning of the free list of memory blocks  */
+    pub OSBlkSize: u32, /* Size (in bytes) of each memory block                    */
+    pub OSNBlks: u32,   /* Total number of blocks in the partition                 */
+    pub OSNFree: u32,   /* Number of memory blocks free                            */
+    pub OSNUsed: u32,   /* Number of memory blocks used                            */
 }
 
 /*
@@ -673,6 +675,21 @@ pub struct OS_STK_DATA {
     OSUsed: u32, /* Number of entries used on the stack                     */
 }
 
+/*
+*********************************************************************************************************
+*                                          TASK STORAGE DATA
+*********************************************************************************************************
+*/
+
+/// the usage of the arena which stores the TCBs and futures of the tasks
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OS_ARENA_DATA {
+    pub OSArenaSize: usize, /* Total size (in bytes) of the task arena                  */
+    pub OSArenaUsed: usize, /* Number of bytes handed out by the arena                  */
+    pub OSFreeBlks: u32,    /* Number of reclaimed task storages waiting for reuse      */
+    pub OSFreeBytes: usize, /* Number of bytes held by the reclaimed task storages      */
+}
+
 /*
 *********************************************************************************************************
 *                                         TASK CONTROL BLOCK
@@ -740,6 +757,9 @@ pub static OSCtxSwCtr: AtomicU32 = AtomicU32::new(0);
 /// Idle counter
 pub static OSIdleCtr: AtomicU32 = AtomicU32::new(0);
 
+/// Counter of number of failed stack allocations
+pub static OSStkAllocFailCtr: AtomicU32 = AtomicU32::new(0);
+
 /// Next available Task register ID
 #[cfg(feature = "OS_TASK_REG_TBL_SIZE")]
 pub static OSTaskRegNextAvailID: AtomicU8 = AtomicU8::new(0);
@@ -17,8 +17,11 @@ pub mod os_mutex;
 /// the mod of queue of uC/OS-II kernel
 pub mod os_q;
 
+#[cfg(all(feature = "OS_EVENT_NAME_EN", not(feature = "no_alloc")))]
+extern crate alloc;
+
 use core::ptr::NonNull;
-#[cfg(feature = "OS_EVENT_NAME_EN")]
+#[cfg(all(feature = "OS_EVENT_NAME_EN", not(feature = "no_alloc")))]
 use alloc::string::String;
 use core::ops::{Deref, DerefMut};
 
@@ -26,8 +29,12 @@ use spin::Once;
 use critical_section::{self, CriticalSection};
 
 use embassy_preempt_cfg::{OS_MAX_EVENTS, OS_LOWEST_PRIO};
+#[cfg(all(feature = "OS_EVENT_NAME_EN", feature = "no_alloc"))]
+use embassy_preempt_cfg::OS_EVENT_NAME_SIZE;
 use embassy_preempt_cfg::ucosii::{OS_PRIO, OS_EVENT_TBL_SIZE};
 use embassy_preempt_structs::cell::SyncUnsafeCell;
+#[cfg(all(feature = "OS_EVENT_NAME_EN", feature = "no_alloc"))]
+use embassy_preempt_structs::name::FixedName;
 use embassy_preempt_log::scheduler_log;
 use embassy_preempt_executor::{GlobalSyncExecutor, OSUnMapTbl};
 use embassy_preempt_executor::task::OS_TCB_REF;
@@ -40,6 +47,13 @@ use embassy_preempt_mem::arena::ARENA;
 *********************************************************************************************************
 */
 
+/// the name of an event. In the `no_alloc` mode it is stored inline and truncated to `OS_EVENT_NAME_SIZE` bytes
+#[cfg(all(feature = "OS_EVENT_NAME_EN", not(feature = "no_alloc")))]
+pub type OS_EVENT_NAME = String;
+/// the name of an event. In the `no_alloc` mode it is stored inline and truncated to `OS_EVENT_NAME_SIZE` bytes
+#[cfg(all(feature = "OS_EVENT_NAME_EN", feature = "no_alloc"))]
+pub type OS_EVENT_NAME = FixedName<OS_EVENT_NAME_SIZE>;
+
 // #[cfg(feature = "OS_EVENT_EN")]
 #[repr(C)]
 #[allow(unused)]
@@ -52,7 +66,7 @@ pub struct OS_EVENT {
     pub OSEventGrp: OS_PRIO,        /* Group corresponding to tasks waiting for event to occur */
     pub OSEventTbl: [OS_PRIO; OS_EVENT_TBL_SIZE as usize], /* List of tasks waiting for event to occur                */
     #[cfg(feature = "OS_EVENT_NAME_EN")]
-    pub OSEventName: String, // the name of the event
+    pub OSEventName: OS_EVENT_NAME, // the name of the event
 }
 
 /// the ref of ECB
@@ -92,7 +106,7 @@ impl OS_EVENT {
             OSEventGrp: 0,
             OSEventTbl: [0; OS_EVENT_TBL_SIZE as usize],
             #[cfg(feature = "OS_EVENT_NAME_EN")]
-            OSEventName: String::new(),
+            OSEventName: OS_EVENT_NAME::new(),
         }
     }
 
@@ -227,7 +241,7 @@ impl EventPool {
                 event_ref.OSEventTbl = [0; OS_EVENT_TBL_SIZE as usize];
                 #[cfg(feature = "OS_EVENT_NAME_EN")]
                 {
-                    event_ref.OSEventName = String::from("?");
+                    event_ref.OSEventName = "?".into();
                 }
             }
             else
@@ -0,0 +1,6 @@
+include!("../embassy-preempt-cfg/forward_cfgs.rs");
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    forward_cfgs();
+}
@@ -6,10 +6,13 @@
 
 //! Raw task storage and pool.
 
+#[cfg(not(feature = "no_alloc"))]
 pub extern crate alloc;
 
 pub mod os_core;
 pub mod os_cpu;
+pub mod os_futures;
+pub mod os_signal;
 pub mod os_task;
 pub mod os_time;
 pub mod state_atomics;
@@ -35,7 +38,9 @@ use core::sync::atomic::Ordering;
 use embassy_preempt_cfg::ucosii::*;
 // use arena::ARENA;
 use embassy_preempt_cfg::*;
-use embassy_preempt_mem::heap::{OS_STK_REF, alloc_stack, get_program_stack};
+use embassy_preempt_mem::heap::{
+    OS_STK_REF, alloc_emergency_stack, alloc_stack, get_program_stack, stack_release_after_failure,
+};
 use embassy_preempt_platform::traits::PlatformMemoryLayout;
 use embassy_preempt_platform::traits::platform::PlatformStatic;
 use embassy_preempt_platform::traits::timer::AlarmHandle;
@@ -47,9 +52,9 @@ use spin::Once;
 use state_atomics::State;
 use task::{OS_TCB, OS_TCB_REF};
 
-pub use self::waker::task_from_waker;
+pub use self::waker::{task_from_waker, try_task_from_waker};
 use crate::os_cpu::OSTaskStkInit;
-#[cfg(feature = "delay_idle")]
+#[cfg(all(feature = "delay_idle", not(feature = "OS_TICKLESS_EN")))]
 use crate::os_time::blockdelay::delay;
 
 /*
@@ -97,6 +102,8 @@ pub struct SyncExecutor {
     OSRdyTbl: [u16; OS_RDY_TBL_SIZE],
     pub(crate) timer_queue: timer_queue::TimerQueue,
     pub(crate) alarm: AlarmHandle,
+    // set when `interrupt_poll` found no stack for the highrdy task, which is left pending
+    switch_deferred: SyncUnsafeCell<bool>,
 }
 
 impl SyncExecutor {
@@ -115,6 +122,7 @@ impl SyncExecutor {
             OSRdyTbl: SyncUnsafeCell::new([0; OS_RDY_TBL_SIZE]),
             timer_queue: timer_queue::TimerQueue::new(),
             alarm,
+            switch_deferred: SyncUnsafeCell::new(false),
         }
     }
 
@@ -194,6 +202,10 @@ impl SyncExecutor {
             }
         });
     }
+    /// check if the task is in the ready queue
+    pub(crate) fn is_task_ready(&self, task: OS_TCB_REF) -> bool {
+        self.OSRdyTbl.get_unmut()[task.OSTCBY as usize] & task.OSTCBBitX != 0
+    }
     // check if an prio is exiting
     pub fn prio_exist(&self, prio: OS_PRIO) -> bool {
         let prio_tbl: &[OS_TCB_REF; (OS_LOWEST_PRIO + 1) as usize];
@@ -233,7 +245,7 @@ impl SyncExecutor {
 
     #[cfg(feature = "OS_TASK_NAME_EN")]
     /// set task's name
-    pub fn set_name(&self, prio: OS_PRIO, name: alloc::string::String) {
+    pub fn set_name(&self, prio: OS_PRIO, name: task::OS_TASK_NAME) {
         let prio_tbl = self.os_prio_tbl.get_mut();
         prio_tbl[prio as usize].OSTCBTaskName = name;
     }
@@ -327,6 +339,7 @@ impl SyncExecutor {
             });
 
             task_log!(trace, "interrupt_poll");
+            self.switch_deferred.set(false);
             if *self.OSPrioCur.get_unmut() != OS_TASK_IDLE_PRIO {
                 self.OSTCBCur.get().needs_stack_save.set(true);
                 // If the current task will be deleted,
@@ -375,8 +388,25 @@ impl SyncExecutor {
                             *self.OSPrioHighRdy.get_unmut()
                         );
                     }
-                    let layout = Layout::from_size_align(PlatformImpl::get_task_stack_size(), 4).unwrap();
-                    stk = alloc_stack(layout);
+                    match self.alloc_task_stack(task) {
+                        Some(new_stk) => stk = new_stk,
+                        None => {
+                            // defer the switch: the current task is not switched out, so its stack is not saved
+                            scheduler_log!(warn, "no stack for the prio {} task, defer the switch", task.OSTCBPrio);
+                            let cur = critical_section::with(|_| self.OSTCBCur.get());
+                            cur.needs_stack_save.set(false);
+                            self.switch_deferred.set(true);
+                            // a ready current task keeps running and the highrdy task is switched to at the next
+                            // scheduling point. A blocked one waits in `block_poll` with the highrdy task pending.
+                            critical_section::with(|_| {
+                                if self.is_task_ready(cur) {
+                                    self.OSPrioHighRdy.set(cur.OSTCBPrio);
+                                    self.OSTCBHighRdy.set(cur);
+                                }
+                            });
+                            return;
+                        }
+                    }
                     {
                         mem_log!(trace, "the bottom of the allocated stk is {:?}", stk.STK_REF);
                     }
@@ -405,6 +435,67 @@ impl SyncExecutor {
         }
     }
 
+    /// switch away from the current task, which has just left the ready list (e.g. in `OSTimeDly()`), must be called
+    /// in the task context
+    ///
+    /// If the switch is deferred as no stack can be found for the highrdy task, the current task cannot go on, so it
+    /// waits here like the idle task would: the switch is retried after every interrupt, once the free stacks have
+    /// been merged, until it succeeds or the current task is ready again.
+    pub(crate) unsafe fn block_poll(&'static self) {
+        unsafe {
+            self.interrupt_poll();
+            while *self.switch_deferred.get_unmut() {
+                embassy_preempt_platform::PlatformImpl::enter_idle_state();
+                stack_release_after_failure();
+                // the interrupt may have readied the current task, or a task which already has a stack
+                if critical_section::with(|_| {
+                    self.set_highrdy();
+                    self.OSPrioHighRdy == self.OSPrioCur
+                }) {
+                    self.switch_deferred.set(false);
+                    break;
+                }
+                self.interrupt_poll();
+            }
+        }
+    }
+
+    /// poll `task`, the current task, on a new program stack of its size, like a preempting task: the context switch
+    /// frees the outgoing program stack, whose task has returned from its poll. If no stack can be found, the CPU
+    /// waits for an interrupt and the scheduler loop retries.
+    unsafe fn switch_program_stack(&'static self, mut task: OS_TCB_REF) {
+        unsafe {
+            scheduler_log!(trace, "switch the program stack for the prio {} task", task.OSTCBPrio);
+            match self.alloc_task_stack(task) {
+                Some(mut stk) => {
+                    stk.STK_REF = OSTaskStkInit(stk.STK_REF);
+                    task.OSTCBStkPtr = Some(stk);
+                    task.needs_stack_save.set(false);
+                    critical_section::with(|_| embassy_preempt_platform::PlatformImpl::trigger_context_switch());
+                }
+                None => embassy_preempt_platform::PlatformImpl::enter_idle_state(),
+            }
+        }
+    }
+
+    /// alloc a stack for the task which is switched to, applying the stack fail policy if the stack area is
+    /// exhausted. None means that the switch has to be deferred.
+    fn alloc_task_stack(&self, task: OS_TCB_REF) -> Option<OS_STK_REF> {
+        let layout = Layout::from_size_align(task.preempt_stk_size(), 4).unwrap();
+        let err = match alloc_stack(layout) {
+            Ok(stk) => return Some(stk),
+            Err(err) => err,
+        };
+        match os_task::stk_fail_policy() {
+            OS_STK_FAIL_POLICY::Defer => None,
+            OS_STK_FAIL_POLICY::Reserve => alloc_emergency_stack(layout).ok(),
+            OS_STK_FAIL_POLICY::Hook(hook) => {
+                hook(task.OSTCBPrio, err);
+                alloc_stack(layout).ok()
+            }
+        }
+    }
+
     /// since when it was called, there is no task running, we need poll all the task that is ready in bitmap
     pub unsafe fn poll(&'static self) -> ! {
         unsafe {
@@ -416,6 +507,9 @@ impl SyncExecutor {
             );
             // build this as a loop
             loop {
+                // a stack allocation failed on the preemption path: merge the cached free stacks here, out of the
+                // interrupt, so that the deferred switch can find a stack
+                stack_release_after_failure();
                 // test: print the ready queue
                 #[cfg(feature = "log-scheduler")]
                 critical_section::with(|_| {
@@ -423,8 +517,8 @@ impl SyncExecutor {
                     self.print_ready_queue();
                     scheduler_log!(info, "the highrdy task's prio is {}", self.OSPrioHighRdy.get_unmut());
                 });
-                // if the highrdy task is the idle task, we need to delay some time
-                #[cfg(feature = "delay_idle")]
+                // if the highrdy task is the idle task, we need to delay some time (the tickless idle task sleeps instead)
+                #[cfg(all(feature = "delay_idle", not(feature = "OS_TICKLESS_EN")))]
                 if critical_section::with(|_| *self.OSPrioHighRdy.get_unmut() == OS_TASK_IDLE_PRIO) {
                     scheduler_log!(trace, "begin delay the idle task");
                     delay(block_delay_poll);
@@ -450,16 +544,57 @@ impl SyncExecutor {
                     continue;
                 }
                 let task = task.unwrap();
+                // the program stack may be the smaller stack of a task which preempted the previous one: a task too
+                // large for it is polled on a stack of its own size instead, which becomes the program stack
+                if task.preempt_stk_size() > get_program_stack().get().layout.size() {
+                    self.switch_program_stack(task);
+                    continue;
+                }
                 // execute the task depending on if it has stack
                 self.single_poll(task);
             }
         }
     }
 
+    /// remove a finished task from the scheduler and give its storage back, must be called in a cs
+    unsafe fn task_exit(&self, task: OS_TCB_REF) {
+        unsafe {
+            let prio_tbl = self.os_prio_tbl.get_mut();
+            // if the task is still in the prio table, it has finished by itself (not deleted by OSTaskDel)
+            if prio_tbl[task.OSTCBPrio as usize] == task {
+                task_log!(trace, "the task {} exits", task.OSTCBPrio);
+                self.set_task_unready(task);
+                self.timer_queue.remove(task);
+                task.expires_at.set(u64::MAX);
+                prio_tbl[task.OSTCBPrio as usize].ptr = None;
+                OSTaskCtr.fetch_sub(1, Ordering::SeqCst);
+            }
+            task::reclaim_task(task, true);
+        }
+    }
+
     pub unsafe fn single_poll(&'static self, mut task: OS_TCB_REF) {
         unsafe {
             task_log!(trace, "single_poll");
+            // the task may have been woken by something other than its timer (e.g. the inner future of
+            // `with_timeout` won), so drop the stale timer-queue entry. The futures register their deadline
+            // again through `_embassy_time_schedule_wake` if they are still pending.
+            critical_section::with(|_| {
+                if *task.expires_at.get_unmut() != u64::MAX {
+                    self.timer_queue.remove(task);
+                    task.expires_at.set(u64::MAX);
+                }
+            });
             task.OS_POLL_FN.get().unwrap_unchecked()(task);
+            // the task has finished or deleted itself, its future is not borrowed any more
+            if !task.OSTCBStat.is_spawned() {
+                critical_section::with(|_| {
+                    task.needs_stack_save.set(false);
+                    self.task_exit(task);
+                    self.set_highrdy()
+                });
+                return;
+            }
             // by noah：Remove tasks from the ready queue in advance to facilitate subsequent unified operations
             // update timer
             // by yck: but the following part will not be executed, because OS_POLL_FN will execute task's 'poll',
@@ -34,6 +34,7 @@
 
 use core::ffi::c_void;
 use core::sync::atomic::Ordering;
+use core::task::{Context, Waker};
 
 // use embassy_preempt_port::bottom_driver::BOT_DRIVER;
 
@@ -41,11 +42,16 @@ use core::sync::atomic::Ordering;
 // use core::cell::RefCell;
 use crate::os_cpu::*;
 
-use embassy_preempt_mem::heap::{Init_Heap, OS_InitStackAllocator};
+use embassy_preempt_mem::heap::OS_InitStackAllocator;
+#[cfg(not(feature = "no_alloc"))]
+use embassy_preempt_mem::heap::Init_Heap;
+#[cfg(feature = "OS_MEM_EN")]
+use embassy_preempt_mem::os_mem::OS_MemInit;
 use embassy_preempt_platform::traits::platform::PlatformStatic;
 use embassy_preempt_platform::Platform;
 use crate::GlobalSyncExecutor;
 use crate::SyncOSTaskCreate;
+#[cfg(not(feature = "OS_TICKLESS_EN"))]
 use crate::os_time::blockdelay;
 #[cfg(feature = "OS_TASK_NAME_EN")]
 use crate::OSTaskNameSet;
@@ -172,8 +178,8 @@ pub extern "C" fn OSInit() {
     // OS_InitTCBList(); /* Initialize the free list of OS_TCBs      */
     // to be done: For now, we just aim to implement the task module, so we will impl OS_InitEventList in the future
     // OS_InitEventList(); /* Initialize the free list of OS_EVENTs    */
-    // #[cfg(all(feature="OS_MEM_EN",feature="OS_MAX_MEM_PART_EN"))]
-    // OS_MemInit(); /* Initialize the memory manager            */
+    #[cfg(feature = "OS_MEM_EN")]
+    OS_MemInit(); /* Initialize the memory manager            */
     // // to be done: For now, we just aim to implement the task module, so we will impl OS_InitEventList in the future
     // #[cfg(all(feature="OS_Q_EN",feature="OS_MAX_QS"))]
     // OS_QInit();
@@ -198,6 +204,8 @@ pub extern "C" fn OSInit() {
 
     // first of all, initilize the moudles about memery alloc
     // init the heap to use String and Vec - must be before task creation!
+    // there is no heap in the no_alloc mode
+    #[cfg(not(feature = "no_alloc"))]
     Init_Heap();
     OS_InitStackAllocator();
 
@@ -288,6 +296,72 @@ pub unsafe fn OSIntExit() {
     }
 }
 
+/// `OSIntEnter()` for the interrupt handlers of the platform crate, which cannot depend on the executor
+#[unsafe(no_mangle)]
+extern "C" fn __OSIntEnter() {
+    OSIntEnter();
+}
+
+/// `OSIntExit()` for the interrupt handlers of the platform crate, which cannot depend on the executor
+#[unsafe(no_mangle)]
+extern "C" fn __OSIntExit() {
+    unsafe { OSIntExit() };
+}
+
+/// Poll a future of the platform drivers until `poll` returns `true`, blocking the current task in between, for
+/// `embassy_preempt_platform::blocking::block_on`
+///
+/// The future is polled with the waker of the current task inside a critical section, and the task leaves the ready
+/// list in the same one while it is pending, so a wake from an interrupt handler cannot be lost. A deadline the future
+/// registered through `_embassy_time_schedule_wake` puts the task in the timer queue, like in `single_poll`. Then the
+/// highest priority ready task is switched in, like `OSTimeDly()`. Before `OSStart()`, in an ISR or with the
+/// scheduler locked there is no task switch: the future is polled in a loop.
+#[unsafe(no_mangle)]
+fn __OSBlockOn(poll: &mut dyn FnMut(&mut Context<'_>) -> bool) {
+    let executor = GlobalSyncExecutor().as_ref().unwrap();
+    let can_block = OSRunning.load(Ordering::Acquire)
+        && OSIntNesting.load(Ordering::Acquire) == 0
+        && OSLockNesting.load(Ordering::Acquire) == 0;
+    loop {
+        // `None` once the future is ready, else the head of the timer queue
+        let next_expire = critical_section::with(|_| {
+            if !can_block {
+                return if poll(&mut Context::from_waker(Waker::noop())) { None } else { Some(u64::MAX) };
+            }
+            let cur = *executor.OSTCBCur.get_unmut();
+            // the deadline of the previous poll is registered again if the future is still pending
+            unsafe {
+                if *cur.expires_at.get_unmut() != u64::MAX {
+                    executor.timer_queue.remove(cur);
+                    cur.expires_at.set(u64::MAX);
+                }
+            }
+            let waker = unsafe { crate::waker::from_task(cur) };
+            if poll(&mut Context::from_waker(&waker)) {
+                return None;
+            }
+            unsafe {
+                executor.set_task_unready(cur);
+                Some(executor.timer_queue.update(cur))
+            }
+        });
+        let Some(next_expire) = next_expire else {
+            return;
+        };
+        if !can_block {
+            continue;
+        }
+        unsafe { crate::os_time::set_alarm_at(next_expire) };
+        // if the future has been woken meanwhile, the current task is ready again and polls it at once
+        if critical_section::with(|_| unsafe {
+            executor.set_highrdy();
+            executor.OSPrioHighRdy != executor.OSPrioCur
+        }) {
+            unsafe { executor.block_poll() };
+        }
+    }
+}
+
 /*
 *********************************************************************************************************
 *                                         PREVENT SCHEDULING
@@ -677,11 +751,17 @@ fn OS_InitTaskIdle() {
     os_log!(trace, "OS_InitTaskIdle");
     let idle_fn = |_args: *mut c_void| -> ! {
         loop {
+            #[cfg(not(feature = "OS_TICKLESS_EN"))]
             {
                 task_log!(trace, "task idle");
                 blockdelay::delay(1);
             }
             OSIdleCtr.fetch_add(1, Ordering::SeqCst);
+            // sleep until the next timer expiration, or just wait if it is too close
+            #[cfg(feature = "OS_TICKLESS_EN")]
+            if crate::os_time::tickless::idle_sleep() {
+                continue;
+            }
             embassy_preempt_platform::PlatformImpl::enter_idle_state();
         }
     };
@@ -8,6 +8,9 @@ use embassy_preempt_mem::heap::{get_interrupt_stack, get_program_stack};
 use embassy_preempt_platform::traits::platform::PlatformStatic;
 use embassy_preempt_platform::OsStk;
 
+#[cfg(feature = "OS_STK_CANARY_EN")]
+use crate::os_task::stk_overflow;
+use crate::task::reclaim_task;
 use crate::GlobalSyncExecutor;
 
 /// finish the init part of the CPU/MCU
@@ -40,7 +43,8 @@ extern "C" fn __ContextSwitchHandler() {
     let global_executor = GlobalSyncExecutor().as_ref().unwrap();
     let prio_cur = global_executor.OSPrioCur.get_unmut();
     let prio_highrdy = global_executor.OSPrioHighRdy.get_unmut();
-    if prio_highrdy == prio_cur {
+    // the current task may also be switched to a new stack of its own, see `SyncExecutor::switch_program_stack`
+    if prio_highrdy == prio_cur && global_executor.OSTCBHighRdy.get_unmut().OSTCBStkPtr.is_none() {
         // we will reset the msp to the original
         let msp_stk = get_interrupt_stack().get().STK_REF.as_ptr();
         let current_psp = unsafe { embassy_preempt_platform::PlatformImpl::get_current_stack_pointer() };
@@ -73,6 +77,13 @@ extern "C" fn __ContextSwitchHandler() {
 
     let tcb_cur = global_executor.OSTCBCur.get_mut();
 
+    // lift the guard of the outgoing stack, then check that the outgoing task has not overflowed it
+    embassy_preempt_platform::PlatformImpl::set_stack_guard(core::ptr::null_mut());
+    #[cfg(feature = "OS_STK_CANARY_EN")]
+    if !old_stk.canary_intact() {
+        stk_overflow(*tcb_cur);
+    }
+
     // see if it is a thread
     if *tcb_cur.needs_stack_save.get_unmut() {
         let old_stk_ptr = unsafe { embassy_preempt_platform::PlatformImpl::get_current_stack_pointer() };
@@ -83,12 +94,17 @@ extern "C" fn __ContextSwitchHandler() {
     } else {
         mem::forget(old_stk);
     }
+    // the task has deleted itself and will never be resumed, its suspended future is leaked
+    if !tcb_cur.OSTCBStat.is_spawned() {
+        critical_section::with(|_| unsafe { reclaim_task(*tcb_cur, false) });
+    }
     unsafe {
         global_executor.set_cur_highrdy();
         tcb_cur.needs_stack_save.set(false);
     }
     let msp_stk = get_interrupt_stack().get().STK_REF.as_ptr();
     os_log!(info, "psp: {}, msp: {}", program_stk_ptr, msp_stk);
+    embassy_preempt_platform::PlatformImpl::set_stack_guard(stk_heap_ref.as_ptr());
 
     unsafe {
         let _psp = program_stk_ptr as *mut embassy_preempt_platform::chip::UcStk;
@@ -106,6 +122,16 @@ extern "C" fn __ContextSwitchHandler() {
     }
 }
 
+/// MemManage fault: with the MPU stack guard, the running task has hit the bottom of its stack
+#[cfg(feature = "OS_STK_MPU_GUARD_EN")]
+#[unsafe(no_mangle)]
+extern "C" fn MemoryManagement() {
+    let global_executor = GlobalSyncExecutor().as_ref().unwrap();
+    crate::os_task::stk_overflow(*global_executor.OSTCBCur.get_unmut());
+    // returning would fault again
+    panic!("stack overflow");
+}
+
 /// the function to mock/init the stack of the task
 /// set the pc to the executor's poll function
 pub fn OSTaskStkInit(stk_ref: NonNull<OsStk>) -> NonNull<OsStk> {
@@ -0,0 +1,73 @@
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+enum MaybeDone<Fut: Future> {
+    /// A not-yet-completed future
+    Future(Fut),
+    /// The output of the completed future
+    Done(Fut::Output),
+    /// The empty variant after the result of a [`MaybeDone`] has been
+    /// taken using the [`take_output`](MaybeDone::take_output) method.
+    Gone,
+}
+
+impl<Fut: Future> MaybeDone<Fut> {
+    /// poll the inner future if it is not done yet, return true if it is done
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+        // safety: the future is never moved out of `self` until it is done
+        let this = unsafe { self.get_unchecked_mut() };
+        match this {
+            Self::Future(fut) => match unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                Poll::Ready(res) => {
+                    *this = Self::Done(res);
+                    true
+                }
+                Poll::Pending => false,
+            },
+            _ => true,
+        }
+    }
+
+    fn take_output(&mut self) -> Fut::Output {
+        match mem::replace(self, Self::Gone) {
+            MaybeDone::Done(out) => out,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Future for the [`join`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Join<Fut1: Future, Fut2: Future> {
+    fut1: MaybeDone<Fut1>,
+    fut2: MaybeDone<Fut2>,
+}
+
+impl<Fut1: Future, Fut2: Future> Future for Join<Fut1, Fut2> {
+    type Output = (Fut1::Output, Fut2::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // safety: the fields are never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+        let done1 = unsafe { Pin::new_unchecked(&mut this.fut1) }.poll(cx);
+        let done2 = unsafe { Pin::new_unchecked(&mut this.fut2) }.poll(cx);
+        if done1 && done2 {
+            Poll::Ready((this.fut1.take_output(), this.fut2.take_output()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Joins the result of two futures, waiting for them both to complete.
+///
+/// This function will return a new future which awaits both futures to
+/// complete. The returned future will finish with a tuple of both results.
+pub fn join<Fut1: Future, Fut2: Future>(future1: Fut1, future2: Fut2) -> Join<Fut1, Fut2> {
+    Join {
+        fut1: MaybeDone::Future(future1),
+        fut2: MaybeDone::Future(future2),
+    }
+}
@@ -0,0 +1,12 @@
+//! Combinators over kernel futures.
+//!
+//! These combinators poll their inner futures with the `Context` of the task that polls them,
+//! so the wakers seen by `Timer` and the event futures are the ones created by the executor.
+
+/// the mod of join of uC/OS-II kernel
+pub mod join;
+/// the mod of select of uC/OS-II kernel
+pub mod select;
+
+pub use join::{join, Join};
+pub use select::{select, select_array, Either, Select, SelectArray};
@@ -0,0 +1,185 @@
+//! ISR-safe signal between an interrupt and a task.
+//!
+//! A [`Signal`] can be posted from any context (task or ISR) and waited on by one task, either
+//! asynchronously with [`Signal::wait`] or, for a sync task, with [`Signal::wait_blocking`].
+//!
+//! When posted from an ISR which is wrapped by `OSIntEnter()`/`OSIntExit()`, the context switch to the woken
+//! task is deferred to the `OSIntExit()` of the outermost ISR:
+//! ``` no_run
+//! static SIGNAL: InterruptNotify = InterruptNotify::new();
+//!
+//! #[no_mangle]
+//! extern "C" fn EXTI15_10() {
+//!     OSIntEnter();
+//!     // ... clear the pending bit ...
+//!     SIGNAL.signal(());
+//!     unsafe { OSIntExit() };
+//! }
+//! ```
+
+use core::cell::RefCell;
+use core::future::{poll_fn, Future};
+use core::mem;
+use core::sync::atomic::Ordering;
+use core::task::{Poll, Waker};
+
+use critical_section::Mutex;
+use embassy_preempt_cfg::ucosii::{OSIntNesting, OSLockNesting, OSRunning, OS_ERR_STATE};
+
+use crate::task::OS_TCB_REF;
+use crate::{wake_task_no_pend, GlobalSyncExecutor};
+
+/// A signal carrying no value, used by an ISR to notify a task.
+pub type InterruptNotify = Signal<()>;
+
+enum State<T> {
+    None,
+    /// an async task is waiting
+    WaitingWaker(Waker),
+    /// a sync task is blocked in `wait_blocking`
+    WaitingTask(OS_TCB_REF),
+    Signaled(T),
+}
+
+/// Single-slot signal which can be posted from any ISR.
+///
+/// Posting overwrites a value which has not been taken yet. Only one task should wait on a
+/// signal at a time: a new waiter replaces (and wakes) the previous one.
+pub struct Signal<T> {
+    state: Mutex<RefCell<State<T>>>,
+}
+
+impl<T> Signal<T> {
+    /// Create a new, empty signal.
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State::None)),
+        }
+    }
+
+    /// Post a value to the signal and make the waiting task (if any) ready.
+    ///
+    /// In thread context the rescheduling happens at once. Inside an ISR (OSIntNesting > 0) it is
+    /// deferred to `OSIntExit()`.
+    pub fn signal(&self, val: T) {
+        task_log!(trace, "Signal::signal");
+        let old = critical_section::with(|cs| self.state.borrow(cs).replace(State::Signaled(val)));
+        match old {
+            // the waker of the executor calls IntCtxSW itself, which does nothing while OSIntNesting > 0
+            State::WaitingWaker(waker) => waker.wake(),
+            State::WaitingTask(task) => {
+                critical_section::with(|_| wake_task_no_pend(task));
+                if OSRunning.load(Ordering::Acquire) {
+                    unsafe { GlobalSyncExecutor().as_ref().unwrap().IntCtxSW() };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Remove the posted value (if any) and forget the waiting task.
+    pub fn reset(&self) {
+        critical_section::with(|cs| self.state.borrow(cs).replace(State::None));
+    }
+
+    /// Take the posted value without waiting.
+    pub fn try_take(&self) -> Option<T> {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow(cs).borrow_mut();
+            match mem::replace(&mut *state, State::None) {
+                State::Signaled(val) => Some(val),
+                other => {
+                    *state = other;
+                    None
+                }
+            }
+        })
+    }
+
+    /// Whether a value has been posted and not taken yet.
+    pub fn signaled(&self) -> bool {
+        critical_section::with(|cs| matches!(*self.state.borrow(cs).borrow(), State::Signaled(_)))
+    }
+
+    /// Wait asynchronously for the signal and take its value.
+    pub fn wait(&self) -> impl Future<Output = T> + '_ {
+        poll_fn(move |cx| {
+            let (res, replaced) = critical_section::with(|cs| {
+                let mut state = self.state.borrow(cs).borrow_mut();
+                match mem::replace(&mut *state, State::None) {
+                    State::Signaled(val) => (Poll::Ready(val), None),
+                    State::WaitingWaker(w) if w.will_wake(cx.waker()) => {
+                        *state = State::WaitingWaker(w);
+                        (Poll::Pending, None)
+                    }
+                    other => {
+                        *state = State::WaitingWaker(cx.waker().clone());
+                        (Poll::Pending, Some(other))
+                    }
+                }
+            });
+            // the previous waiter is replaced, let it poll again
+            match replaced {
+                Some(State::WaitingWaker(w)) => w.wake(),
+                Some(State::WaitingTask(task)) => critical_section::with(|_| wake_task_no_pend(task)),
+                _ => {}
+            }
+            res
+        })
+    }
+
+    /// Block the current task until the signal is posted and take its value.
+    ///
+    /// This is meant for sync tasks. The task leaves the ready list and the highest priority ready task
+    /// is switched in, just like `OSTimeDly()`.
+    ///
+    /// Returns `OS_ERR_PEND_ISR` if called from an ISR and `OS_ERR_PEND_LOCKED` if the scheduler is locked.
+    pub fn wait_blocking(&self) -> Result<T, OS_ERR_STATE> {
+        task_log!(trace, "Signal::wait_blocking");
+        if OSIntNesting.load(Ordering::Acquire) > 0 {
+            return Err(OS_ERR_STATE::OS_ERR_PEND_ISR);
+        }
+        if OSLockNesting.load(Ordering::Acquire) > 0 {
+            return Err(OS_ERR_STATE::OS_ERR_PEND_LOCKED);
+        }
+        let executor = GlobalSyncExecutor().as_ref().unwrap();
+        loop {
+            let (val, replaced) = critical_section::with(|cs| {
+                let mut state = self.state.borrow(cs).borrow_mut();
+                match mem::replace(&mut *state, State::None) {
+                    State::Signaled(val) => (Some(val), None),
+                    other => {
+                        let cur = *executor.OSTCBCur.get_unmut();
+                        unsafe { executor.set_task_unready(cur) };
+                        *state = State::WaitingTask(cur);
+                        (None, Some(other))
+                    }
+                }
+            });
+            if let Some(val) = val {
+                return Ok(val);
+            }
+            match replaced {
+                Some(State::WaitingWaker(w)) => w.wake(),
+                Some(State::WaitingTask(task)) if task != *executor.OSTCBCur.get_unmut() => {
+                    critical_section::with(|_| wake_task_no_pend(task))
+                }
+                _ => {}
+            }
+            // switch to the highest priority ready task. If the signal has been posted meanwhile, the current task is
+            // ready again and the loop takes the value.
+            if critical_section::with(|_| unsafe {
+                executor.set_highrdy();
+                executor.OSPrioHighRdy != executor.OSPrioCur
+            }) {
+                unsafe { executor.block_poll() };
+            }
+        }
+    }
+}
+
+impl<T> Default for Signal<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -12,22 +12,47 @@
 ********************************************************************************************************************************************
 */
 
-pub extern crate alloc;
-use alloc::string::ToString;
 use embassy_preempt_platform::{OsStk};
 use core::alloc::Layout;
 use core::ffi::c_void;
 use core::future::Future;
 use core::sync::atomic::Ordering;
 
-use super::{GlobalSyncExecutor, OS_TCB_REF, task::OS_TASK_STORAGE};
+use super::{GlobalSyncExecutor, OS_TCB_REF, task::{OS_TASK_NAME, OS_TASK_STORAGE, arena_usage, reclaim_task}};
 
 use embassy_preempt_cfg::{OS_LOWEST_PRIO, OS_TASK_REG_TBL_SIZE, ucosii::OS_PRIO};
-use embassy_preempt_mem::heap::{dealloc_stack, stk_from_ptr};
-use embassy_preempt_cfg::ucosii::{OS_PRIO_SELF, OS_TASK_IDLE_PRIO, OSRunning, OSIntNesting, OSTaskCtr, OS_ERR_STATE};
+use embassy_preempt_mem::heap::{dealloc_stack, reserve_emergency_stack, stk_from_ptr};
+use embassy_preempt_platform::traits::PlatformMemoryLayout;
+use embassy_preempt_platform::PlatformImpl;
+use embassy_preempt_structs::cell::SyncUnsafeCell;
+use embassy_preempt_cfg::ucosii::{OS_ARENA_DATA, OS_PRIO_SELF, OS_TASK_IDLE_PRIO, OSRunning, OSIntNesting, OSTaskCtr, OS_ERR_STATE};
 
 const DEFAULT_REVOKE_STACK_SIZE: usize = 128;
 
+/// What the scheduler does when no stack can be allocated for the task it switches to.
+///
+/// In every case the failure is counted in `OSStkAllocFailCtr`. If the current task is blocked (e.g. in `OSTimeDly()`)
+/// when the switch is deferred, it waits for a stack with the CPU idle, and the switch is retried after every interrupt.
+#[derive(Clone, Copy)]
+pub enum OS_STK_FAIL_POLICY {
+    /// keep running the current task, the switch is retried at the next scheduling point
+    Defer,
+    /// switch on the emergency stack reserved by `OSTaskStkFailPolicySet()`, defer if it is already in use
+    Reserve,
+    /// call the hook with the priority of the task and `OS_ERR_STK_ALLOC`, then retry the allocation once
+    /// (the hook may have freed some stacks) and defer if it fails again
+    Hook(fn(OS_PRIO, OS_ERR_STATE)),
+}
+
+static OSStkFailPolicy: SyncUnsafeCell<OS_STK_FAIL_POLICY> = SyncUnsafeCell::new(OS_STK_FAIL_POLICY::Defer);
+
+/// The hook called when a task has overflowed its stack, with the priority and the name of the task
+static OSStkOverflowHook: SyncUnsafeCell<fn(OS_PRIO, &str)> = SyncUnsafeCell::new(OS_StkOverflowDefault);
+
+fn OS_StkOverflowDefault(prio: OS_PRIO, name: &str) {
+    panic!("task {} ({}) overflowed its stack", prio, name);
+}
+
 /*
 ********************************************************************************************************************************************
 *                                                           interface
@@ -47,6 +72,22 @@ pub extern "C" fn SyncOSTaskCreate<F, R>(
     _ptos: *mut OsStk,
     prio: OS_PRIO,
 ) -> OS_ERR_STATE
+where
+    F: FnOnce(*mut c_void) -> R + 'static,
+    R: ReturnUnitOrNeverReturn,
+{
+    SyncOSTaskCreateStk(task, p_arg, _ptos, prio, 0)
+}
+
+/// Create a sync task which gets a stack of `stk_size` bytes when it is preempted, instead of the stack size
+/// of its priority band. A `stk_size` of 0 means the size of the band.
+pub fn SyncOSTaskCreateStk<F, R>(
+    task: F,
+    p_arg: *mut c_void,
+    _ptos: *mut OsStk,
+    prio: OS_PRIO,
+    stk_size: usize,
+) -> OS_ERR_STATE
 where
     // check by liam: why the future is 'static: because the definition of OS_TASK_STORAGE's generic F is 'static
     F: FnOnce(*mut c_void) -> R + 'static,
@@ -73,7 +114,7 @@ where
         dealloc_stack(&mut stk);
     }
     OSTaskCtr.fetch_add(1, Ordering::SeqCst);
-    return init_task(prio, future_func);
+    return init_task(prio, stk_size, future_func);
 }
 
 /// Create a task in uC/OS-II kernel. This func is used by async Rust
@@ -83,6 +124,22 @@ where
     F: Future + 'static,
     FutFn: FnOnce(*mut c_void) -> F + 'static,
 {
+    AsyncOSTaskCreateStk(task, p_arg, _ptos, prio, 0)
+}
+
+/// Create an async task which gets a stack of `stk_size` bytes when it is preempted, instead of the stack size
+/// of its priority band. A `stk_size` of 0 means the size of the band.
+pub fn AsyncOSTaskCreateStk<F, FutFn>(
+    task: FutFn,
+    p_arg: *mut c_void,
+    _ptos: *mut OsStk,
+    prio: OS_PRIO,
+    stk_size: usize,
+) -> OS_ERR_STATE
+where
+    F: Future + 'static,
+    FutFn: FnOnce(*mut c_void) -> F + 'static,
+{
     
     task_log!(info, "Creating async task with priority {}", prio);
     let future_func = || task(p_arg);
@@ -94,7 +151,7 @@ where
         dealloc_stack(&mut stk);
     }
     OSTaskCtr.fetch_add(1, Ordering::SeqCst);
-    return init_task(prio, future_func);
+    return init_task(prio, stk_size, future_func);
 }
 
 
@@ -112,7 +169,7 @@ pub extern "C" fn OSTaskCreate(
     SyncOSTaskCreate(fun_ptr, p_arg, ptos, prio)
 }
 
-fn init_task<F: Future + 'static>(prio: OS_PRIO, future_func: impl FnOnce() -> F) -> OS_ERR_STATE {
+fn init_task<F: Future + 'static>(prio: OS_PRIO, stk_size: usize, future_func: impl FnOnce() -> F) -> OS_ERR_STATE {
     // Make sure we don't create the task from within an ISR
     if OSIntNesting.load(Ordering::Acquire) > 0 {
         return OS_ERR_STATE::OS_ERR_TASK_CREATE_ISR;
@@ -134,7 +191,7 @@ fn init_task<F: Future + 'static>(prio: OS_PRIO, future_func: impl FnOnce() -> F
     }
         task_log!(trace, "the prio is exist");
 
-    let err = OS_TASK_STORAGE::init(prio, 0, 0 as *mut (), 0, "".to_string(), future_func);
+    let err = OS_TASK_STORAGE::init(prio, 0, 0 as *mut (), 0, OS_TASK_NAME::new(), stk_size, future_func);
     if err == OS_ERR_STATE::OS_ERR_NONE {
         // check whether the task is created after the OS has started
         if OSRunning.load(Ordering::Acquire) {
@@ -309,22 +366,26 @@ pub fn OSTaskDel(prio: OS_PRIO) -> OS_ERR_STATE {
 
         // remove task from the priority table
         prio_tbl[prio as usize].ptr = None;
-        // destroy stack only when os is running
-        if OSRunning.load(Ordering::Acquire) {
-            // if prio == executor.OSTCBCur.get_unmut().OSTCBPrio {
-            if prio == *executor.OSPrioCur.get_unmut() {
-                // deleting the task itself sets 'needs_stack_save' to 'false' will destroy the stack in PenSV
-                unsafe { ptcb.needs_stack_save.set(false); }
-            } else {
-                // drop the stack directly when deleting other tasks
-                dealloc_stack(&mut ptcb.take_stk());
-            }
-        }
         // remove task from the timer queue
         unsafe { executor.timer_queue.remove(ptcb); }
         #[cfg(feature = "OS_TASK_NAME_EN")]
         {
-            ptcb.OSTCBTaskName = "?".to_string();
+            ptcb.OSTCBTaskName = "?".into();
+        }
+        // if prio == executor.OSTCBCur.get_unmut().OSTCBPrio {
+        if OSRunning.load(Ordering::Acquire) && prio == *executor.OSPrioCur.get_unmut() {
+            // deleting the task itself sets 'needs_stack_save' to 'false' will destroy the stack in PenSV
+            // the storage is reclaimed when the task leaves the CPU
+            unsafe { ptcb.needs_stack_save.set(false); }
+        } else {
+            // if the task has been preempted, its poll is suspended on the stack which is destroyed here,
+            // so its future can not be dropped and is leaked
+            let preempted = !ptcb.is_stk_none();
+            // drop the stack directly when deleting other tasks
+            if preempted {
+                dealloc_stack(&mut ptcb.take_stk());
+            }
+            unsafe { reclaim_task(ptcb, !preempted); }
         }
         return OS_ERR_STATE::OS_ERR_NONE;
     });
@@ -359,11 +420,62 @@ pub fn OSTaskNameSet(prio: OS_PRIO, pname: &str) -> OS_ERR_STATE {
     let result = critical_section::with(|_cs| { 
         let executor = GlobalSyncExecutor().as_ref().unwrap();   
         if executor.prio_exist(prio) {
-            executor.set_name(prio, pname.to_string());
+            executor.set_name(prio, pname.into());
             OS_ERR_STATE::OS_ERR_NONE
         } else {
             OS_ERR_STATE::OS_ERR_TASK_NOT_EXIST
         }
     });
     return result;
-}
\ No newline at end of file
+}
+
+/// Set the policy applied when no stack can be allocated for a preempting task.
+///
+/// `OS_STK_FAIL_POLICY::Reserve` reserves a stack of the largest band size at once, and returns `OS_ERR_STK_ALLOC`
+/// (keeping the old policy) if there is no memory left for it.
+pub fn OSTaskStkFailPolicySet(policy: OS_STK_FAIL_POLICY) -> OS_ERR_STATE {
+    if let OS_STK_FAIL_POLICY::Reserve = policy {
+        let size = PlatformImpl::get_stack_bands()
+            .iter()
+            .map(|band| band.stack_size)
+            .fold(PlatformImpl::get_task_stack_size(), usize::max);
+        let layout = Layout::from_size_align(size, 4).unwrap();
+        let err = reserve_emergency_stack(layout);
+        if err != OS_ERR_STATE::OS_ERR_NONE {
+            return err;
+        }
+    }
+    critical_section::with(|_| unsafe { OSStkFailPolicy.set(policy) });
+    OS_ERR_STATE::OS_ERR_NONE
+}
+
+pub(crate) fn stk_fail_policy() -> OS_STK_FAIL_POLICY {
+    critical_section::with(|_| unsafe { OSStkFailPolicy.get() })
+}
+
+/// Set the hook called when a task has overflowed its stack. It gets the priority and the name of the task.
+///
+/// An overflow is found either by the canary check when the task is switched out (`OS_STK_CANARY_EN`), or by the MPU
+/// guard (`OS_STK_MPU_GUARD_EN`). The memory below the stack may already be corrupted, so the hook should not expect
+/// the system to keep running correctly; the default hook panics.
+pub fn OSTaskStkOverflowHookSet(hook: fn(OS_PRIO, &str)) {
+    critical_section::with(|_| unsafe { OSStkOverflowHook.set(hook) });
+}
+
+/// report the stack overflow of `task` to the hook
+#[cfg(any(feature = "OS_STK_CANARY_EN", feature = "OS_STK_MPU_GUARD_EN"))]
+pub(crate) fn stk_overflow(task: OS_TCB_REF) {
+    task_log!(error, "the task {} overflowed its stack", task.OSTCBPrio);
+    #[cfg(feature = "OS_TASK_NAME_EN")]
+    let name = task.OSTCBTaskName.as_str();
+    #[cfg(not(feature = "OS_TASK_NAME_EN"))]
+    let name = "";
+    let hook = critical_section::with(|_| unsafe { OSStkOverflowHook.get() });
+    hook(task.OSTCBPrio, name);
+}
+
+/// Get the usage of the arena which stores the tasks, including the storages
+/// of finished or deleted tasks which are waiting to be reused.
+pub fn OSTaskArenaQuery() -> OS_ARENA_DATA {
+    arena_usage()
+}
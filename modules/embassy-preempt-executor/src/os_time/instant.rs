@@ -26,6 +26,10 @@ impl Instant {
             // ticks: embassy_time_driver::now(),
         }
     }
+    /// Create an Instant from a tick count since system boot.
+    pub const fn from_ticks(ticks: u64) -> Instant {
+        Instant { ticks }
+    }
     /// Adds one Duration to self, returning a new `Instant` or None in the event of an overflow.
     pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
         self.ticks.checked_add(duration.ticks).map(|ticks| Instant { ticks })
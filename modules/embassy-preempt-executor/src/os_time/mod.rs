@@ -11,21 +11,47 @@ pub mod blockdelay;
 pub mod duration;
 /// the mod of instant of uC/OS-II kernel
 pub mod instant;
+/// the mod of ticker of uC/OS-II kernel
+pub mod ticker;
+/// the mod of the tickless idle
+#[cfg(feature = "OS_TICKLESS_EN")]
+pub(crate) mod tickless;
 /// the mod of timer of uC/OS-II kernel
 pub mod timer;
 
 /// delay async task 'n' ticks
 pub(crate) unsafe fn delay_tick(_ticks: u64) { unsafe {
+    delay_until(get_platform_trait().get_timer_driver().now() + _ticks);
+}}
+
+/// delay current task until the absolute tick `at`
+pub(crate) unsafe fn delay_until(at: u64) { unsafe {
     // by noah：Remove tasks from the ready queue in advance to facilitate subsequent unified operations
     let executor = GlobalSyncExecutor().as_ref().unwrap();
     let task = executor.OSTCBCur.get_mut();
-    task.expires_at.set(get_platform_trait().get_timer_driver().now() + _ticks);
+    task.expires_at.set(at);
     // update timer
-    let mut next_expire = critical_section::with(|_| {
+    let next_expire = critical_section::with(|_| {
         executor.set_task_unready(*task);
         critical_section::with(|_| executor.timer_queue.update(*task))
     });
     timer_log!(trace, "in delay_tick the next expire is {:?}", next_expire);
+    set_alarm_at(next_expire);
+    // find the highrdy
+    if critical_section::with(|_| {
+        executor.set_highrdy();
+        executor.OSPrioHighRdy != executor.OSPrioCur
+    }) {
+        // switch to the highrdy task
+        GlobalSyncExecutor().as_ref().unwrap().block_poll();
+        timer_log!(trace, "end the delay");
+    }
+}}
+
+/// move the alarm to `next_expire`, the head of the timer queue returned by `timer_queue.update`, if it is earlier
+/// than the alarm set
+pub(crate) unsafe fn set_alarm_at(mut next_expire: u64) { unsafe {
+    let executor = GlobalSyncExecutor().as_ref().unwrap();
     if critical_section::with(|_| {
         if next_expire < *executor.timer_queue.set_time.get_unmut() {
             executor.timer_queue.set_time.set(next_expire);
@@ -49,20 +75,11 @@ pub(crate) unsafe fn delay_tick(_ticks: u64) { unsafe {
                 .dequeue_expired(get_platform_trait().get_timer_driver().now(), wake_task_no_pend);
             // then we need to set a new alarm according to the next expiration time
             next_expire = executor.timer_queue.next_expiration();
-            timer_log!(trace, "in delay_tick the next expire is {:?}", next_expire);
+            timer_log!(trace, "in set_alarm_at the next expire is {:?}", next_expire);
             // by noah：we also need to updater the set_time of the timer_queue
             executor.timer_queue.set_time.set(next_expire);
         }
     }
-    // find the highrdy
-    if critical_section::with(|_| {
-        executor.set_highrdy();
-        executor.OSPrioHighRdy != executor.OSPrioCur
-    }) {
-        // call the interrupt poll
-        GlobalSyncExecutor().as_ref().unwrap().interrupt_poll();
-        timer_log!(trace, "end the delay");
-    }
 }}
 
 
@@ -82,6 +99,66 @@ pub fn OSTimeDly(_ticks: u64) {
     }
 }
 
+/// Skip over the periods which have fully elapsed since `deadline`.
+/// `deadline` must not be later than `now`. Returns the latest deadline that is not later than `now`
+/// (still phase-locked to the original one) and the number of whole periods skipped.
+pub(crate) fn skip_missed_periods(deadline: u64, period: u64, now: u64) -> (u64, u64) {
+    let missed = (now - deadline) / period;
+    (deadline + missed * period, missed)
+}
+
+/*
+*********************************************************************************************************
+*                                    DELAY TASK UNTIL AN ABSOLUTE TIME
+*
+* Description: This function is called to delay execution of the currently running task until
+*              '*last_wake + period'. Unlike OSTimeDly() the wake time does not depend on when this function
+*              is called, so a periodic task does not drift by the execution time of each iteration.
+*              (modeled on vTaskDelayUntil() of FreeRTOS)
+*
+* Arguments  : last_wake  is the tick at which the task was last woken up. It is updated to the new wake
+*                         time, so it should be initialized with OSTimeGet() once before entering the loop.
+*              period     is the period of the task in ticks.
+*
+* Returns    : (OS_ERR_NONE, missed)   'missed' is the number of whole periods which elapsed before the call.
+*                                      If the deadline has already passed, the task is not delayed and the
+*                                      missed periods are skipped so the next wake up stays phase-locked.
+*              (OS_ERR_TIME_ZERO_DLY, 0)
+*              (OS_ERR_TIME_DLY_ISR, 0)
+*              (OS_ERR_SCHED_LOCKED, 0)
+*********************************************************************************************************
+*/
+/// delay the current task until `*last_wake + period`, reporting the number of missed periods
+pub fn OSTimeDlyUntil(last_wake: &mut u64, period: u64) -> (OS_ERR_STATE, u64) {
+    timer_log!(trace, "OSTimeDlyUntil");
+    // See if trying to call from an ISR
+    if OSIntNesting.load(Ordering::Acquire) > 0 {
+        return (OS_ERR_STATE::OS_ERR_TIME_DLY_ISR, 0);
+    }
+    // See if called with scheduler locked
+    if OSLockNesting.load(Ordering::Acquire) > 0 {
+        return (OS_ERR_STATE::OS_ERR_SCHED_LOCKED, 0);
+    }
+    // a zero period can not be phase-locked, so this is checked even without OS_ARG_CHK_EN
+    if period == 0 {
+        return (OS_ERR_STATE::OS_ERR_TIME_ZERO_DLY, 0);
+    }
+    let now = get_platform_trait().get_timer_driver().now();
+    let deadline = *last_wake + period;
+    if deadline > now {
+        *last_wake = deadline;
+        unsafe {
+            delay_until(deadline);
+        }
+        return (OS_ERR_STATE::OS_ERR_NONE, 0);
+    }
+    // the deadline has passed, return at once without losing the phase
+    let (deadline, missed) = skip_missed_periods(deadline, period, now);
+    timer_log!(trace, "OSTimeDlyUntil missed {} periods", missed);
+    *last_wake = deadline;
+    (OS_ERR_STATE::OS_ERR_NONE, missed)
+}
+
 
 /*
 *********************************************************************************************************
@@ -200,10 +277,20 @@ pub fn OSTimeGet() -> u64 {
 /// Schedule the given waker to be woken at `at`.
 pub fn _embassy_time_schedule_wake(at: u64, waker: &core::task::Waker) {
     timer_log!(trace, "_embassy_time_schedule_wake");
-    let task = crate::waker::task_from_waker(waker);
-    let task = task.header();
-    unsafe {
-        let expires_at = task.expires_at.get();
-        task.expires_at.set(expires_at.min(at));
+    match crate::waker::try_task_from_waker(waker) {
+        // the task is put on the timer queue when its poll returns
+        Some(task) => {
+            let task = task.header();
+            unsafe {
+                let expires_at = task.expires_at.get();
+                task.expires_at.set(expires_at.min(at));
+            }
+        }
+        // a waker wrapped by a combinator crate does not tell its task, so the timer queue keeps the waker itself
+        None => unsafe {
+            let executor = GlobalSyncExecutor().as_ref().unwrap();
+            let next_expire = critical_section::with(|_| executor.timer_queue.update_waker(at, waker));
+            set_alarm_at(next_expire);
+        },
     }
 }
\ No newline at end of file
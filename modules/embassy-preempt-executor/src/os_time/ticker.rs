@@ -0,0 +1,76 @@
+use core::future::{poll_fn, Future};
+use core::task::Poll;
+
+use super::duration::Duration;
+use super::instant::Instant;
+use super::skip_missed_periods;
+use super::timer::schedule_wake;
+
+/// Asynchronous stream of periodic ticks.
+///
+/// Unlike calling `Timer::after` in a loop, the deadlines of a `Ticker` are phase-locked to the [Instant]
+/// it was created (or reset) at, so the time spent between two `next().await` does not make the loop drift.
+///
+/// If the task falls behind by one or more whole periods, those periods are skipped instead of firing
+/// back-to-back, and their number is returned by [Ticker::next].
+///
+/// Example:
+/// ``` no_run
+/// use embassy_preempt_executor::os_time::{duration::Duration, ticker::Ticker};
+///
+/// async fn control_loop() {
+///     let mut ticker = Ticker::every(Duration::from_millis(10));
+///     loop {
+///         let missed = ticker.next().await;
+///         // ...
+///     }
+/// }
+/// ```
+pub struct Ticker {
+    expires_at: Instant,
+    duration: Duration,
+}
+
+impl Ticker {
+    /// Creates a new ticker that ticks at the specified duration interval.
+    pub fn every(duration: Duration) -> Self {
+        let expires_at = Instant::now() + duration;
+        Self { expires_at, duration }
+    }
+
+    /// Resets the ticker back to its original state.
+    /// This causes the ticker to go back to zero, even if the current tick isn't over yet.
+    pub fn reset(&mut self) {
+        self.expires_at = Instant::now() + self.duration;
+    }
+
+    /// Reset the ticker at the deadline.
+    /// If the deadline is in the past, the ticker will fire instantly.
+    pub fn reset_at(&mut self, deadline: Instant) {
+        self.expires_at = deadline;
+    }
+
+    /// Resets the ticker, after the specified duration has passed.
+    /// If the specified duration is zero, the next tick will be after the duration of the ticker.
+    pub fn reset_after(&mut self, after: Duration) {
+        self.expires_at = Instant::now() + after + self.duration;
+    }
+
+    /// Waits for the next tick.
+    /// The output is the number of whole periods which were missed since the previous tick.
+    pub fn next(&mut self) -> impl Future<Output = u64> + '_ {
+        poll_fn(|cx| {
+            let now = Instant::now().as_ticks();
+            if self.expires_at.as_ticks() <= now {
+                let period = self.duration.ticks.max(1);
+                let (deadline, missed) = skip_missed_periods(self.expires_at.as_ticks(), period, now);
+                self.expires_at = Instant::from_ticks(deadline + period);
+                timer_log!(trace, "Ticker fired, missed {} periods", missed);
+                Poll::Ready(missed)
+            } else {
+                schedule_wake(self.expires_at.as_ticks(), cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+}
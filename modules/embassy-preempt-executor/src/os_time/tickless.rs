@@ -0,0 +1,37 @@
+//! Tickless idle (`OS_TICKLESS_EN`)
+//!
+//! Instead of spinning, the idle task hands the next timer expiration to the platform, which sleeps in its deepest
+//! low-power mode that wakes up in time and keeps the time base right across the sleep (see
+//! `Platform::sleep_until`). The alarm of the next expiration is already set, and any other interrupt ends the sleep
+//! early: the idle task then runs again only if nothing has been made ready, and computes the next sleep.
+
+use embassy_preempt_cfg::ucosii::OS_TASK_IDLE_PRIO;
+use embassy_preempt_cfg::{OS_TICKLESS_MIN_SLEEP_US, TICK_HZ};
+use embassy_preempt_platform::get_platform_trait;
+
+use crate::GlobalSyncExecutor;
+
+/// `kernel.tickless_min_sleep_us` in ticks, rounded up
+const MIN_SLEEP_TICKS: u64 = (OS_TICKLESS_MIN_SLEEP_US * TICK_HZ).div_ceil(1_000_000);
+
+/// Sleep until the next timer expiration, called by the idle task
+///
+/// Returns `false` without sleeping if the next expiration is closer than `kernel.tickless_min_sleep_us`, or the
+/// platform has no low-power mode; the idle task then waits in `enter_idle_state`.
+pub(crate) fn idle_sleep() -> bool {
+    let executor = GlobalSyncExecutor().as_ref().unwrap();
+    let platform = get_platform_trait();
+    // the interrupts taken from here on wake the core up, and are handled once the sleep is over
+    critical_section::with(|_| {
+        // a task made ready since the idle task was switched in runs first, as soon as the critical section ends
+        if *executor.OSPrioHighRdy.get_unmut() != OS_TASK_IDLE_PRIO {
+            return true;
+        }
+        let wake_at = unsafe { executor.timer_queue.next_expiration() };
+        if wake_at.saturating_sub(platform.get_timer_driver().now()) < MIN_SLEEP_TICKS {
+            return false;
+        }
+        task_log!(trace, "idle sleep until {}", wake_at);
+        platform.sleep_until(wake_at)
+    })
+}
@@ -4,6 +4,54 @@ use core::task::{Context, Poll, Waker};
 
 use super::duration::Duration;
 use super::instant::Instant;
+use crate::os_futures::select::{select, Either, Select};
+
+/// Error returned by [`with_timeout`] and [`with_deadline`] on timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "log-base", derive(defmt::Format))]
+pub struct TimeoutError;
+
+/// Runs a given future with a timeout.
+///
+/// If the future completes before the timeout, its output is returned. Otherwise, on timeout,
+/// work on the future is stopped (`poll` is no longer called), the future is dropped and `Err(TimeoutError)` is returned.
+pub fn with_timeout<F: Future>(timeout: Duration, fut: F) -> WithTimeout<F> {
+    with_deadline(Instant::now() + timeout, fut)
+}
+
+/// Runs a given future with a deadline time.
+///
+/// If the future completes before the deadline, its output is returned. Otherwise, on timeout,
+/// work on the future is stopped (`poll` is no longer called), the future is dropped and `Err(TimeoutError)` is returned.
+pub fn with_deadline<F: Future>(at: Instant, fut: F) -> WithTimeout<F> {
+    WithTimeout {
+        inner: select(fut, Timer::at(at)),
+    }
+}
+
+/// Future for the [`with_timeout`] and [`with_deadline`] functions.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WithTimeout<F> {
+    inner: Select<F, Timer>,
+}
+
+impl<F: Unpin> Unpin for WithTimeout<F> {}
+
+impl<F: Future> Future for WithTimeout<F> {
+    type Output = Result<F::Output, TimeoutError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // safety: `inner` is never moved out of `self`
+        let inner = unsafe { self.map_unchecked_mut(|this| &mut this.inner) };
+        match inner.poll(cx) {
+            // the timer-queue entry left by the timer is removed by the executor
+            // before the task is polled again, see `SyncExecutor::single_poll`
+            Poll::Ready(Either::First(res)) => Poll::Ready(Ok(res)),
+            Poll::Ready(Either::Second(())) => Poll::Ready(Err(TimeoutError)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
 
 #[allow(unused)]
 /// A future that completes at a specified [Instant](struct.Instant.html).
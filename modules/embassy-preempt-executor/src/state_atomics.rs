@@ -1,5 +1,5 @@
 //! Atomic state operations with architecture-specific optimizations.
-#[cfg(target_arch = "arm")]
+#[cfg(all(target_arch = "arm", target_has_atomic = "32"))]
 use core::arch::asm;
 
 use portable_atomic::{AtomicBool, AtomicU32};
@@ -58,10 +58,16 @@ impl State {
         self.spawned.store(false, Ordering::Relaxed);
     }
 
+    /// Whether the task is spawned (has a future).
+    #[inline(always)]
+    pub fn is_spawned(&self) -> bool {
+        self.spawned.load(Ordering::Relaxed)
+    }
+
     /// Mark the task as run-queued if it's spawned and isn't already run-queued. Return true on success.
     #[inline(always)]
     pub fn run_enqueue(&self) -> bool {
-        #[cfg(target_arch = "arm")]
+        #[cfg(all(target_arch = "arm", target_has_atomic = "32"))]
         {
             // ARM-specific optimized implementation using LDREX/STREX
             unsafe {
@@ -84,6 +90,19 @@ impl State {
             }
         }
 
+        #[cfg(all(target_arch = "arm", not(target_has_atomic = "32")))]
+        {
+            // ARMv6-M has no LDREX/STREX, do the read-modify-write in a critical section
+            critical_section::with(|_| {
+                let state = self.as_u32().load(Ordering::Relaxed);
+                if (state & STATE_RUN_QUEUED != 0) || (state & STATE_SPAWNED == 0) {
+                    return false;
+                }
+                self.as_u32().store(state | STATE_RUN_QUEUED, Ordering::Relaxed);
+                true
+            })
+        }
+
         #[cfg(not(target_arch = "arm"))]
         {
             // Generic implementation using standard atomic operations
@@ -1,11 +1,13 @@
 //! Task implementation
+#[cfg(not(feature = "no_alloc"))]
 use alloc::string::String;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll};
 use core::ops::{Deref, DerefMut};
+use core::alloc::Layout;
 use core::ptr::NonNull;
-use core::mem;
+use core::mem::{self, MaybeUninit};
 
 use embassy_preempt_platform::Platform;
 
@@ -15,13 +17,26 @@ use super::GlobalSyncExecutor;
 
 use embassy_preempt_mem::arena::ARENA;
 use embassy_preempt_mem::heap::OS_STK_REF;
-use embassy_preempt_cfg::ucosii::{OS_ERR_STATE, OS_PRIO};
+use embassy_preempt_cfg::ucosii::{OS_ARENA_DATA, OS_ERR_STATE, OS_PRIO};
 use embassy_preempt_cfg::OS_TASK_REG_TBL_SIZE;
+#[cfg(feature = "no_alloc")]
+use embassy_preempt_cfg::OS_TASK_NAME_SIZE;
 use embassy_preempt_structs::cell::{SyncUnsafeCell, UninitCell};
+#[cfg(feature = "no_alloc")]
+use embassy_preempt_structs::name::FixedName;
 use embassy_preempt_platform::traits::platform::PlatformStatic;
+use embassy_preempt_platform::traits::PlatformMemoryLayout;
+use embassy_preempt_platform::PlatformImpl;
 #[cfg(feature = "OS_EVENT_EN")]
 use crate::event::OS_EVENT_REF;
 
+/// the name of a task. In the `no_alloc` mode it is stored inline and truncated to `OS_TASK_NAME_SIZE` bytes
+#[cfg(not(feature = "no_alloc"))]
+pub type OS_TASK_NAME = String;
+/// the name of a task. In the `no_alloc` mode it is stored inline and truncated to `OS_TASK_NAME_SIZE` bytes
+#[cfg(feature = "no_alloc")]
+pub type OS_TASK_NAME = FixedName<OS_TASK_NAME_SIZE>;
+
 /// the TCB of the task. It contains the task's info
 #[allow(unused)]
 // we put it in executor crate to use "pub(crate)" to make it can be used in the other mod in order to reduce coupling
@@ -37,6 +52,10 @@ pub struct OS_TCB {
 
     // the poll fn that will be called by the executor. In the func, a waker will be create.
     pub(crate) OS_POLL_FN: SyncUnsafeCell<Option<unsafe fn(OS_TCB_REF)>>,
+    // the fn that gives the task storage back to the free list. It is None once the storage is reclaimed.
+    pub(crate) OS_RECLAIM_FN: SyncUnsafeCell<Option<unsafe fn(OS_TCB_REF, bool)>>,
+    pub(crate) OSTCBStorageSize: usize, /* Size (in bytes) of the block holding the task storage  */
+    pub(crate) OSTCBPreemptStkSize: usize, /* Size (in bytes) of the stack got when preempted, 0 for the size of the prio band */
 
     #[cfg(feature = "OS_EVENT_EN")]
     pub(crate) OSTCBEventPtr: SyncUnsafeCell<Option<OS_EVENT_REF>>, /* Pointer to event control block                */
@@ -69,7 +88,7 @@ pub struct OS_TCB {
     OSTCBStkUsed: INT32U,             /* Number of bytes used from the stack                     */
     
     #[cfg(feature = "OS_TASK_NAME_EN")]
-    pub(crate) OSTCBTaskName: String,
+    pub(crate) OSTCBTaskName: OS_TASK_NAME,
     
     #[cfg(feature = "OS_TASK_REG_TBL_SIZE")]
     pub(crate) OSTCBRegTbl: [usize; OS_TASK_REG_TBL_SIZE],
@@ -145,6 +164,14 @@ impl OS_TCB {
     pub fn is_stk_none(&self) -> bool {
         self.OSTCBStkPtr.is_none()
     }
+    /// the size of the stack the task gets when it is preempted
+    pub fn preempt_stk_size(&self) -> usize {
+        if self.OSTCBPreemptStkSize != 0 {
+            self.OSTCBPreemptStkSize
+        } else {
+            PlatformImpl::get_task_stack_size_for_prio(self.OSTCBPrio)
+        }
+    }
 }
 
 #[cfg(feature = "OS_TASK_CREATE_EXT_EN")]
@@ -178,6 +205,9 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
                 OSTimerNext: SyncUnsafeCell::new(None),
                 OSTimerPrev: SyncUnsafeCell::new(None),
                 OS_POLL_FN: SyncUnsafeCell::new(None),
+                OS_RECLAIM_FN: SyncUnsafeCell::new(None),
+                OSTCBStorageSize: 0,
+                OSTCBPreemptStkSize: 0,
                 #[cfg(feature = "OS_EVENT_EN")]
                 OSTCBEventPtr: SyncUnsafeCell::new(None),
                 #[cfg(any(all(feature = "OS_Q_EN", feature = "OS_MAX_QS"), feature = "OS_MBOX_EN"))]
@@ -208,7 +238,7 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
                 #[cfg(feature = "OS_TASK_REG_TBL_SIZE")]
                 OSTCBRegTbl: [0; OS_TASK_REG_TBL_SIZE],
                 #[cfg(feature = "OS_TASK_NAME_EN")]
-                OSTCBTaskName: String::new(),
+                OSTCBTaskName: OS_TASK_NAME::new(),
                 expires_at: SyncUnsafeCell::new(u64::MAX),
                 needs_stack_save: SyncUnsafeCell::new(false),
             },
@@ -224,12 +254,13 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
         id: u16,
         pext: *mut (),
         opt: u16,
-        _name: String,
+        _name: OS_TASK_NAME,
+        stk_size: usize,
         future_func: impl FnOnce() -> F,
     ) -> OS_ERR_STATE {
         
         task_log!(debug, "init of OS_TASK_STORAGE");
-        task_log!(trace, "prio: {}, _name: {}", prio, _name[0..]);
+        task_log!(trace, "prio: {}, _name: {}", prio, _name.as_str());
         // by noah: claim a TaskStorage
         let task_ref = OS_TASK_STORAGE::<F>::claim();
 
@@ -238,6 +269,7 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
         unsafe {
             this = &mut *(task_ref.as_ptr() as *mut OS_TASK_STORAGE<F>);
             this.task_tcb.OS_POLL_FN.set(Some(OS_TASK_STORAGE::<F>::poll));
+            this.task_tcb.OS_RECLAIM_FN.set(Some(OS_TASK_STORAGE::<F>::reclaim));
             this.future.write_in_place(future_func);
         }
         assert_eq!(
@@ -250,6 +282,7 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
         this.task_tcb.OSTCBX = prio & 0x07;
         this.task_tcb.OSTCBBitY = 1 << this.task_tcb.OSTCBY;
         this.task_tcb.OSTCBBitX = 1 << this.task_tcb.OSTCBX;
+        this.task_tcb.OSTCBPreemptStkSize = stk_size;
         // set the stat
         if !this.task_tcb.OSTCBStat.spawn() {
             panic!("task with prio {} spawn failed", prio);
@@ -278,7 +311,7 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
         }
         #[cfg(feature = "OS_TASK_NAME_EN")]
         {
-            let name = &_name[0..];
+            let name = _name.as_str();
             task_log!(trace, "created task name: {} will be set", name);
             this.task_tcb.OSTCBTaskName = _name;
         }
@@ -311,7 +344,7 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
             Poll::Ready(_) => {
                 
                 task_log!(trace, "the task {} is ready", this.task_tcb.OSTCBPrio);
-                this.future.drop_in_place();
+                // the future will be dropped when the executor reclaims the storage
                 this.task_tcb.OSTCBStat.despawn();
             }
             Poll::Pending => {
@@ -325,16 +358,46 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
         mem::forget(waker);
     }}
 
+    /// drop the future if required and give the storage back to the free list
+    unsafe fn reclaim(p: OS_TCB_REF, drop_future: bool) { unsafe {
+        let this = &mut *(p.as_ptr() as *mut OS_TASK_STORAGE<F>);
+        if drop_future {
+            this.future.drop_in_place();
+        }
+        // the storage will be overwritten without drop when it is claimed again
+        #[cfg(feature = "OS_TASK_NAME_EN")]
+        {
+            this.task_tcb.OSTCBTaskName = OS_TASK_NAME::new();
+        }
+        this.task_tcb.OSTCBStkPtr = None;
+        free_storage(p);
+    }}
+
+    /// the layout of the block holding the storage, rounded up to its size class
+    fn storage_layout() -> Layout {
+        let layout = Layout::new::<OS_TASK_STORAGE<F>>();
+        Layout::from_size_align(layout.size().next_multiple_of(TASK_STORAGE_CLASS), layout.align()).unwrap()
+    }
+
     /// this func will be called to create a new task(TCB)
     // refer to the get of TaskPoolRef in embassy
     fn claim() -> OS_TCB_REF {
+        let layout = OS_TASK_STORAGE::<F>::storage_layout();
         // by noah: for we can create task after OSTaskCreate, so we need a cs
         critical_section::with(|cs| {
-            let task_storage = ARENA.alloc::<OS_TASK_STORAGE<F>>(cs);
-                task_log!(trace, "size of the task storage is {}", mem::size_of::<OS_TASK_STORAGE<F>>());
+            // reuse a reclaimed storage of the same size class first
+            let ptr = match take_free_storage(layout) {
+                Some(ptr) => ptr,
+                None => match ARENA.alloc_layout(cs, layout) {
+                    Some(ptr) => ptr,
+                    None => panic!("embassy-executor: task arena is full. You must increase the arena size, see the documentation for details: https://docs.embassy.dev/embassy-executor/"),
+                },
+            };
+            task_log!(trace, "size of the task storage is {}", layout.size());
+            let task_storage = unsafe { &mut *(ptr.as_ptr() as *mut MaybeUninit<OS_TASK_STORAGE<F>>) };
             // create a new task which is not init
-            task_storage.write(OS_TASK_STORAGE::new());
-            // by noah：no panic will occurred here because if the Arena is not enough, the program will panic when alloc
+            let task_storage = task_storage.write(OS_TASK_STORAGE::new());
+            task_storage.task_tcb.OSTCBStorageSize = layout.size();
             OS_TCB_REF {
                 ptr: Some(NonNull::new(task_storage as *mut _ as _).unwrap()),
             }
@@ -342,6 +405,82 @@ impl<F: Future + 'static> OS_TASK_STORAGE<F> {
     }
 }
 
+/*
+****************************************************************************************************************************************
+*                                                             task storage free list
+****************************************************************************************************************************************
+*/
+
+/// the granularity of the size classes of the task storage.
+/// A reclaimed storage is reused by any task whose storage falls in the same class.
+const TASK_STORAGE_CLASS: usize = 32;
+
+/// The reclaimed task storages. A reclaimed storage is kept as a valid but despawned TCB, so a stale waker
+/// pointing to it will not enqueue it. The list is linked by `OSTimerNext`, as such a task is never in the timer queue.
+static TASK_FREE_LIST: SyncUnsafeCell<Option<OS_TCB_REF>> = SyncUnsafeCell::new(None);
+
+/// push a task storage to the free list, must be called in a cs
+unsafe fn free_storage(p: OS_TCB_REF) { unsafe {
+    task_log!(trace, "free the storage of the task {}", p.OSTCBPrio);
+    p.OS_POLL_FN.set(None);
+    p.OSTimerPrev.set(None);
+    p.OSTimerNext.set(TASK_FREE_LIST.get());
+    TASK_FREE_LIST.set(Some(p));
+}}
+
+/// take a task storage which fits the layout from the free list, must be called in a cs
+fn take_free_storage(layout: Layout) -> Option<NonNull<u8>> {
+    let executor = GlobalSyncExecutor().as_ref().unwrap();
+    let mut prev: Option<OS_TCB_REF> = None;
+    let mut cur = unsafe { TASK_FREE_LIST.get() };
+    while let Some(cur_ref) = cur {
+        let next = unsafe { cur_ref.OSTimerNext.get() };
+        // the current task may still be referred by the scheduler until the next switch
+        if cur_ref.OSTCBStorageSize == layout.size()
+            && cur_ref.as_ptr() as usize % layout.align() == 0
+            && cur_ref != *executor.OSTCBCur.get_unmut()
+        {
+            unsafe {
+                match prev {
+                    Some(prev_ref) => prev_ref.OSTimerNext.set(next),
+                    None => TASK_FREE_LIST.set(next),
+                }
+            }
+            return NonNull::new(cur_ref.as_ptr() as *mut u8);
+        }
+        prev = cur;
+        cur = next;
+    }
+    None
+}
+
+/// Give the storage of a task which is despawned back to the free list.
+/// If `drop_future` is false, the future is leaked, e.g. when the task is deleted while its poll is suspended on its own stack.
+/// Nothing is done if the storage has already been reclaimed. Must be called in a cs.
+pub(crate) unsafe fn reclaim_task(task: OS_TCB_REF, drop_future: bool) { unsafe {
+    if let Some(reclaim) = task.OS_RECLAIM_FN.swap(None) {
+        reclaim(task, drop_future);
+    }
+}}
+
+/// get the usage of the task arena and of the reclaimed task storages
+pub(crate) fn arena_usage() -> OS_ARENA_DATA {
+    critical_section::with(|cs| {
+        let mut data = OS_ARENA_DATA {
+            OSArenaSize: ARENA.size(),
+            OSArenaUsed: ARENA.used(cs),
+            ..Default::default()
+        };
+        let mut cur = unsafe { TASK_FREE_LIST.get() };
+        while let Some(cur_ref) = cur {
+            data.OSFreeBlks += 1;
+            data.OSFreeBytes += cur_ref.OSTCBStorageSize;
+            cur = unsafe { cur_ref.OSTimerNext.get() };
+        }
+        data
+    })
+}
+
 unsafe impl Sync for OS_TCB_REF {}
 unsafe impl Send for OS_TCB_REF {}
 
@@ -2,13 +2,22 @@
 
 #[allow(unused)]
 
+use core::sync::atomic::Ordering;
+use core::task::Waker;
+
+use embassy_preempt_cfg::ucosii::OSLockNesting;
+
 use super::OS_TCB_REF;
 
 use embassy_preempt_structs::cell::SyncUnsafeCell;
 
+/// the number of wakers not created by the executor (e.g. wrapped by a combinator crate) that can wait at a time
+const FOREIGN_WAKERS: usize = 8;
+
 pub(crate) struct TimerQueue {
     head: SyncUnsafeCell<Option<OS_TCB_REF>>,   // head of the timer queue, indicating the earliest arriving task
     pub(crate) set_time: SyncUnsafeCell<u64>,   // timestamp of the earliest arriving task
+    wakers: SyncUnsafeCell<[Option<(u64, Waker)>; FOREIGN_WAKERS]>, // foreign wakers and their expiration time
 }
 
 impl TimerQueue {
@@ -16,6 +25,7 @@ impl TimerQueue {
         Self {
             head: SyncUnsafeCell::new(None),
             set_time: SyncUnsafeCell::new(u64::MAX),
+            wakers: SyncUnsafeCell::new([const { None }; FOREIGN_WAKERS]),
         }
     }
 
@@ -55,14 +65,47 @@ impl TimerQueue {
         return *self.head.get_unmut().as_ref().unwrap().expires_at.get_unmut();
     }}
 
-    /// get the arrival time of the earliest arriving task
+    /// Insert a foreign waker to be woken at `at`, a waker already in the queue keeps the earlier time.
+    /// return the next expiration time.
+    ///
+    /// If the queue is full, the earliest waker is woken right away to make room, wakers may be woken spuriously.
+    pub(crate) unsafe fn update_waker(&self, at: u64, waker: &Waker) -> u64 { unsafe {
+        timer_log!(trace, "in timer update_waker");
+        let wakers = self.wakers.get_mut();
+        if let Some((expires_at, _)) = wakers.iter_mut().flatten().find(|(_, w)| w.will_wake(waker)) {
+            *expires_at = (*expires_at).min(at);
+        } else {
+            let slot = match wakers.iter().position(Option::is_none) {
+                Some(free) => free,
+                None => {
+                    let earliest = (0..FOREIGN_WAKERS)
+                        .min_by_key(|&i| wakers[i].as_ref().map_or(u64::MAX, |(t, _)| *t))
+                        .unwrap();
+                    if let Some((_, evicted)) = wakers[earliest].take() {
+                        wake_no_pend(evicted);
+                    }
+                    earliest
+                }
+            };
+            wakers[slot] = Some((at, waker.clone()));
+        }
+        self.next_expiration()
+    }}
+
+    /// get the arrival time of the earliest arriving task or foreign waker
     pub(crate) unsafe fn next_expiration(&self) -> u64 {
         let head = self.head.get_unmut();
-        if let Some(head_ref) = head {
+        let task_at = if let Some(head_ref) = head {
             *head_ref.expires_at.get_unmut()
         } else {
             u64::MAX
-        }
+        };
+        self.wakers
+            .get_unmut()
+            .iter()
+            .flatten()
+            .map(|(at, _)| *at)
+            .fold(task_at, u64::min)
     }
     
     /// wake up all tasks whose delay time has arrived
@@ -92,6 +135,12 @@ impl TimerQueue {
             cur_ref.OSTimerPrev.set(None);
             cur = next;
         }
+        for slot in self.wakers.get_mut().iter_mut() {
+            if slot.as_ref().is_some_and(|(at, _)| *at <= now) {
+                let (_, waker) = slot.take().unwrap();
+                wake_no_pend(waker);
+            }
+        }
     }}
 
     /// remove a task from the timer queue
@@ -131,3 +180,11 @@ impl TimerQueue {
         }
     }}
 }
+
+/// Wake a foreign waker without switching to the woken task, like `wake_task_no_pend`: the callers of the timer queue
+/// reschedule by themselves once it is consistent again
+fn wake_no_pend(waker: Waker) {
+    OSLockNesting.fetch_add(1, Ordering::SeqCst);
+    waker.wake();
+    OSLockNesting.fetch_sub(1, Ordering::SeqCst);
+}
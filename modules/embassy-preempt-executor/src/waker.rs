@@ -13,7 +13,12 @@ unsafe fn clone(p: *const ()) -> RawWaker {
 }
 
 unsafe fn wake(p: *const ()) { unsafe {
-    wake_task_no_pend(OS_TCB_REF::from_ptr(p as *const OS_TCB));
+    let task = OS_TCB_REF::from_ptr(p as *const OS_TCB);
+    // the task has finished or been deleted, and its storage may be reclaimed
+    if !task.OSTCBStat.is_spawned() {
+        return;
+    }
+    wake_task_no_pend(task);
     GlobalSyncExecutor().as_ref().unwrap().IntCtxSW();
 }}
 
@@ -35,19 +40,26 @@ pub(crate) unsafe fn from_task(p: OS_TCB_REF) -> Waker { unsafe {
 ///
 /// # Panics
 ///
-/// Panics if the waker is not created by the Embassy executor.
+/// Panics if the waker is not created by the executor.
 pub fn task_from_waker(waker: &Waker) -> OS_TCB_REF {
+    try_task_from_waker(waker).expect(
+        "Found waker not created by the Embassy executor. `embassy_time::Timer` only works with the Embassy executor.",
+    )
+}
+
+/// Get a task pointer from a waker, or `None` if the waker was not created by this executor.
+pub fn try_task_from_waker(waker: &Waker) -> Option<OS_TCB_REF> {
     // safety: OK because WakerHack has the same layout as Waker.
     // This is not really guaranteed because the structs are `repr(Rust)`, it is
     // indeed the case in the current implementation.
     // TODO use waker_getters when stable. https://github.com/rust-lang/rust/issues/96992
     let hack: &WakerHack = unsafe { mem::transmute(waker) };
     if hack.vtable != &VTABLE {
-        panic!("Found waker not created by the Embassy executor. `embassy_time::Timer` only works with the Embassy executor.")
+        return None;
     }
 
     // safety: our wakers are always created with `OS_TCB_REF::as_ptr`
-    unsafe { OS_TCB_REF::from_ptr(hack.data as *const OS_TCB) }
+    Some(unsafe { OS_TCB_REF::from_ptr(hack.data as *const OS_TCB) })
 }
 
 struct WakerHack {
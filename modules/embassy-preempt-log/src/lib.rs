@@ -6,9 +6,12 @@
 
 #![no_std]
 
-#[cfg(feature = "log-base")]
+#[cfg(all(feature = "log-base", not(feature = "log-semihosting")))]
 use defmt_rtt as _;
 
+#[cfg(feature = "log-semihosting")]
+use defmt_semihosting as _;
+
 #[cfg(feature = "log-base")]
 pub use defmt;
 
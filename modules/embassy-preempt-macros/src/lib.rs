@@ -14,6 +14,7 @@ use syn::{parse_macro_input, ItemFn};
 /// target architectures:
 /// - ARM Cortex-M targets: uses `cortex_m_rt::entry`
 /// - QingKe targets: uses `qingke_rt::entry`
+/// - Other RISC-V targets: uses `riscv_rt::entry`
 /// - Other targets: provides fallback implementation
 ///
 /// # Examples
@@ -41,6 +42,10 @@ pub fn entry(_args: TokenStream, input: TokenStream) -> TokenStream {
         #[qingke_rt::entry]
         #input
 
+        #[cfg(all(target_arch = "riscv32", not(feature = "qingke")))]
+        #[riscv_rt::entry]
+        #input
+
         #[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
         compile_error!("Unsupported target architecture for embassy_preempt_macros::entry. Supported architectures: arm, riscv32");
     };
@@ -0,0 +1,10 @@
+include!("../embassy-preempt-cfg/forward_cfgs.rs");
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    forward_cfgs();
+    // the kernel crates above follow the no_alloc mode, whichever of them turned it on
+    if std::env::var_os("CARGO_FEATURE_NO_ALLOC").is_some() {
+        println!("cargo::metadata=no_alloc=1");
+    }
+}
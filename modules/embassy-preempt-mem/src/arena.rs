@@ -3,7 +3,7 @@
 use core::alloc::Layout;
 use core::cell::{Cell, UnsafeCell};
 use core::mem::MaybeUninit;
-use core::ptr::null_mut;
+use core::ptr::{null_mut, NonNull};
 
 use embassy_preempt_cfg::OS_ARENA_SIZE;
 
@@ -34,6 +34,13 @@ impl<const N: usize> Arena<N> {
         mem_log!(trace, "alloc of Arena");
         let layout = Layout::new::<T>();
 
+        match self.alloc_layout(cs, layout) {
+            Some(res) => unsafe { &mut *(res.as_ptr() as *mut MaybeUninit<T>) },
+            None => panic!("embassy-executor: task arena is full. You must increase the arena size, see the documentation for details: https://docs.embassy.dev/embassy-executor/"),
+        }
+    }
+    /// alloc a memory block of the given layout, return None if the arena is full
+    pub fn alloc_layout(&'static self, cs: CriticalSection, layout: Layout) -> Option<NonNull<u8>> {
         let start = self.buf.get().cast::<u8>();
         let end = unsafe { start.add(N) };
 
@@ -47,13 +54,26 @@ impl<const N: usize> Arena<N> {
 
         if align_offset + layout.size() > bytes_left {
             mem_log!(error, "Task arena full: requested {} bytes, {} available", layout.size(), bytes_left);
-            panic!("embassy-executor: task arena is full. You must increase the arena size, see the documentation for details: https://docs.embassy.dev/embassy-executor/");
+            return None;
         }
         let res = unsafe { ptr.add(align_offset) };
         let ptr = unsafe { ptr.add(align_offset + layout.size()) };
 
         self.ptr.borrow(cs).set(ptr);
-        unsafe { &mut *(res as *mut MaybeUninit<T>) }
+        NonNull::new(res)
+    }
+    /// the number of bytes that have been handed out by the arena
+    pub fn used(&self, cs: CriticalSection) -> usize {
+        let ptr = self.ptr.borrow(cs).get();
+        if ptr.is_null() {
+            0
+        } else {
+            (ptr as usize) - (self.buf.get() as usize)
+        }
+    }
+    /// the total size of the arena in bytes
+    pub const fn size(&self) -> usize {
+        N
     }
     /// deallocate the most recently allocated memory block
     pub fn dealloc<T>(&'static self, cs: CriticalSection, ptr: *mut T) {
@@ -1,41 +1,215 @@
-use alloc::alloc::{GlobalAlloc, Layout};
+use core::alloc::{GlobalAlloc, Layout};
 use core::mem;
 use core::ptr::{self, NonNull};
 
 use super::linked_list::Heap;
+use super::tlsf::Tlsf;
 use super::Locked;
 
 /// The block sizes to use.
-///
-/// The sizes must each be power of 2 because they are also used as
-/// the block alignment (alignments must be always powers of 2).
-const BLOCK_SIZES: &[usize] = &[128, 256, 512, 1024, 2048, 4096, 8192, 16384];
+pub const BLOCK_SIZES: &[usize] = &[128, 256, 512, 1024, 2048, 4096, 8192, 16384];
+
+/// The alignment of every block. The blocks are carved from the fallback heap with this alignment only, so that
+/// a heap aligning its chunks to `GRANULE` does not over-allocate; larger alignments bypass the blocks.
+pub const BLOCK_ALIGN: usize = 8;
+
+/// The statistics of one block size class.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BlockClassStats {
+    /// The size of the blocks of this class
+    pub block_size: usize,
+    /// The number of blocks handed out
+    pub allocated: usize,
+    /// The number of blocks waiting in the free list of this class
+    pub free: usize,
+}
+
+/// The statistics of a [`FixedSizeBlockAllocator`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AllocatorStats {
+    /// Per block size class counts
+    pub classes: [BlockClassStats; BLOCK_SIZES.len()],
+    /// The number of allocations too large for any class, served by the fallback heap directly
+    pub large_allocated: usize,
+    /// The total size of the fallback heap
+    pub fallback_size: usize,
+    /// The bytes taken from the fallback heap, including the blocks in the free lists
+    pub fallback_used: usize,
+    /// The highest value of `fallback_used`
+    pub fallback_peak: usize,
+    /// The largest free hole of the fallback heap
+    pub largest_free_hole: usize,
+    /// The bytes handed out to the users (blocks are counted with their class size)
+    pub in_use: usize,
+    /// The highest value of `in_use`
+    pub peak_in_use: usize,
+    /// The number of allocations which failed
+    pub failed_allocs: usize,
+}
+
+/// The heap the blocks are carved from, and which serves the allocations larger than any block.
+pub trait FallbackHeap: Send + Sized {
+    /// An uninitialized heap
+    const EMPTY: Self;
+    /// Initialize the heap with the given bounds, see [`Heap::init`].
+    unsafe fn init(&mut self, heap_bottom: *mut u8, heap_size: usize);
+    /// Allocate a chunk of the given layout.
+    #[allow(clippy::result_unit_err)]
+    fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, ()>;
+    /// Free a chunk returned by [`allocate`](Self::allocate) with the same layout.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout);
+    /// The size of the heap
+    fn size(&self) -> usize;
+    /// The used part of the heap
+    fn used(&self) -> usize;
+    /// The highest used part of the heap since init
+    fn peak(&self) -> usize;
+    /// The largest free hole of the heap
+    fn largest_free_hole(&self) -> usize;
+}
+
+macro_rules! impl_fallback_heap {
+    ($heap:ty) => {
+        impl FallbackHeap for $heap {
+            const EMPTY: Self = <$heap>::empty();
+            unsafe fn init(&mut self, heap_bottom: *mut u8, heap_size: usize) {
+                unsafe { <$heap>::init(self, heap_bottom, heap_size) }
+            }
+            fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, ()> {
+                self.allocate_first_fit(layout)
+            }
+            unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+                unsafe { <$heap>::deallocate(self, ptr, layout) }
+            }
+            fn size(&self) -> usize {
+                <$heap>::size(self)
+            }
+            fn used(&self) -> usize {
+                <$heap>::used(self)
+            }
+            fn peak(&self) -> usize {
+                <$heap>::peak(self)
+            }
+            fn largest_free_hole(&self) -> usize {
+                <$heap>::largest_free_hole(self)
+            }
+        }
+    };
+}
+
+// first fit over a linked list of holes, O(number of holes)
+impl_fallback_heap!(Heap);
+// two-level segregated fit, O(1)
+impl_fallback_heap!(Tlsf);
 
 /// Choose an appropriate block size for the given layout.
 ///
-/// Returns an index into the `BLOCK_SIZES` array.
+/// Returns an index into the `BLOCK_SIZES` array, or None if the layout is served by the fallback heap.
 fn list_index(layout: &Layout) -> Option<usize> {
-let required_block_size = layout.size().max(layout.align());
-    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+    if layout.align() > BLOCK_ALIGN {
+        return None;
+    }
+    BLOCK_SIZES.iter().position(|&s| s >= layout.size())
 }
 
 struct ListNode {
     next: Option<&'static mut ListNode>,
 }
 
-pub struct FixedSizeBlockAllocator {
+/// Serves the allocations up to the largest of [`BLOCK_SIZES`] from per-size free lists, and
+/// the larger ones from the fallback heap `H`.
+pub struct FixedSizeBlockAllocator<H: FallbackHeap = Heap> {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
-    fallback_allocator: Heap,
+    fallback_allocator: H,
+    /// give the free blocks back to the fallback heap when it runs out of memory
+    release_on_failure: bool,
+    // statistics
+    allocated: [usize; BLOCK_SIZES.len()],
+    free_blocks: [usize; BLOCK_SIZES.len()],
+    large_allocated: usize,
+    in_use: usize,
+    peak_in_use: usize,
+    failed_allocs: usize,
 }
 
-impl FixedSizeBlockAllocator {
+impl<H: FallbackHeap> FixedSizeBlockAllocator<H> {
     /// Creates an empty FixedSizeBlockAllocator.
     pub const fn new() -> Self {
         const EMPTY: Option<&'static mut ListNode> = None;
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
-            fallback_allocator: Heap::empty(),
+            fallback_allocator: H::EMPTY,
+            release_on_failure: false,
+            allocated: [0; BLOCK_SIZES.len()],
+            free_blocks: [0; BLOCK_SIZES.len()],
+            large_allocated: 0,
+            in_use: 0,
+            peak_in_use: 0,
+            failed_allocs: 0,
+        }
+    }
+
+    /// Get the statistics of the allocator.
+    pub fn stats(&self) -> AllocatorStats {
+        let mut stats = AllocatorStats {
+            large_allocated: self.large_allocated,
+            fallback_size: self.fallback_allocator.size(),
+            fallback_used: self.fallback_allocator.used(),
+            fallback_peak: self.fallback_allocator.peak(),
+            largest_free_hole: self.fallback_allocator.largest_free_hole(),
+            in_use: self.in_use,
+            peak_in_use: self.peak_in_use,
+            failed_allocs: self.failed_allocs,
+            ..Default::default()
+        };
+        for (index, class) in stats.classes.iter_mut().enumerate() {
+            class.block_size = BLOCK_SIZES[index];
+            class.allocated = self.allocated[index];
+            class.free = self.free_blocks[index];
         }
+        stats
+    }
+
+    /// Set whether the free blocks of all classes are released to the fallback heap (see
+    /// [`release_free_blocks`](Self::release_free_blocks)) when it fails to serve an allocation.
+    /// The allocation is then retried once.
+    pub fn set_release_on_failure(&mut self, enable: bool) {
+        self.release_on_failure = enable;
+    }
+
+    /// Give every block waiting in the free lists back to the fallback heap, where it merges
+    /// with its free neighbours. Later requests of any size can use that memory again.
+    ///
+    /// Returns the number of bytes released.
+    pub fn release_free_blocks(&mut self) -> usize {
+        let released = (0..BLOCK_SIZES.len()).map(|index| self.release_free_class(index)).sum();
+        mem_log!(trace, "release_free_blocks: released {} bytes", released);
+        released
+    }
+
+    /// Give the free blocks of the class `index` of [`BLOCK_SIZES`] back to the fallback heap, see
+    /// [`release_free_blocks`](Self::release_free_blocks).
+    ///
+    /// Returns the number of bytes released.
+    pub fn release_free_class(&mut self, index: usize) -> usize {
+        let block_size = BLOCK_SIZES[index];
+        let layout = Layout::from_size_align(block_size, BLOCK_ALIGN).unwrap();
+        let mut released = 0;
+        while let Some(node) = self.list_heads[index].take() {
+            self.list_heads[index] = node.next.take();
+            let ptr = NonNull::from(node).cast::<u8>();
+            // the block was carved from the fallback heap with exactly this layout
+            unsafe { self.fallback_allocator.deallocate(ptr, layout) };
+            self.free_blocks[index] -= 1;
+            released += block_size;
+        }
+        released
+    }
+
+    /// record a successful allocation of `size` bytes
+    fn record_alloc(&mut self, size: usize) {
+        self.in_use += size;
+        self.peak_in_use = self.peak_in_use.max(self.in_use);
     }
 
     /// Initialize the allocator with the given heap bounds.
@@ -52,7 +226,11 @@ impl FixedSizeBlockAllocator {
     /// Allocates using the fallback allocator.
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
         mem_log!(trace, "fallback_alloc: requesting size={} align={}", layout.size(), layout.align());
-        match self.fallback_allocator.allocate_first_fit(layout) {
+        let mut res = self.fallback_allocator.allocate(layout);
+        if res.is_err() && self.release_on_failure && self.release_free_blocks() > 0 {
+            res = self.fallback_allocator.allocate(layout);
+        }
+        match res {
             Ok(ptr) => {
                 mem_log!(trace, "fallback_alloc: success ptr={:x}", ptr.as_ptr());
                 ptr.as_ptr()
@@ -65,46 +243,64 @@ impl FixedSizeBlockAllocator {
     }
 }
 
-unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+impl<H: FallbackHeap> FixedSizeBlockAllocator<H> {
+    /// Allocate a block of the class of `layout`, or a chunk of the fallback heap if it is too large for any class.
+    /// Returns null if there is no memory left.
+    pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
         mem_log!(trace, "alloc: will alloc {}", layout);
-        let mut allocator = self.lock();
         match list_index(&layout) {
             Some(index) => {
-                match allocator.list_heads[index].take() {
+                match self.list_heads[index].take() {
                     Some(node) => {
-                        allocator.list_heads[index] = node.next.take();
+                        self.list_heads[index] = node.next.take();
                         let ptr = node as *mut ListNode as *mut u8;
                         mem_log!(trace, "alloc: reused block size={} ptr={:x}", BLOCK_SIZES[index], ptr);
+                        self.free_blocks[index] -= 1;
+                        self.allocated[index] += 1;
+                        self.record_alloc(BLOCK_SIZES[index]);
                         ptr
                     }
                     None => {
                         // no block exists in list => allocate new block
                         let block_size = BLOCK_SIZES[index];
-                        // only works if all block sizes are a power of 2
-                        let block_align = block_size;
-                        let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                        let layout = Layout::from_size_align(block_size, BLOCK_ALIGN).unwrap();
                         // TODO: added to debug, remove later when release
-                        let ptr = allocator.fallback_alloc(layout);
+                        let ptr = self.fallback_alloc(layout);
+                        if ptr.is_null() {
+                            self.failed_allocs += 1;
+                        } else {
+                            self.allocated[index] += 1;
+                            self.record_alloc(block_size);
+                        }
                         ptr
                     }
                 }
             }
             None => {
-                let ptr = allocator.fallback_alloc(layout);
+                let ptr = self.fallback_alloc(layout);
                 mem_log!(trace, "alloc: fallback size={} align={} ptr={:x}", layout.size(), layout.align(), ptr);
+                if ptr.is_null() {
+                    self.failed_allocs += 1;
+                } else {
+                    self.large_allocated += 1;
+                    self.record_alloc(layout.size());
+                }
                 ptr
             }
         }
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) { unsafe {
-        let mut allocator = self.lock();
+    /// Free a pointer returned by [`alloc`](Self::alloc) with the same layout.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`alloc`](Self::alloc) with `layout` and must not be used anymore.
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) { unsafe {
         match list_index(&layout) {
             Some(index) => {
                 mem_log!(trace, "dealloc: returning block size={} ptr={:x}", BLOCK_SIZES[index], ptr);
                 let new_node = ListNode {
-                    next: allocator.list_heads[index].take(),
+                    next: self.list_heads[index].take(),
                 };
                 // verify that block has size and alignment required for storing node
                 // check liam: maybe below assert code should palce at the beginning Some(index) branch
@@ -112,13 +308,79 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
                 let new_node_ptr = ptr as *mut ListNode;
                 new_node_ptr.write(new_node);
-                allocator.list_heads[index] = Some(&mut *new_node_ptr);
+                self.list_heads[index] = Some(&mut *new_node_ptr);
+                self.allocated[index] -= 1;
+                self.free_blocks[index] += 1;
+                self.in_use -= BLOCK_SIZES[index];
             }
             None => {
                 mem_log!(trace, "dealloc: fallback size={} align={} ptr={:x}", layout.size(), layout.align(), ptr);
                 let ptr = NonNull::new(ptr).unwrap();
-                allocator.fallback_allocator.deallocate(ptr, layout);
+                self.fallback_allocator.deallocate(ptr, layout);
+                self.large_allocated -= 1;
+                self.in_use -= layout.size();
             }
         }
     }}
 }
+
+unsafe impl<H: FallbackHeap> GlobalAlloc for Locked<FixedSizeBlockAllocator<H>> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.lock().dealloc(ptr, layout) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::boxed::Box;
+    use std::vec;
+
+    use super::*;
+
+    fn new_allocator(size: usize) -> Locked<FixedSizeBlockAllocator<Tlsf>> {
+        let words: &'static mut [usize] = Box::leak(vec![0usize; size / mem::size_of::<usize>()].into_boxed_slice());
+        let allocator = Locked::new(FixedSizeBlockAllocator::new());
+        unsafe { allocator.lock().init(words.as_mut_ptr().cast(), size) };
+        allocator
+    }
+
+    #[test]
+    fn blocks_are_reused_and_released() {
+        let allocator = new_allocator(4096);
+        let l = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let a = allocator.alloc(l);
+            assert!(!a.is_null());
+            // only the block and its header are taken from the fallback heap, not an alignment gap
+            assert!(allocator.lock().stats().fallback_used <= 2 * BLOCK_SIZES[0]);
+            allocator.dealloc(a, l);
+            assert_eq!(allocator.alloc(l), a);
+            allocator.dealloc(a, l);
+        }
+        let mut allocator = allocator.lock();
+        assert_eq!(allocator.stats().classes[0].free, 1);
+        assert_eq!(allocator.release_free_blocks(), BLOCK_SIZES[0]);
+        assert_eq!(allocator.stats().fallback_used, 0);
+    }
+
+    #[test]
+    fn over_aligned_bypasses_blocks() {
+        let allocator = new_allocator(4096);
+        let l = Layout::from_size_align(64, 64).unwrap();
+        unsafe {
+            let p = allocator.alloc(l);
+            assert_eq!(p as usize % 64, 0);
+            assert_eq!(allocator.lock().stats().large_allocated, 1);
+            allocator.dealloc(p, l);
+        }
+        let stats = allocator.lock().stats();
+        assert_eq!(stats.large_allocated, 0);
+        assert_eq!(stats.fallback_used, 0);
+    }
+}
@@ -389,6 +389,18 @@ impl HoleList {
         aligned_layout
     }
 
+    /// Returns the size of the largest hole. This walks the whole list.
+    pub fn largest_hole(&self) -> usize {
+        let mut largest = 0;
+        let mut hole = self.first.next;
+        while let Some(h) = hole {
+            let h = unsafe { h.as_ref() };
+            largest = largest.max(h.size);
+            hole = h.next;
+        }
+        largest
+    }
+
     /// Returns the minimal allocation size. Smaller allocations or deallocations are not allowed.
     pub fn min_size() -> usize {
         size_of::<usize>() * 2
@@ -20,6 +20,7 @@ pub mod hole;
 /// A fixed size heap backed by a linked list of free memory blocks.
 pub struct Heap {
     used: usize,
+    peak: usize,
     holes: HoleList,
 }
 
@@ -30,6 +31,7 @@ impl Heap {
     pub const fn empty() -> Heap {
         Heap {
             used: 0,
+            peak: 0,
             holes: HoleList::empty(),
         }
     }
@@ -126,6 +128,7 @@ impl Heap {
     pub unsafe fn new(heap_bottom: *mut u8, heap_size: usize) -> Heap { unsafe {
         Heap {
             used: 0,
+            peak: 0,
             holes: HoleList::new(heap_bottom, heap_size),
         }
     }}
@@ -156,6 +159,7 @@ impl Heap {
         match self.holes.allocate_first_fit(layout) {
             Ok((ptr, aligned_layout)) => {
                 self.used += aligned_layout.size();
+                self.peak = self.peak.max(self.used);
                 Ok(ptr)
             }
             Err(err) => Err(err),
@@ -213,6 +217,16 @@ impl Heap {
         self.size() - self.used
     }
 
+    /// Returns the highest size of the used part of the heap since init
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Returns the size of the largest free hole, which bounds the largest possible allocation
+    pub fn largest_free_hole(&self) -> usize {
+        self.holes.largest_hole()
+    }
+
     /// Extends the size of the heap by creating a new hole at the end.
     ///
     /// Small extensions are not guaranteed to grow the usable size of
@@ -266,6 +280,7 @@ impl LockedHeap {
     pub unsafe fn new(heap_bottom: *mut u8, heap_size: usize) -> LockedHeap { unsafe {
         LockedHeap(Spinlock::new(Heap {
             used: 0,
+            peak: 0,
             holes: HoleList::new(heap_bottom, heap_size),
         }))
     }}
@@ -6,15 +6,30 @@ pub mod fixed_size_block;
 pub mod linked_list;
 /// Stack_Allocator for OS_STK
 pub mod stack_allocator;
+/// Fixed stack slots for the `no_alloc` mode
+pub mod stack_pool;
+/// Two-Level Segregated Fit allocator with O(1) allocation and deallocation
+pub mod tlsf;
 
+#[cfg(not(feature = "no_alloc"))]
 use embassy_preempt_platform::chip::PlatformImpl;
+#[cfg(not(feature = "no_alloc"))]
 use embassy_preempt_platform::traits::memory_layout::PlatformMemoryLayout;
-use fixed_size_block::FixedSizeBlockAllocator;
+#[cfg(not(feature = "no_alloc"))]
+use fixed_size_block::{AllocatorStats, FixedSizeBlockAllocator};
 pub use stack_allocator::*;
 
-/// Global allocator
+/// The fallback heap of the global allocator
+#[cfg(all(not(feature = "no_alloc"), not(feature = "tlsf_heap")))]
+type HeapBackend = linked_list::Heap;
+#[cfg(all(not(feature = "no_alloc"), feature = "tlsf_heap"))]
+type HeapBackend = tlsf::Tlsf;
+
+/// Global allocator, not installed in the `no_alloc` mode
+#[cfg(not(feature = "no_alloc"))]
 #[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+static ALLOCATOR: Locked<FixedSizeBlockAllocator<HeapBackend>> = Locked::new(FixedSizeBlockAllocator::new());
+#[cfg(not(feature = "no_alloc"))]
 #[allow(unused)]
 pub fn Init_Heap() {
     mem_log!(
@@ -31,6 +46,19 @@ pub fn Init_Heap() {
     mem_log!(trace, "Init_Heap: completed");
 }
 
+/// Get the statistics of the global allocator
+#[cfg(not(feature = "no_alloc"))]
+pub fn heap_stats() -> AllocatorStats {
+    ALLOCATOR.lock().stats()
+}
+
+/// Give the free fixed-size blocks of the global allocator back to its heap.
+/// Returns the number of bytes released.
+#[cfg(not(feature = "no_alloc"))]
+pub fn heap_release_free_blocks() -> usize {
+    ALLOCATOR.lock().release_free_blocks()
+}
+
 /// A wrapper around spin::Mutex to permit trait implementations.
 pub struct Locked<A> {
     inner: spin::Mutex<A>,
@@ -46,4 +74,10 @@ impl<A> Locked<A> {
     pub fn lock(&self) -> spin::MutexGuard<'_, A> {
         self.inner.lock()
     }
+
+    /// Lock without spinning, None if the lock is held. An interrupt handler must not spin on a lock the code it
+    /// has preempted may hold.
+    pub fn try_lock(&self) -> Option<spin::MutexGuard<'_, A>> {
+        self.inner.try_lock()
+    }
 }
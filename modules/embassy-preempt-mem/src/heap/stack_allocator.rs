@@ -4,9 +4,14 @@
 ********************************************************************************************************************************************
 */
 
-use alloc::alloc::{GlobalAlloc, Layout};
+use core::alloc::Layout;
+use core::cell::Cell;
 use core::ptr::NonNull;
+use core::sync::atomic::Ordering;
+#[cfg(not(feature = "no_alloc"))]
+use core::sync::atomic::AtomicBool;
 
+use embassy_preempt_cfg::ucosii::{OSStkAllocFailCtr, OS_ERR_STATE};
 use embassy_preempt_log::mem_log;
 use embassy_preempt_platform::OsStk;
 use embassy_preempt_platform::chip::PlatformImpl;
@@ -14,12 +19,36 @@ use embassy_preempt_platform::chip::PlatformImpl;
 use embassy_preempt_platform::traits::memory_layout::PlatformMemoryLayout;
 use embassy_preempt_platform::traits::platform::PlatformStatic;
 use embassy_preempt_structs::cell::UPSafeCell;
-use spin::Once;
+use spin::{MutexGuard, Once};
 
 use super::Locked;
-use super::fixed_size_block::FixedSizeBlockAllocator;
-static STACK_ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+use super::fixed_size_block::AllocatorStats;
+#[cfg(not(feature = "no_alloc"))]
+use super::fixed_size_block::{BLOCK_SIZES, FixedSizeBlockAllocator};
+#[cfg(feature = "no_alloc")]
+use super::stack_pool::StackPool;
+/// The fallback heap of the stack allocator. Stacks are allocated when a task is preempted, so the O(1)
+/// TLSF keeps the preemption latency bounded.
+#[cfg(all(not(feature = "no_alloc"), not(feature = "tlsf_stack")))]
+type StackBackend = super::linked_list::Heap;
+#[cfg(all(not(feature = "no_alloc"), feature = "tlsf_stack"))]
+type StackBackend = super::tlsf::Tlsf;
+#[cfg(not(feature = "no_alloc"))]
+type StackAllocator = FixedSizeBlockAllocator<StackBackend>;
+/// In the `no_alloc` mode the stack area is carved into fixed slots at init
+#[cfg(feature = "no_alloc")]
+type StackAllocator = StackPool;
+/// Stacks are allocated and freed on the preemption path (interrupt handlers and PendSV), which only try to lock it:
+/// the task context locks it in a critical section.
+static STACK_ALLOCATOR: Locked<StackAllocator> = Locked::new(StackAllocator::new());
+/// the stacks freed on the preemption path while the stack allocator was locked, as a list threaded through the
+/// stacks themselves (address of the first, 0 if none). They are given back the next time it is locked.
+static DEFERRED_FREES: critical_section::Mutex<Cell<usize>> = critical_section::Mutex::new(Cell::new(0));
+/// set when a stack allocation fails, the free stacks are then released by `stack_release_after_failure`
+#[cfg(not(feature = "no_alloc"))]
+static RELEASE_PENDING: AtomicBool = AtomicBool::new(false);
 static PROGRAM_STACK: Once<UPSafeCell<OS_STK_REF>> = Once::new();
+static EMERGENCY_STACK: spin::Mutex<EmergencyStack> = spin::Mutex::new(EmergencyStack { layout: None, stk: None });
 static INTERRUPT_STACK: Once<UPSafeCell<OS_STK_REF>> = Once::new();
 
 /// Get access to the program stack
@@ -32,6 +61,66 @@ pub fn get_interrupt_stack() -> &'static UPSafeCell<OS_STK_REF> {
     INTERRUPT_STACK.get().expect("INTERRUPT_STACK not initialized")
 }
 
+/// A stack kept aside for when the stack area is exhausted
+struct EmergencyStack {
+    /// the layout of the reserved stack, None if no stack is reserved
+    layout: Option<Layout>,
+    /// the reserved stack, None while it is in use
+    stk: Option<NonNull<u8>>,
+}
+unsafe impl Send for EmergencyStack {}
+
+/// The head of a stack whose free has been deferred, see `DEFERRED_FREES`
+struct DeferredFree {
+    /// the address of the next deferred stack, 0 if none
+    next: usize,
+    /// the layout the stack was allocated with
+    layout: Layout,
+}
+
+/// allocate from the locked stack allocator, null if there is no memory left
+fn allocate(allocator: &mut StackAllocator, layout: Layout) -> *mut u8 {
+    #[cfg(not(feature = "no_alloc"))]
+    return allocator.alloc(layout);
+    #[cfg(feature = "no_alloc")]
+    return allocator.allocate(layout).map_or(core::ptr::null_mut(), NonNull::as_ptr);
+}
+
+/// give a stack back to the locked stack allocator
+///
+/// # Safety
+/// `stk_ptr` must have been allocated with `layout` and must not be used anymore
+unsafe fn deallocate(allocator: &mut StackAllocator, stk_ptr: NonNull<u8>, layout: Layout) {
+    #[cfg(not(feature = "no_alloc"))]
+    unsafe {
+        allocator.dealloc(stk_ptr.as_ptr(), layout)
+    };
+    #[cfg(feature = "no_alloc")]
+    unsafe {
+        let _ = layout;
+        allocator.deallocate(stk_ptr)
+    };
+}
+
+/// lock the stack allocator, or None if it is held by the code the caller has interrupted. The stacks freed
+/// meanwhile are given back first.
+fn try_lock_stack_allocator() -> Option<MutexGuard<'static, StackAllocator>> {
+    let mut allocator = STACK_ALLOCATOR.try_lock()?;
+    let mut next = critical_section::with(|cs| DEFERRED_FREES.borrow(cs).replace(0));
+    while let Some(stk) = NonNull::new(next as *mut DeferredFree) {
+        let DeferredFree { next: after, layout } = unsafe { stk.as_ptr().read() };
+        unsafe { deallocate(&mut allocator, stk.cast(), layout) };
+        next = after;
+    }
+    Some(allocator)
+}
+
+/// lock the stack allocator from the task context, must be called in a critical section: the preemption path
+/// cannot come in while the lock is held
+fn lock_stack_allocator() -> MutexGuard<'static, StackAllocator> {
+    try_lock_stack_allocator().expect("the stack allocator is locked outside of a critical section")
+}
+
 /*
 ********************************************************************************************************************************************
 *                                                           interface
@@ -40,36 +129,179 @@ pub fn get_interrupt_stack() -> &'static UPSafeCell<OS_STK_REF> {
 /// init the stack allocator and set up the program stack and the interrupt stack
 pub fn OS_InitStackAllocator() {
     mem_log!(trace, "Init Stack Allocator");
-    unsafe {
-        STACK_ALLOCATOR.lock().init(
+    #[cfg(not(feature = "no_alloc"))]
+    critical_section::with(|_| unsafe {
+        let mut allocator = lock_stack_allocator();
+        allocator.init(
             PlatformImpl::get_stack_start() as *mut u8,
             PlatformImpl::calculate_stack_size(),
         );
-    }
+        // stacks are allocated on the preemption path, where walking the free lists on a failure would be too
+        // slow: the scheduler releases them later through `stack_release_after_failure`
+        allocator.set_release_on_failure(false);
+    });
+    #[cfg(feature = "no_alloc")]
+    critical_section::with(|_| {
+        let mut pool = lock_stack_allocator();
+        unsafe {
+            pool.init(
+                PlatformImpl::get_stack_start() as *mut u8,
+                PlatformImpl::calculate_stack_size(),
+            );
+        }
+        carve_stack_slots(&mut pool);
+    });
     // allocate interrupt Stack and set the interrupt stack pointe
     let layout = Layout::from_size_align(PlatformImpl::get_interrupt_stack_size(), 4).unwrap();
-    let stk = alloc_stack(layout);
+    let Ok(stk) = alloc_stack(layout) else {
+        panic!("no memory for the interrupt stack");
+    };
     INTERRUPT_STACK.call_once(|| unsafe { UPSafeCell::new(stk) });
 
-    // allocate program stack
-    let layout = Layout::from_size_align(PlatformImpl::get_program_stack_size(), 4).unwrap();
-    let stk = alloc_stack(layout);
+    // allocate program stack, large enough for a task of any band
+    let layout = Layout::from_size_align(PlatformImpl::get_initial_program_stack_size(), 4).unwrap();
+    let Ok(stk) = alloc_stack(layout) else {
+        panic!("no memory for the program stack");
+    };
     let stk_ptr = stk.STK_REF.as_ptr() as *mut u8;
     PROGRAM_STACK.call_once(|| unsafe { UPSafeCell::new(stk) });
     // then we change the sp to the top of the program stack
     // this depending on the arch so we need extern and implement in the port
     embassy_preempt_platform::PlatformImpl::set_program_stack_pointer(stk_ptr);
 }
+/// carve the stack area into the stacks `calculate_stack_size` accounts for: the interrupt stack, then the program
+/// stack and `max_stacks` stacks per stack band, or `max_programs` program stacks without bands
+#[cfg(feature = "no_alloc")]
+fn carve_stack_slots(pool: &mut StackPool) {
+    let bands = PlatformImpl::get_stack_bands();
+    let mut carved = pool.add_slots(PlatformImpl::get_interrupt_stack_size(), 1);
+    if bands.is_empty() {
+        carved &= pool.add_slots(PlatformImpl::get_program_stack_size(), PlatformImpl::get_max_programs());
+    } else {
+        carved &= pool.add_slots(PlatformImpl::get_initial_program_stack_size(), 1);
+        for band in bands {
+            carved &= pool.add_slots(band.stack_size, band.max_stacks);
+        }
+    }
+    if !carved {
+        panic!("the stack area can not hold the stack pool, check the stack sizes and the number of stack bands");
+    }
+}
 /// alloc a new stack
-pub fn alloc_stack(layout: Layout) -> OS_STK_REF {
+///
+/// Returns `OS_ERR_STK_ALLOC` (and counts the failure in `OSStkAllocFailCtr`) if the stack area is exhausted. On
+/// the preemption path it also fails, without counting, if the interrupted code holds the stack allocator: the
+/// switch is then deferred like for an exhausted stack area.
+pub fn alloc_stack(layout: Layout) -> Result<OS_STK_REF, OS_ERR_STATE> {
     mem_log!(trace, "alloc_stack");
-    let heap_ptr: *mut u8;
-    unsafe {
-        heap_ptr = STACK_ALLOCATOR.alloc(layout);
+    let heap_ptr =
+        critical_section::with(|_| try_lock_stack_allocator().map(|mut allocator| allocate(&mut allocator, layout)));
+    let Some(heap_ptr) = heap_ptr else {
+        mem_log!(warn, "the stack allocator is busy");
+        return Err(OS_ERR_STATE::OS_ERR_STK_ALLOC);
+    };
+    if heap_ptr.is_null() {
+        mem_log!(error, "failed to alloc a stack of {} bytes", layout.size());
+        OSStkAllocFailCtr.fetch_add(1, Ordering::Relaxed);
+        #[cfg(not(feature = "no_alloc"))]
+        RELEASE_PENDING.store(true, Ordering::Relaxed);
+        return Err(OS_ERR_STATE::OS_ERR_STK_ALLOC);
     }
-    //
     mem_log!(trace, "alloc a stack at {}", heap_ptr);
-    stk_from_ptr(heap_ptr, layout)
+    Ok(new_stack(heap_ptr, layout))
+}
+/// reserve an emergency stack of the given layout, which is handed out by `alloc_emergency_stack` when the stack
+/// area is exhausted. Once the emergency stack is in use, the next freed stack of the same layout refills it.
+pub fn reserve_emergency_stack(layout: Layout) -> OS_ERR_STATE {
+    // the preemption path takes both locks, it must not come in while they are held
+    critical_section::with(|_| {
+        let mut emergency = EMERGENCY_STACK.lock();
+        if emergency.layout.is_some() {
+            return OS_ERR_STATE::OS_ERR_NONE;
+        }
+        let heap_ptr = allocate(&mut lock_stack_allocator(), layout);
+        match NonNull::new(heap_ptr) {
+            Some(stk) => {
+                emergency.layout = Some(layout);
+                emergency.stk = Some(stk);
+                OS_ERR_STATE::OS_ERR_NONE
+            }
+            None => OS_ERR_STATE::OS_ERR_STK_ALLOC,
+        }
+    })
+}
+/// take the emergency stack, if one is reserved, is not in use and is large enough for `layout`
+///
+/// It must only run with interrupts masked, so it takes a critical section itself, and it fails if the interrupted
+/// code holds the emergency stack.
+pub fn alloc_emergency_stack(layout: Layout) -> Result<OS_STK_REF, OS_ERR_STATE> {
+    critical_section::with(|_| {
+        let Some(mut emergency) = EMERGENCY_STACK.try_lock() else {
+            return Err(OS_ERR_STATE::OS_ERR_STK_ALLOC);
+        };
+        match (emergency.stk, emergency.layout) {
+            (Some(stk), Some(reserved)) if reserved.size() >= layout.size() => {
+                emergency.stk = None;
+                mem_log!(warn, "use the emergency stack at {}", stk.as_ptr());
+                Ok(new_stack(stk.as_ptr(), reserved))
+            }
+            _ => Err(OS_ERR_STATE::OS_ERR_STK_ALLOC),
+        }
+    })
+}
+/// give a stack back, refilling the emergency stack first if it is in use
+///
+/// It must only run with interrupts masked, so it takes a critical section itself. If the interrupted code holds a
+/// lock, the stack is given back the next time the stack allocator is locked.
+fn free_stack(stk_ptr: *mut u8, layout: Layout) {
+    critical_section::with(|cs| {
+        let Some(stk) = NonNull::new(stk_ptr) else {
+            return;
+        };
+        if let Some(mut emergency) = EMERGENCY_STACK.try_lock() {
+            if emergency.layout == Some(layout) && emergency.stk.is_none() {
+                emergency.stk = Some(stk);
+                return;
+            }
+        }
+        match STACK_ALLOCATOR.try_lock() {
+            Some(mut allocator) => unsafe { deallocate(&mut allocator, stk, layout) },
+            None => {
+                let deferred = DEFERRED_FREES.borrow(cs);
+                unsafe { stk.cast::<DeferredFree>().as_ptr().write(DeferredFree { next: deferred.get(), layout }) };
+                deferred.set(stk.as_ptr() as usize);
+            }
+        }
+    })
+}
+/// Get the statistics of the stack allocator. The program stack and the interrupt stack are counted as allocated.
+pub fn stack_stats() -> AllocatorStats {
+    critical_section::with(|_| lock_stack_allocator().stats())
+}
+/// Give the free stacks cached by the stack allocator back to the stack area, so that they merge
+/// into larger holes. This walks every free list, so it must be called from a task, not on the preemption path.
+/// Each free list is walked in a critical section of its own.
+/// Returns the number of bytes released, always 0 for the fixed slots of the `no_alloc` mode.
+pub fn stack_release_free_blocks() -> usize {
+    #[cfg(not(feature = "no_alloc"))]
+    let released = {
+        RELEASE_PENDING.store(false, Ordering::Relaxed);
+        (0..BLOCK_SIZES.len())
+            .map(|index| critical_section::with(|_| lock_stack_allocator().release_free_class(index)))
+            .sum()
+    };
+    #[cfg(feature = "no_alloc")]
+    let released = 0;
+    released
+}
+/// Release the free stacks (see `stack_release_free_blocks`) if a stack allocation has failed since the last
+/// release, so that a stack of another size can be found when the switch is retried. Called by the scheduler loop.
+pub fn stack_release_after_failure() -> usize {
+    #[cfg(not(feature = "no_alloc"))]
+    if RELEASE_PENDING.load(Ordering::Relaxed) {
+        return stack_release_free_blocks();
+    }
+    0
 }
 /// dealloc a stack
 pub fn dealloc_stack(stk: &mut OS_STK_REF) {
@@ -80,9 +312,7 @@ pub fn dealloc_stack(stk: &mut OS_STK_REF) {
     let stk_ptr = stk.HEAP_REF.as_ptr();
     stk.STK_REF = NonNull::dangling();
     stk.HEAP_REF = NonNull::dangling();
-    unsafe {
-        STACK_ALLOCATOR.dealloc(stk_ptr, stk.layout);
-    }
+    free_stack(stk_ptr, stk.layout);
 }
 
 /// the ref of the stk
@@ -128,9 +358,7 @@ impl Drop for OS_STK_REF {
         let stk_ptr = self.HEAP_REF.as_ptr();
         self.STK_REF = NonNull::dangling();
         self.HEAP_REF = NonNull::dangling();
-        unsafe {
-            STACK_ALLOCATOR.dealloc(stk_ptr, self.layout);
-        }
+        free_stack(stk_ptr, self.layout);
     }
 }
 
@@ -140,6 +368,37 @@ impl OS_STK_REF {
     }
 }
 
+/// the value of the canary words at the bottom of every stack handed out. Only an overflow overwrites them.
+#[cfg(feature = "OS_STK_CANARY_EN")]
+const STK_CANARY: usize = 0xC0DE_CAFE;
+/// the number of canary words at the bottom of every stack
+#[cfg(feature = "OS_STK_CANARY_EN")]
+pub const STK_CANARY_WORDS: usize = 4;
+
+impl OS_STK_REF {
+    /// check the canary words at the bottom of the stack. A stack without storage always passes.
+    #[cfg(feature = "OS_STK_CANARY_EN")]
+    pub fn canary_intact(&self) -> bool {
+        if self.HEAP_REF == NonNull::dangling() {
+            return true;
+        }
+        let bottom = self.HEAP_REF.as_ptr() as *const usize;
+        (0..STK_CANARY_WORDS).all(|i| unsafe { bottom.add(i).read_volatile() } == STK_CANARY)
+    }
+}
+
+/// turn freshly allocated memory into a stack, writing the canary words at its bottom
+fn new_stack(heap_ptr: *mut u8, layout: Layout) -> OS_STK_REF {
+    #[cfg(feature = "OS_STK_CANARY_EN")]
+    {
+        let bottom = heap_ptr as *mut usize;
+        for i in 0..STK_CANARY_WORDS {
+            unsafe { bottom.add(i).write_volatile(STK_CANARY) };
+        }
+    }
+    stk_from_ptr(heap_ptr, layout)
+}
+
 pub fn stk_from_ptr(heap_ptr: *mut u8, layout: Layout) -> OS_STK_REF {
     OS_STK_REF {
         STK_REF: NonNull::new(unsafe { heap_ptr.offset(layout.size() as isize) as *mut OsStk }).unwrap(),
@@ -0,0 +1,206 @@
+//! Static stack pool
+//!
+//! The stack area is carved into fixed slots once at init, one run of slots per stack size. A stack is taken from the
+//! run of the smallest slots which fit, so no general purpose allocator is involved, nothing fragments and the
+//! allocation time is bounded by the number of runs.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use super::Locked;
+use super::fixed_size_block::{AllocatorStats, BLOCK_SIZES, BlockClassStats};
+
+/// The max. number of slot runs (e.g. the interrupt stack, the program stack and one run per stack band)
+pub const MAX_STACK_RUNS: usize = BLOCK_SIZES.len();
+
+/// the alignment of every slot
+const SLOT_ALIGN: usize = 8;
+
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+/// `count` consecutive slots of `slot_size` bytes starting at `start`
+struct SlotRun {
+    start: usize,
+    slot_size: usize,
+    count: usize,
+    allocated: usize,
+    free_list: Option<&'static mut ListNode>,
+}
+
+impl SlotRun {
+    const EMPTY: Self = SlotRun {
+        start: 0,
+        slot_size: 0,
+        count: 0,
+        allocated: 0,
+        free_list: None,
+    };
+
+    fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr < self.start + self.slot_size * self.count
+    }
+
+    /// push the slot at `addr` to the free list
+    unsafe fn push(&mut self, addr: usize) {
+        let node = addr as *mut ListNode;
+        unsafe {
+            node.write(ListNode {
+                next: self.free_list.take(),
+            });
+            self.free_list = Some(&mut *node);
+        }
+    }
+}
+
+/// Hands out the slots of a stack area carved by [`add_slots`](Self::add_slots).
+pub struct StackPool {
+    runs: [SlotRun; MAX_STACK_RUNS],
+    n_runs: usize,
+    /// the stack area
+    bottom: usize,
+    top: usize,
+    /// the start of the part not carved yet
+    next: usize,
+    // statistics
+    in_use: usize,
+    peak_in_use: usize,
+    failed_allocs: usize,
+}
+
+impl StackPool {
+    /// Creates an empty StackPool.
+    pub const fn new() -> Self {
+        StackPool {
+            runs: [SlotRun::EMPTY; MAX_STACK_RUNS],
+            n_runs: 0,
+            bottom: 0,
+            top: 0,
+            next: 0,
+            in_use: 0,
+            peak_in_use: 0,
+            failed_allocs: 0,
+        }
+    }
+
+    /// Initialize the pool with the given stack area, which holds no slot yet.
+    ///
+    /// # Safety
+    ///
+    /// The memory in the given bounds must be valid, unused and must not be used by anything else.
+    pub unsafe fn init(&mut self, area_bottom: *mut u8, area_size: usize) {
+        self.bottom = area_bottom as usize;
+        self.top = self.bottom + area_size;
+        self.next = self.bottom.next_multiple_of(SLOT_ALIGN);
+        self.n_runs = 0;
+    }
+
+    /// Carve `count` slots of `slot_size` bytes (rounded up to 8) from the rest of the stack area.
+    ///
+    /// Returns false if the area is too small or if there are already [`MAX_STACK_RUNS`] runs of other sizes.
+    pub fn add_slots(&mut self, slot_size: usize, count: usize) -> bool {
+        if count == 0 {
+            return true;
+        }
+        let slot_size = slot_size.next_multiple_of(SLOT_ALIGN);
+        if slot_size.saturating_mul(count) > self.top.saturating_sub(self.next) {
+            return false;
+        }
+        // slots of the size of the last run extend it, the areas are contiguous
+        let idx = match self.n_runs.checked_sub(1) {
+            Some(last) if self.runs[last].slot_size == slot_size => last,
+            _ if self.n_runs < MAX_STACK_RUNS => {
+                self.runs[self.n_runs] = SlotRun {
+                    start: self.next,
+                    slot_size,
+                    ..SlotRun::EMPTY
+                };
+                self.n_runs += 1;
+                self.n_runs - 1
+            }
+            _ => return false,
+        };
+        let run = &mut self.runs[idx];
+        run.count += count;
+        // push from the top, so the slots are handed out from the bottom
+        for i in (0..count).rev() {
+            unsafe { run.push(self.next + i * slot_size) };
+        }
+        self.next += slot_size * count;
+        true
+    }
+
+    /// Take a slot of the smallest run which fits `layout`.
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let run = self.runs[..self.n_runs]
+            .iter_mut()
+            .filter(|run| {
+                run.slot_size >= layout.size()
+                    && run.start % layout.align() == 0
+                    && run.slot_size % layout.align() == 0
+                    && run.free_list.is_some()
+            })
+            .min_by_key(|run| run.slot_size);
+        let Some(run) = run else {
+            self.failed_allocs += 1;
+            return None;
+        };
+        let node = run.free_list.take().unwrap();
+        run.free_list = node.next.take();
+        run.allocated += 1;
+        self.in_use += run.slot_size;
+        self.peak_in_use = self.peak_in_use.max(self.in_use);
+        NonNull::new(node as *mut ListNode as *mut u8)
+    }
+
+    /// Give a slot back to its run.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`allocate`](Self::allocate) and must not be used anymore.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>) {
+        let addr = ptr.as_ptr() as usize;
+        let Some(run) = self.runs[..self.n_runs].iter_mut().find(|run| run.contains(addr)) else {
+            panic!("StackPool: {:#x} is not a stack slot", addr);
+        };
+        run.allocated -= 1;
+        self.in_use -= run.slot_size;
+        unsafe { run.push(addr) };
+    }
+
+    /// Get the statistics of the pool. Each run is reported as a block class; the carved part of the stack area is
+    /// reported as the used part of the fallback heap.
+    pub fn stats(&self) -> AllocatorStats {
+        let mut stats = AllocatorStats::default();
+        for (class, run) in stats.classes.iter_mut().zip(&self.runs[..self.n_runs]) {
+            *class = BlockClassStats {
+                block_size: run.slot_size,
+                allocated: run.allocated,
+                free: run.count - run.allocated,
+            };
+        }
+        stats.fallback_size = self.top - self.bottom;
+        stats.fallback_used = self.next - self.bottom;
+        stats.fallback_peak = stats.fallback_used;
+        stats.largest_free_hole = self.top - self.next;
+        stats.in_use = self.in_use;
+        stats.peak_in_use = self.peak_in_use;
+        stats.failed_allocs = self.failed_allocs;
+        stats
+    }
+}
+
+unsafe impl Send for StackPool {}
+
+unsafe impl GlobalAlloc for Locked<StackPool> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().allocate(layout).map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe { self.lock().deallocate(ptr) };
+        }
+    }
+}
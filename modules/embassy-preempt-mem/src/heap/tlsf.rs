@@ -0,0 +1,454 @@
+//! Two-Level Segregated Fit allocator.
+//!
+//! The free blocks are kept in `FL_COUNT * SL_COUNT` segregated lists. The first level splits the
+//! sizes by powers of two and the second level splits every power of two linearly into `SL_COUNT`
+//! ranges. Two bitmaps record which lists are not empty, so finding a suitable free block is a
+//! couple of bit scans, and allocation and deallocation both run in bounded time whatever the
+//! number of free blocks is.
+//!
+//! Every block starts with a [`BlockHdr`] holding its size and a pointer to the physically previous
+//! block, so a freed block is merged with its free neighbours in O(1) as well.
+
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::{self, NonNull};
+
+/// log2 of the number of second level lists per first level
+const SL_LOG2: usize = 4;
+const SL_COUNT: usize = 1 << SL_LOG2;
+/// the granularity of the block sizes, and the alignment of every payload
+const GRANULE: usize = 2 * size_of::<usize>();
+const GRANULE_LOG2: usize = GRANULE.trailing_zeros() as usize;
+/// the blocks smaller than this are all kept in the first level 0, in linear ranges of `GRANULE`
+const FL_SHIFT: usize = SL_LOG2 + GRANULE_LOG2;
+const SMALL_BLOCK: usize = 1 << FL_SHIFT;
+const FL_COUNT: usize = usize::BITS as usize - FL_SHIFT + 1;
+
+/// the block is free
+const FREE: usize = 0b01;
+/// the physically previous block is free
+const PREV_FREE: usize = 0b10;
+const FLAGS: usize = FREE | PREV_FREE;
+
+/// The header in front of every block (allocated or free).
+#[repr(C)]
+struct BlockHdr {
+    /// the physically previous block, null for the first block
+    prev_phys: *mut BlockHdr,
+    /// the size of the whole block including this header, with the flags in the low bits
+    size: usize,
+}
+
+/// A free block, the list links are stored in the (unused) payload.
+#[repr(C)]
+struct FreeBlock {
+    hdr: BlockHdr,
+    next_free: *mut FreeBlock,
+    prev_free: *mut FreeBlock,
+}
+
+const HDR_SIZE: usize = size_of::<BlockHdr>();
+const MIN_BLOCK: usize = size_of::<FreeBlock>();
+
+impl BlockHdr {
+    fn size(&self) -> usize {
+        self.size & !FLAGS
+    }
+
+    fn is_free(&self) -> bool {
+        self.size & FREE != 0
+    }
+
+    fn is_prev_free(&self) -> bool {
+        self.size & PREV_FREE != 0
+    }
+
+    fn set_size(&mut self, size: usize) {
+        self.size = size | (self.size & FLAGS);
+    }
+
+    fn set_flag(&mut self, flag: usize, set: bool) {
+        if set {
+            self.size |= flag;
+        } else {
+            self.size &= !flag;
+        }
+    }
+
+    /// the physically next block. The last block is followed by a zero sized sentinel which is never free.
+    unsafe fn next_phys(&mut self) -> *mut BlockHdr {
+        unsafe { (self as *mut BlockHdr).cast::<u8>().add(self.size()).cast() }
+    }
+}
+
+/// get the (first level, second level) list in which a block of `size` is kept
+fn mapping_insert(size: usize) -> (usize, usize) {
+    if size < SMALL_BLOCK {
+        (0, size >> GRANULE_LOG2)
+    } else {
+        let fl = (usize::BITS - 1 - size.leading_zeros()) as usize;
+        let sl = (size >> (fl - SL_LOG2)) ^ SL_COUNT;
+        (fl - FL_SHIFT + 1, sl)
+    }
+}
+
+/// get the first list in which every block is large enough for `size`
+fn mapping_search(size: usize) -> Option<(usize, usize)> {
+    let size = if size >= SMALL_BLOCK {
+        let fl = (usize::BITS - 1 - size.leading_zeros()) as usize;
+        size.checked_add((1 << (fl - SL_LOG2)) - 1)?
+    } else {
+        size
+    };
+    let (fl, sl) = mapping_insert(size);
+    if fl < FL_COUNT { Some((fl, sl)) } else { None }
+}
+
+/// A heap managed by a Two-Level Segregated Fit allocator.
+///
+/// It offers the same interface as the linked list [`Heap`](super::linked_list::Heap), but
+/// [`allocate_first_fit`](Self::allocate_first_fit) and [`deallocate`](Self::deallocate) run in
+/// O(1) instead of O(number of holes).
+pub struct Tlsf {
+    fl_bitmap: usize,
+    sl_bitmap: [u32; FL_COUNT],
+    free_lists: [[*mut FreeBlock; SL_COUNT]; FL_COUNT],
+    size: usize,
+    used: usize,
+    peak: usize,
+}
+
+unsafe impl Send for Tlsf {}
+
+impl Tlsf {
+    /// Creates an empty heap. All allocate calls will return `Err`.
+    pub const fn empty() -> Tlsf {
+        Tlsf {
+            fl_bitmap: 0,
+            sl_bitmap: [0; FL_COUNT],
+            free_lists: [[ptr::null_mut(); SL_COUNT]; FL_COUNT],
+            size: 0,
+            used: 0,
+            peak: 0,
+        }
+    }
+
+    /// Initializes an empty heap.
+    ///
+    /// The bottom is aligned up and the top aligned down to `2 * size_of::<usize>()`. A region too
+    /// small to hold one block leaves the heap empty.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called at most once. The memory in the
+    /// `[heap_bottom, heap_bottom + heap_size)` range must be valid for the `'static` lifetime and
+    /// must not be used for anything else.
+    pub unsafe fn init(&mut self, heap_bottom: *mut u8, heap_size: usize) {
+        *self = Tlsf::empty();
+        let start = heap_bottom as usize;
+        let bottom = (start + GRANULE - 1) & !(GRANULE - 1);
+        let top = start.saturating_add(heap_size) & !(GRANULE - 1);
+        if top < bottom || top - bottom < MIN_BLOCK + HDR_SIZE {
+            return;
+        }
+        // one free block covering the region, followed by the sentinel
+        let block_size = top - bottom - HDR_SIZE;
+        unsafe {
+            let block = heap_bottom.add(bottom - start).cast::<BlockHdr>();
+            block.write(BlockHdr {
+                prev_phys: ptr::null_mut(),
+                size: block_size | FREE,
+            });
+            (*block).next_phys().write(BlockHdr {
+                prev_phys: block,
+                size: PREV_FREE,
+            });
+            self.insert(block.cast());
+        }
+        self.size = top - bottom;
+    }
+
+    /// Allocates a chunk of the given size with the given alignment. Returns a pointer to the
+    /// beginning of that chunk if it was successful. Else it returns `Err`.
+    ///
+    /// Despite its name (kept to match [`Heap`](super::linked_list::Heap)) this is a good fit
+    /// search: it takes the first block of the smallest non-empty list whose blocks are all large
+    /// enough, which is found with two bit scans.
+    #[allow(clippy::result_unit_err)]
+    pub fn allocate_first_fit(&mut self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        let payload = layout.size().max(MIN_BLOCK - HDR_SIZE);
+        let block_size = payload.checked_add(HDR_SIZE + GRANULE - 1).ok_or(())? & !(GRANULE - 1);
+        let align = layout.align();
+        // a larger alignment may need a free block to be split off in front of the payload
+        let search_size = if align > GRANULE {
+            block_size.checked_add(align + MIN_BLOCK).ok_or(())?
+        } else {
+            block_size
+        };
+        let (fl, sl) = mapping_search(search_size).ok_or(())?;
+        let (fl, sl) = self.find_suitable(fl, sl).ok_or(())?;
+        unsafe {
+            let mut block = self.free_lists[fl][sl];
+            self.remove(block, fl, sl);
+            if align > GRANULE {
+                let payload_addr = block as usize + HDR_SIZE;
+                let mut aligned = (payload_addr + align - 1) & !(align - 1);
+                if aligned != payload_addr && aligned - payload_addr < MIN_BLOCK {
+                    aligned = (payload_addr + MIN_BLOCK + align - 1) & !(align - 1);
+                }
+                let gap = aligned - payload_addr;
+                if gap != 0 {
+                    // the leading part stays free, the rest becomes the block to hand out
+                    let rest = self.split(block.cast(), gap);
+                    self.insert(block);
+                    (*rest).set_flag(PREV_FREE, true);
+                    block = rest.cast();
+                }
+            }
+            let hdr = block.cast::<BlockHdr>();
+            if (*hdr).size() - block_size >= MIN_BLOCK {
+                let rest = self.split(hdr, block_size);
+                (*rest).set_flag(FREE, true);
+                (*rest).set_flag(PREV_FREE, false);
+                self.insert(rest.cast());
+            }
+            (*hdr).set_flag(FREE, false);
+            (*(*hdr).next_phys()).set_flag(PREV_FREE, false);
+            self.used += (*hdr).size();
+            self.peak = self.peak.max(self.used);
+            Ok(NonNull::new_unchecked(hdr.cast::<u8>().add(HDR_SIZE)))
+        }
+    }
+
+    /// Frees the given allocation and merges it with its free neighbours.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a pointer returned by a call to [`allocate_first_fit`](Self::allocate_first_fit)
+    /// of this heap, and must not have been freed yet.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, _layout: Layout) {
+        unsafe {
+            let mut block = ptr.as_ptr().sub(HDR_SIZE).cast::<BlockHdr>();
+            self.used -= (*block).size();
+            if (*block).is_prev_free() {
+                let prev = (*block).prev_phys;
+                let (fl, sl) = mapping_insert((*prev).size());
+                self.remove(prev.cast(), fl, sl);
+                (*prev).set_size((*prev).size() + (*block).size());
+                block = prev;
+            }
+            let next = (*block).next_phys();
+            if (*next).is_free() {
+                let (fl, sl) = mapping_insert((*next).size());
+                self.remove(next.cast(), fl, sl);
+                (*block).set_size((*block).size() + (*next).size());
+            }
+            (*block).set_flag(FREE, true);
+            let next = (*block).next_phys();
+            (*next).prev_phys = block;
+            (*next).set_flag(PREV_FREE, true);
+            self.insert(block.cast());
+        }
+    }
+
+    /// Returns the size of the heap, including the block headers.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the size of the used part of the heap, including the block headers.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the size of the free part of the heap
+    pub fn free(&self) -> usize {
+        self.size - self.used
+    }
+
+    /// Returns the highest size of the used part of the heap since init
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Returns the payload size of the largest free block, which bounds the largest possible allocation
+    pub fn largest_free_hole(&self) -> usize {
+        if self.fl_bitmap == 0 {
+            return 0;
+        }
+        let fl = (usize::BITS - 1 - self.fl_bitmap.leading_zeros()) as usize;
+        let sl = (u32::BITS - 1 - self.sl_bitmap[fl].leading_zeros()) as usize;
+        // the blocks of one list differ in size, so look at all of them
+        let mut largest = 0;
+        let mut block = self.free_lists[fl][sl];
+        while !block.is_null() {
+            unsafe {
+                largest = largest.max((*block).hdr.size());
+                block = (*block).next_free;
+            }
+        }
+        largest - HDR_SIZE
+    }
+
+    /// find the first non-empty list at or above (fl, sl)
+    fn find_suitable(&self, fl: usize, sl: usize) -> Option<(usize, usize)> {
+        let sl_map = self.sl_bitmap[fl] & (!0u32 << sl);
+        if sl_map != 0 {
+            return Some((fl, sl_map.trailing_zeros() as usize));
+        }
+        // fl + 1 < usize::BITS as FL_COUNT is smaller than usize::BITS
+        let fl_map = self.fl_bitmap & (!0usize << (fl + 1));
+        if fl_map == 0 {
+            return None;
+        }
+        let fl = fl_map.trailing_zeros() as usize;
+        Some((fl, self.sl_bitmap[fl].trailing_zeros() as usize))
+    }
+
+    /// split `block` (which is not in any list) at `size`, the second part is returned and keeps the
+    /// flags of `block`. `block` must be larger than `size + MIN_BLOCK`.
+    unsafe fn split(&mut self, block: *mut BlockHdr, size: usize) -> *mut BlockHdr {
+        unsafe {
+            let rest = block.cast::<u8>().add(size).cast::<BlockHdr>();
+            rest.write(BlockHdr {
+                prev_phys: block,
+                size: ((*block).size() - size) | ((*block).size & FLAGS),
+            });
+            (*(*rest).next_phys()).prev_phys = rest;
+            (*block).set_size(size);
+            rest
+        }
+    }
+
+    unsafe fn insert(&mut self, block: *mut FreeBlock) {
+        let (fl, sl) = mapping_insert(unsafe { (*block).hdr.size() });
+        let head = self.free_lists[fl][sl];
+        unsafe {
+            (*block).next_free = head;
+            (*block).prev_free = ptr::null_mut();
+            if !head.is_null() {
+                (*head).prev_free = block;
+            }
+        }
+        self.free_lists[fl][sl] = block;
+        self.fl_bitmap |= 1 << fl;
+        self.sl_bitmap[fl] |= 1 << sl;
+    }
+
+    unsafe fn remove(&mut self, block: *mut FreeBlock, fl: usize, sl: usize) {
+        unsafe {
+            let next = (*block).next_free;
+            let prev = (*block).prev_free;
+            if !next.is_null() {
+                (*next).prev_free = prev;
+            }
+            if !prev.is_null() {
+                (*prev).next_free = next;
+            } else {
+                self.free_lists[fl][sl] = next;
+                if next.is_null() {
+                    self.sl_bitmap[fl] &= !(1 << sl);
+                    if self.sl_bitmap[fl] == 0 {
+                        self.fl_bitmap &= !(1 << fl);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::boxed::Box;
+    use std::vec;
+
+    use super::*;
+
+    /// a heap over a leaked buffer of `size` bytes
+    fn new_heap(size: usize) -> Tlsf {
+        let words: &'static mut [usize] = Box::leak(vec![0usize; size / size_of::<usize>()].into_boxed_slice());
+        let mut heap = Tlsf::empty();
+        unsafe { heap.init(words.as_mut_ptr().cast(), size) };
+        heap
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn empty() {
+        let mut heap = Tlsf::empty();
+        assert!(heap.allocate_first_fit(layout(8, 8)).is_err());
+        assert_eq!(heap.largest_free_hole(), 0);
+    }
+
+    #[test]
+    fn split() {
+        let mut heap = new_heap(4096);
+        let hole = heap.largest_free_hole();
+        let a = heap.allocate_first_fit(layout(100, 8)).unwrap();
+        assert_eq!(a.as_ptr() as usize % GRANULE, 0);
+        // the block is rounded up to the granule with its header, the rest stays one free block
+        assert_eq!(heap.used(), (100 + HDR_SIZE).next_multiple_of(GRANULE));
+        assert_eq!(heap.largest_free_hole(), hole - heap.used());
+        unsafe { heap.deallocate(a, layout(100, 8)) };
+        assert_eq!(heap.used(), 0);
+        assert_eq!(heap.largest_free_hole(), hole);
+    }
+
+    #[test]
+    fn merge() {
+        let mut heap = new_heap(4096);
+        let hole = heap.largest_free_hole();
+        let l = layout(256, 8);
+        let a = heap.allocate_first_fit(l).unwrap();
+        let b = heap.allocate_first_fit(l).unwrap();
+        let c = heap.allocate_first_fit(l).unwrap();
+        let rest = heap.largest_free_hole();
+        unsafe {
+            // no free neighbour
+            heap.deallocate(b, l);
+            assert_eq!(heap.largest_free_hole(), rest);
+            // merged with the next block
+            heap.deallocate(a, l);
+            assert!(heap.allocate_first_fit(layout(2 * 256, 8)).is_ok_and(|ab| ab == a));
+            heap.deallocate(a, layout(2 * 256, 8));
+            // merged with the previous block and the rest of the heap
+            heap.deallocate(c, l);
+        }
+        assert_eq!(heap.used(), 0);
+        assert_eq!(heap.largest_free_hole(), hole);
+    }
+
+    #[test]
+    fn alignment() {
+        let mut heap = new_heap(8192);
+        let hole = heap.largest_free_hole();
+        // move the next payload off any large alignment
+        let pad = heap.allocate_first_fit(layout(8, 8)).unwrap();
+        for align in [32, 256, 1024] {
+            let l = layout(64, align);
+            let p = heap.allocate_first_fit(l).unwrap();
+            assert_eq!(p.as_ptr() as usize % align, 0);
+            unsafe { heap.deallocate(p, l) };
+        }
+        unsafe { heap.deallocate(pad, layout(8, 8)) };
+        // the gaps split off in front of the aligned blocks merged back
+        assert_eq!(heap.used(), 0);
+        assert_eq!(heap.largest_free_hole(), hole);
+    }
+
+    #[test]
+    fn exhausted() {
+        let mut heap = new_heap(1024);
+        let hole = heap.largest_free_hole();
+        assert!(heap.allocate_first_fit(layout(hole + 1, 8)).is_err());
+        let half = heap.allocate_first_fit(layout(hole / 2, 8)).unwrap();
+        assert!(heap.allocate_first_fit(layout(hole / 2, 8)).is_err());
+        unsafe { heap.deallocate(half, layout(hole / 2, 8)) };
+        assert_eq!(heap.largest_free_hole(), hole);
+    }
+}
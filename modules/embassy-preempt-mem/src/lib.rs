@@ -6,10 +6,12 @@
 //! - Fixed-size block allocation
 //! - Stack-based allocation
 //! - Linked list-based heap management
+//! - Fixed-size memory partitions
 
 #![no_std]
 #![allow(missing_docs)]
 
+#[cfg(not(feature = "no_alloc"))]
 pub extern crate alloc;
 
 #[macro_use]
@@ -17,5 +19,7 @@ extern crate embassy_preempt_log;
 
 pub mod arena;
 pub mod heap;
+#[cfg(feature = "OS_MEM_EN")]
+pub mod os_mem;
 
 pub use heap::*;
\ No newline at end of file
@@ -0,0 +1,524 @@
+//! Fixed-size memory partitions of uC/OS-II.
+//!
+//! A partition is carved from a user-provided static buffer into blocks of the same size. The free blocks
+//! are linked through their first word, so `OSMemGet`/`OSMemPut` are O(1), do not touch the heap and can be
+//! called from an ISR.
+
+/*
+********************************************************************************************************************************************
+*                                                           import
+********************************************************************************************************************************************
+*/
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+
+use embassy_preempt_cfg::ucosii::{OS_ERR_STATE, OS_MEM_DATA};
+use embassy_preempt_cfg::OS_MAX_MEM_PART;
+use embassy_preempt_structs::cell::SyncUnsafeCell;
+
+/*
+********************************************************************************************************************************************
+*                                                           data structure
+********************************************************************************************************************************************
+*/
+
+/// the memory control block of a partition
+pub struct OS_MEM {
+    OSMemAddr: *mut u8,     /* Pointer to beginning of memory partition              */
+    OSMemFreeList: *mut u8, /* Pointer to list of free memory blocks                 */
+    OSMemBlkSize: usize,    /* Size (in bytes) of each block in the partition        */
+    OSMemNBlks: u32,        /* Total number of blocks in the partition               */
+    OSMemNFree: u32,        /* Number of free memory blocks in the partition         */
+    #[cfg(feature = "OS_MEM_NAME_EN")]
+    OSMemName: &'static str, /* Memory partition name                                */
+}
+
+/// the ref of a memory partition
+#[derive(Clone, Copy, PartialEq)]
+pub struct OS_MEM_REF {
+    /// the pointer to the memory control block
+    pub ptr: Option<NonNull<OS_MEM>>,
+}
+
+unsafe impl Sync for OS_MEM_REF {}
+unsafe impl Send for OS_MEM_REF {}
+
+impl OS_MEM {
+    const fn new() -> Self {
+        Self {
+            OSMemAddr: ptr::null_mut(),
+            OSMemFreeList: ptr::null_mut(),
+            OSMemBlkSize: 0,
+            OSMemNBlks: 0,
+            OSMemNFree: 0,
+            #[cfg(feature = "OS_MEM_NAME_EN")]
+            OSMemName: "?",
+        }
+    }
+
+    /// link all the blocks of the partition into the free list
+    unsafe fn init(&mut self, addr: *mut u8, nblks: u32, blksize: usize) { unsafe {
+        let mut plink = addr;
+        for i in 0..nblks {
+            let next = if i + 1 < nblks { plink.add(blksize) } else { ptr::null_mut() };
+            (plink as *mut *mut u8).write_unaligned(next);
+            plink = plink.add(blksize);
+        }
+        self.OSMemAddr = addr;
+        self.OSMemFreeList = addr;
+        self.OSMemNFree = nblks;
+        self.OSMemNBlks = nblks;
+        self.OSMemBlkSize = blksize;
+    }}
+
+    /// take a block from the free list, must be called in a cs
+    unsafe fn get(&mut self) -> Option<NonNull<u8>> { unsafe {
+        if self.OSMemNFree == 0 {
+            return None;
+        }
+        let pblk = self.OSMemFreeList;
+        self.OSMemFreeList = (pblk as *mut *mut u8).read_unaligned();
+        self.OSMemNFree -= 1;
+        NonNull::new(pblk)
+    }}
+
+    /// give a block back to the free list, must be called in a cs
+    unsafe fn put(&mut self, pblk: *mut u8) -> OS_ERR_STATE { unsafe {
+        let start = self.OSMemAddr as usize;
+        let end = start + self.OSMemNBlks as usize * self.OSMemBlkSize;
+        let addr = pblk as usize;
+        if addr < start || addr >= end || (addr - start) % self.OSMemBlkSize != 0 {
+            return OS_ERR_STATE::OS_ERR_MEM_INVALID_PBLK;
+        }
+        // Make sure all blocks not already returned
+        if self.OSMemNFree >= self.OSMemNBlks {
+            return OS_ERR_STATE::OS_ERR_MEM_FULL;
+        }
+        (pblk as *mut *mut u8).write_unaligned(self.OSMemFreeList);
+        self.OSMemFreeList = pblk;
+        self.OSMemNFree += 1;
+        OS_ERR_STATE::OS_ERR_NONE
+    }}
+
+    fn data(&self) -> OS_MEM_DATA {
+        OS_MEM_DATA {
+            OSAddr: self.OSMemAddr as *mut (),
+            OSFreeList: self.OSMemFreeList as *mut (),
+            OSBlkSize: self.OSMemBlkSize as u32,
+            OSNBlks: self.OSMemNBlks,
+            OSNFree: self.OSMemNFree,
+            OSNUsed: self.OSMemNBlks - self.OSMemNFree,
+        }
+    }
+}
+
+/*
+********************************************************************************************************************************************
+*                                                           global variables
+********************************************************************************************************************************************
+*/
+
+/// the memory partition table
+static OSMemTbl: SyncUnsafeCell<[OS_MEM; OS_MAX_MEM_PART]> = SyncUnsafeCell::new([const { OS_MEM::new() }; OS_MAX_MEM_PART]);
+/// the free memory partition table list, linked by `OSMemFreeList` of the unused partitions
+static OSMemFreeList: SyncUnsafeCell<OS_MEM_REF> = SyncUnsafeCell::new(OS_MEM_REF { ptr: None });
+
+unsafe impl Sync for OS_MEM {}
+
+/*
+********************************************************************************************************************************************
+*                                                           interface
+********************************************************************************************************************************************
+*/
+
+/*
+*********************************************************************************************************
+*                                    INITIALIZE MEMORY PARTITION MANAGER
+*
+* Description : This function is called by uC/OS-II to initialize the memory partition manager.  Your
+*               application MUST NOT call this function.
+*
+* Arguments   : none
+*
+* Returns     : none
+*********************************************************************************************************
+*/
+/// initialize the memory partition manager, called by OSInit
+pub fn OS_MemInit() {
+    mem_log!(trace, "OS_MemInit");
+    critical_section::with(|_| {
+        let tbl = OSMemTbl.get_mut();
+        for i in 0..OS_MAX_MEM_PART {
+            let next: *mut OS_MEM = if i + 1 < OS_MAX_MEM_PART { &mut tbl[i + 1] as *mut OS_MEM } else { ptr::null_mut() };
+            tbl[i] = OS_MEM::new();
+            tbl[i].OSMemFreeList = next as *mut u8;
+        }
+        unsafe { OSMemFreeList.set(OS_MEM_REF { ptr: tbl.first_mut().map(NonNull::from) }) };
+    });
+}
+
+/*
+*********************************************************************************************************
+*                                        CREATE A MEMORY PARTITION
+*
+* Description : Create a fixed-sized memory partition that will be managed by uC/OS-II.
+*
+* Arguments   : buf      is the memory which will be used by the partition. It must be aligned to a pointer.
+*               nblks    is the number of memory blocks to create from the partition.
+*               blksize  is the size (in bytes) of each block in the memory partition.
+*
+* Returns     : (OS_ERR_NONE, pmem)               if the partition was created
+*               (OS_ERR_MEM_INVALID_ADDR, None)   if 'buf' is not aligned to a pointer
+*               (OS_ERR_MEM_INVALID_PART, None)   no free partitions available
+*               (OS_ERR_MEM_INVALID_BLKS, None)   user specified an invalid number of blocks (must be >= 2,
+*                                                 and never 0 even without the argument checks)
+*               (OS_ERR_MEM_INVALID_SIZE, None)   user specified an invalid block size
+*                                                 (must be a multiple of a pointer and the blocks must fit in 'buf')
+*********************************************************************************************************
+*/
+/// create a fixed-sized memory partition in `buf`
+pub fn OSMemCreate(buf: &'static mut [u8], nblks: u32, blksize: usize) -> (OS_ERR_STATE, OS_MEM_REF) {
+    mem_log!(trace, "OSMemCreate");
+    let none = OS_MEM_REF { ptr: None };
+    #[cfg(feature = "OS_ARG_CHK_EN")]
+    {
+        // Must be pointer size aligned
+        if buf.as_ptr() as usize % mem::align_of::<*mut u8>() != 0 {
+            return (OS_ERR_STATE::OS_ERR_MEM_INVALID_ADDR, none);
+        }
+        // Must have at least 2 blocks per partition
+        if nblks < 2 {
+            return (OS_ERR_STATE::OS_ERR_MEM_INVALID_BLKS, none);
+        }
+    }
+    // An empty partition has no block to link, even without the argument checks
+    if nblks == 0 {
+        return (OS_ERR_STATE::OS_ERR_MEM_INVALID_BLKS, none);
+    }
+    // Must contain space for at least a pointer, and keep the next block aligned
+    if blksize < mem::size_of::<*mut u8>() || blksize % mem::align_of::<*mut u8>() != 0 {
+        return (OS_ERR_STATE::OS_ERR_MEM_INVALID_SIZE, none);
+    }
+    // The blocks must fit in 'buf', without the size wrapping around
+    match (nblks as usize).checked_mul(blksize) {
+        Some(size) if size <= buf.len() => {}
+        _ => return (OS_ERR_STATE::OS_ERR_MEM_INVALID_SIZE, none),
+    }
+    critical_section::with(|_| unsafe {
+        // Get next free memory partition
+        let Some(mut pmem) = OSMemFreeList.get().ptr else {
+            return (OS_ERR_STATE::OS_ERR_MEM_INVALID_PART, none);
+        };
+        let pmem_ref = pmem.as_mut();
+        OSMemFreeList.set(OS_MEM_REF { ptr: NonNull::new(pmem_ref.OSMemFreeList as *mut OS_MEM) });
+        pmem_ref.init(buf.as_mut_ptr(), nblks, blksize);
+        #[cfg(feature = "OS_MEM_NAME_EN")]
+        {
+            pmem_ref.OSMemName = "?";
+        }
+        (OS_ERR_STATE::OS_ERR_NONE, OS_MEM_REF { ptr: Some(pmem) })
+    })
+}
+
+/*
+*********************************************************************************************************
+*                                          GET A MEMORY BLOCK
+*
+* Description : Get a memory block from a partition. This function can be called from an ISR.
+*
+* Arguments   : pmem    is a pointer to the memory partition control block
+*
+* Returns     : (OS_ERR_NONE, pblk)                 if the block was obtained
+*               (OS_ERR_MEM_NO_FREE_BLKS, null)     if there are no more free memory blocks
+*               (OS_ERR_MEM_INVALID_PMEM, null)     if you passed an invalid 'pmem'
+*********************************************************************************************************
+*/
+/// get a memory block from a partition
+pub fn OSMemGet(pmem: OS_MEM_REF) -> (OS_ERR_STATE, *mut u8) {
+    let Some(mut pmem) = pmem.ptr else {
+        return (OS_ERR_STATE::OS_ERR_MEM_INVALID_PMEM, ptr::null_mut());
+    };
+    critical_section::with(|_| match unsafe { pmem.as_mut().get() } {
+        Some(pblk) => (OS_ERR_STATE::OS_ERR_NONE, pblk.as_ptr()),
+        None => (OS_ERR_STATE::OS_ERR_MEM_NO_FREE_BLKS, ptr::null_mut()),
+    })
+}
+
+/*
+*********************************************************************************************************
+*                                        RELEASE A MEMORY BLOCK
+*
+* Description : Returns a memory block to a partition. This function can be called from an ISR.
+*
+* Arguments   : pmem    is a pointer to the memory partition control block
+*               pblk    is a pointer to the memory block being released.
+*
+* Returns     : OS_ERR_NONE              if the memory block was inserted into the partition
+*               OS_ERR_MEM_FULL          if you are returning a memory block to an already FULL memory
+*                                        partition (You freed more blocks than you allocated!)
+*               OS_ERR_MEM_INVALID_PMEM  if you passed an invalid 'pmem'
+*               OS_ERR_MEM_INVALID_PBLK  if 'pblk' is not a block of this partition
+*********************************************************************************************************
+*/
+/// release a memory block to a partition
+pub fn OSMemPut(pmem: OS_MEM_REF, pblk: *mut u8) -> OS_ERR_STATE {
+    let Some(mut pmem) = pmem.ptr else {
+        return OS_ERR_STATE::OS_ERR_MEM_INVALID_PMEM;
+    };
+    if pblk.is_null() {
+        return OS_ERR_STATE::OS_ERR_MEM_INVALID_PBLK;
+    }
+    critical_section::with(|_| unsafe { pmem.as_mut().put(pblk) })
+}
+
+/*
+*********************************************************************************************************
+*                                          QUERY MEMORY PARTITION
+*
+* Description : This function is used to determine the number of free memory blocks and the number of
+*               used memory blocks from a memory partition.
+*
+* Arguments   : pmem    is a pointer to the memory partition control block
+*
+* Returns     : (OS_ERR_NONE, data)              if the partition was queried
+*               (OS_ERR_MEM_INVALID_PMEM, None)  if you passed an invalid 'pmem'
+*********************************************************************************************************
+*/
+/// get the information about a memory partition
+pub fn OSMemQuery(pmem: OS_MEM_REF) -> (OS_ERR_STATE, Option<OS_MEM_DATA>) {
+    let Some(pmem) = pmem.ptr else {
+        return (OS_ERR_STATE::OS_ERR_MEM_INVALID_PMEM, None);
+    };
+    critical_section::with(|_| (OS_ERR_STATE::OS_ERR_NONE, Some(unsafe { pmem.as_ref().data() })))
+}
+
+/// get the name of a memory partition
+#[cfg(feature = "OS_MEM_NAME_EN")]
+pub fn OSMemNameGet(pmem: OS_MEM_REF) -> (OS_ERR_STATE, &'static str) {
+    let Some(pmem) = pmem.ptr else {
+        return (OS_ERR_STATE::OS_ERR_MEM_INVALID_PMEM, "");
+    };
+    critical_section::with(|_| (OS_ERR_STATE::OS_ERR_NONE, unsafe { pmem.as_ref().OSMemName }))
+}
+
+/// set the name of a memory partition
+#[cfg(feature = "OS_MEM_NAME_EN")]
+pub fn OSMemNameSet(pmem: OS_MEM_REF, pname: &'static str) -> OS_ERR_STATE {
+    let Some(mut pmem) = pmem.ptr else {
+        return OS_ERR_STATE::OS_ERR_MEM_INVALID_PMEM;
+    };
+    #[cfg(feature = "OS_ARG_CHK_EN")]
+    if pname.is_empty() {
+        return OS_ERR_STATE::OS_ERR_PNAME_NULL;
+    }
+    critical_section::with(|_| unsafe { pmem.as_mut().OSMemName = pname });
+    OS_ERR_STATE::OS_ERR_NONE
+}
+
+/*
+********************************************************************************************************************************************
+*                                                           typed pool
+********************************************************************************************************************************************
+*/
+
+#[repr(C)]
+union PoolSlot<T> {
+    value: mem::ManuallyDrop<MaybeUninit<T>>,
+    next: *mut u8,
+}
+
+/// A typed pool of `N` blocks holding a `T` each, which hands out [`PoolBox`]es.
+///
+/// The pool is a partition over its own static storage, so it does not use a slot of the partition table.
+/// Like `OSMemGet`/`OSMemPut`, allocating and dropping a box is O(1) and ISR-safe.
+/// ``` no_run
+/// static POOL: Pool<[u8; 64], 8> = Pool::new();
+///
+/// let buf = POOL.alloc([0; 64]).unwrap();
+/// ```
+pub struct Pool<T, const N: usize> {
+    storage: UnsafeCell<MaybeUninit<[PoolSlot<T>; N]>>,
+    part: SyncUnsafeCell<OS_MEM>,
+    inited: SyncUnsafeCell<bool>,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Pool<T, N> {}
+
+impl<T, const N: usize> Pool<T, N> {
+    /// create an empty pool, the storage is linked lazily on the first allocation
+    pub const fn new() -> Self {
+        Self {
+            storage: UnsafeCell::new(MaybeUninit::uninit()),
+            part: SyncUnsafeCell::new(OS_MEM::new()),
+            inited: SyncUnsafeCell::new(false),
+        }
+    }
+
+    /// must be called in a cs
+    #[allow(clippy::mut_from_ref)]
+    fn part(&self) -> &mut OS_MEM {
+        if !*self.inited.get_unmut() {
+            unsafe {
+                self.part
+                    .get_mut()
+                    .init(self.storage.get() as *mut u8, N as u32, mem::size_of::<PoolSlot<T>>());
+                self.inited.set(true);
+            }
+        }
+        self.part.get_mut()
+    }
+
+    /// move `value` into a block of the pool, or give it back if the pool is exhausted
+    pub fn alloc(&'static self, value: T) -> Result<PoolBox<T, N>, T> {
+        match critical_section::with(|_| unsafe { self.part().get() }) {
+            Some(pblk) => {
+                let ptr = pblk.cast::<T>();
+                unsafe { ptr.as_ptr().write(value) };
+                Ok(PoolBox {
+                    ptr,
+                    pool: self,
+                    _marker: PhantomData,
+                })
+            }
+            None => Err(value),
+        }
+    }
+
+    /// the number of free blocks of the pool
+    pub fn free(&self) -> usize {
+        critical_section::with(|_| self.part().OSMemNFree as usize)
+    }
+
+    /// get the information about the pool
+    pub fn query(&self) -> OS_MEM_DATA {
+        critical_section::with(|_| self.part().data())
+    }
+}
+
+impl<T, const N: usize> Default for Pool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A box in a [`Pool`]. The value is dropped and the block given back to the pool when the box is dropped.
+pub struct PoolBox<T: 'static, const N: usize> {
+    ptr: NonNull<T>,
+    pool: &'static Pool<T, N>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send, const N: usize> Send for PoolBox<T, N> {}
+unsafe impl<T: Sync, const N: usize> Sync for PoolBox<T, N> {}
+
+impl<T, const N: usize> Deref for PoolBox<T, N> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T, const N: usize> DerefMut for PoolBox<T, N> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T, const N: usize> Drop for PoolBox<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.ptr.as_ptr()) };
+        let res = critical_section::with(|_| unsafe { self.pool.part().put(self.ptr.as_ptr() as *mut u8) });
+        debug_assert!(res == OS_ERR_STATE::OS_ERR_NONE);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::boxed::Box;
+    use std::vec;
+
+    use super::*;
+
+    /// a leaked buffer of `words` words, aligned to a pointer
+    fn buf(words: usize) -> &'static mut [u8] {
+        let words: &'static mut [usize] = Box::leak(vec![0usize; words].into_boxed_slice());
+        unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, mem::size_of_val(words)) }
+    }
+
+    const WORD: usize = mem::size_of::<usize>();
+
+    #[test]
+    fn create_rejects_no_blocks() {
+        let (err, pmem) = OSMemCreate(buf(4), 0, WORD);
+        assert!(err == OS_ERR_STATE::OS_ERR_MEM_INVALID_BLKS);
+        assert!(pmem.ptr.is_none());
+    }
+
+    #[test]
+    fn create_rejects_overflowing_size() {
+        let (err, pmem) = OSMemCreate(buf(4), u32::MAX, usize::MAX / 2 & !(WORD - 1));
+        assert!(err == OS_ERR_STATE::OS_ERR_MEM_INVALID_SIZE);
+        assert!(pmem.ptr.is_none());
+    }
+
+    #[test]
+    fn create_rejects_small_buffer() {
+        let (err, _) = OSMemCreate(buf(4), 5, WORD);
+        assert!(err == OS_ERR_STATE::OS_ERR_MEM_INVALID_SIZE);
+    }
+
+    #[test]
+    fn create_rejects_unaligned_size() {
+        let (err, _) = OSMemCreate(buf(4), 2, WORD + 1);
+        assert!(err == OS_ERR_STATE::OS_ERR_MEM_INVALID_SIZE);
+    }
+
+    // the only test using the partition table, which `OS_MemInit` resets
+    #[test]
+    fn get_put() {
+        OS_MemInit();
+        let (err, pmem) = OSMemCreate(buf(8), 4, 2 * WORD);
+        assert!(err == OS_ERR_STATE::OS_ERR_NONE);
+
+        let blks: [*mut u8; 4] = core::array::from_fn(|_| {
+            let (err, pblk) = OSMemGet(pmem);
+            assert!(err == OS_ERR_STATE::OS_ERR_NONE);
+            pblk
+        });
+        for (i, a) in blks.iter().enumerate() {
+            assert_eq!(*a as usize % WORD, 0);
+            assert!(blks[i + 1..].iter().all(|b| (*a as usize).abs_diff(*b as usize) >= 2 * WORD));
+        }
+        assert!(OSMemGet(pmem).0 == OS_ERR_STATE::OS_ERR_MEM_NO_FREE_BLKS);
+
+        // not the start of a block
+        assert!(OSMemPut(pmem, unsafe { blks[0].add(1) }) == OS_ERR_STATE::OS_ERR_MEM_INVALID_PBLK);
+        for pblk in blks {
+            assert!(OSMemPut(pmem, pblk) == OS_ERR_STATE::OS_ERR_NONE);
+        }
+        assert!(OSMemPut(pmem, blks[0]) == OS_ERR_STATE::OS_ERR_MEM_FULL);
+
+        let (_, data) = OSMemQuery(pmem);
+        let data = data.unwrap();
+        assert_eq!(data.OSNFree, 4);
+        assert_eq!(data.OSNUsed, 0);
+    }
+
+    #[test]
+    fn pool() {
+        static POOL: Pool<u64, 2> = Pool::new();
+        let a = POOL.alloc(1).unwrap();
+        let b = POOL.alloc(2).unwrap();
+        assert_eq!(POOL.alloc(3).err(), Some(3));
+        assert_eq!((*a, *b), (1, 2));
+        drop(a);
+        assert_eq!(POOL.free(), 1);
+        assert_eq!(*POOL.alloc(4).unwrap(), 4);
+    }
+}
@@ -1,24 +1,45 @@
 use std::env;
-#[cfg(feature = "memory-x")]
+use std::fs;
 use std::path::PathBuf;
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
-    // Check if STM32F401RE feature is enabled
-    let has_stm32f401re = env::var("CARGO_FEATURE_STM32F401RE").is_ok();
+    // the STM32 chip, for its memory.x
+    let stm32_chip = ["stm32f401re", "stm32f411re", "stm32f446re", "stm32g474re"]
+        .into_iter()
+        .find(|chip| env::var(format!("CARGO_FEATURE_{}", chip.to_uppercase())).is_ok());
     let has_ch32v307wcu6 = env::var("CARGO_FEATURE_CH32V307WCU6").is_ok();
+    let has_microbit = env::var("CARGO_FEATURE_MICROBIT").is_ok();
+    let has_mps2_an386 = env::var("CARGO_FEATURE_MPS2_AN386").is_ok();
+    let has_qemu_virt = env::var("CARGO_FEATURE_QEMU_VIRT").is_ok();
 
     
-    let chip_core_name = if has_stm32f401re {
-        "stm32f401re"
+    let chip_core_name = if let Some(chip) = stm32_chip {
+        chip
     } else if has_ch32v307wcu6 {
         "ch32v307wcu6"
+    } else if has_microbit {
+        "microbit"
+    } else if has_mps2_an386 {
+        "mps2_an386"
+    } else if has_qemu_virt {
+        "qemu_virt"
     } else {
         panic!("No supported chip feature enabled")
     };
 
-    if has_ch32v307wcu6 || has_stm32f401re {
+    // there is no PAC for these chips, the device.x of the interrupt vectors comes from here
+    if has_microbit || has_mps2_an386 {
+        let crate_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        let device_x = crate_dir.join("src/memory_x").join(chip_core_name).join("device.x");
+        fs::copy(&device_x, out_dir.join("device.x")).unwrap();
+        println!("cargo:rustc-link-search={}", out_dir.display());
+        println!("cargo:rerun-if-changed={}", device_x.display());
+    }
+
+    if has_ch32v307wcu6 || stm32_chip.is_some() || has_microbit || has_mps2_an386 || has_qemu_virt {
         #[cfg(feature = "memory-x")]
         let crate_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
 
@@ -0,0 +1,90 @@
+//! Context switch for ARMv6-M (Cortex-M0/M0+)
+//!
+//! ARMv6-M has no `STMDB`/`LDMIA` with the high registers, no `ORR` with an immediate and no `LDREX`/`STREX`. The
+//! functions below build the same frame as the ARMv7-M port (`R4-R11, R14` below the hardware exception frame), so
+//! the chip's `UcStk` layout is unchanged, but only use Thumb-1 instructions: R8-R11 go through R4-R7.
+//!
+//! A chip with an ARMv6-M core implements `PlatformStatic` by delegating to these functions and `super::context`.
+
+use core::arch::asm;
+
+/// Save R4-R11 and R14 of the interrupted task below its exception frame and move the PSP down.
+///
+/// R4-R7 are used to move R8-R11 and are reloaded before returning.
+///
+/// # Safety
+/// Must be called first thing in the PendSV handler, while R4-R11 and LR still hold the task's values.
+#[inline(always)]
+pub unsafe fn save_task_context() {
+    unsafe {
+        asm!(
+            "CPSID   I",                 // Disable interrupts for atomic context save
+            "MRS     R0, PSP",           // Get current Process Stack Pointer
+            "SUBS    R0, #36",           // Room for R4-R11, R14
+            "STMIA   R0!, {{R4-R7}}",    // Save R4-R7
+            "MOV     R4, R8",
+            "MOV     R5, R9",
+            "MOV     R6, R10",
+            "MOV     R7, R11",
+            "STMIA   R0!, {{R4-R7}}",    // Save R8-R11
+            "MOV     R4, LR",
+            "STR     R4, [R0]",          // Save R14 (EXC_RETURN)
+            "SUBS    R0, #32",
+            "LDMIA   R0!, {{R4-R7}}",    // Reload R4-R7
+            "SUBS    R0, #16",
+            "MSR     PSP, R0",           // Write back updated PSP
+            out("r0") _,
+            options(nostack)
+        );
+    }
+}
+
+/// Restore R4-R11 from the task's stack, set the PSP and MSP, and return from the exception into the task.
+///
+/// # Safety
+/// `stack_pointer` must point to a context saved by [`save_task_context`] or built by `super::context::init_task_stack`. Must be
+/// called from the PendSV handler.
+#[inline(always)]
+pub unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize, return_value: u32) {
+    unsafe {
+        asm!(
+            "ADDS    R0, #16",
+            "LDMIA   R0!, {{R4-R7}}",    // Load the saved R8-R11
+            "MOV     R8, R4",
+            "MOV     R9, R5",
+            "MOV     R10, R6",
+            "MOV     R11, R7",
+            "ADDS    R0, #4",            // Skip the saved R14, EXC_RETURN is passed in R2
+            "MSR     PSP, R0",           // Set task's Process Stack Pointer
+            "SUBS    R0, #36",
+            "LDMIA   R0!, {{R4-R7}}",    // Load the saved R4-R7
+            "MSR     MSP, R1",           // Restore system Main Stack Pointer
+            "CPSIE   I",                 // Re-enable interrupts
+            "BX      R2",                // Branch to EXC_RETURN value to resume task
+            in("r0") stack_pointer,
+            in("r1") interrupt_stack,
+            in("r2") return_value,
+            options(noreturn),
+        );
+    }
+}
+
+/// Move the MSP to the interrupt stack and run Thread mode on the PSP.
+#[inline(never)]
+pub fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+    unsafe {
+        asm!(
+            "MSR     MSP, R1",           // Set MSP to interrupt stack pointer
+            "MRS     R0, CONTROL",       // Read current CONTROL register
+            "MOVS    R2, #2",
+            "ORRS    R0, R2",            // Set bit 1 to use PSP in thread mode
+            "MSR     CONTROL, R0",       // Write back modified CONTROL
+            "ISB",
+            "BX      LR",                // Return to caller
+            in("r1") interrupt_stack,
+            out("r0") _,
+            out("r2") _,
+            options(nostack),
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Context switch for ARMv7-M (Cortex-M3/M4/M7)
+//!
+//! With the `cortex-m4f` feature, a task switched out with an active FP context (EXC_RETURN bit 4 clear) also has
+//! S16-S31 saved below R4-R11, R14, and is resumed with its own EXC_RETURN so the hardware unstacks the extended frame.
+//!
+//! The context switch masks interrupts the same way as the kernel's critical sections (`super::critical_section`):
+//! PRIMASK if `OS_KERNEL_INTERRUPT_PRIO` is 0, otherwise BASEPRI, leaving the interrupts above it running.
+//!
+//! A chip with an ARMv7-M core implements `PlatformStatic` by delegating to these functions and `super::context`.
+
+use core::arch::asm;
+
+use embassy_preempt_cfg::OS_KERNEL_INTERRUPT_PRIO;
+
+/// Save R4-R11 and R14 (and S16-S31 if the task has an FP context) of the interrupted task below its exception frame
+/// and move the PSP down.
+///
+/// # Safety
+/// Must be called first thing in the PendSV handler, while R4-R11 and LR still hold the task's values.
+#[inline(always)]
+pub unsafe fn save_task_context() {
+    // Mask the kernel's interrupts for the atomic context save, through R0 only so R4-R11 are untouched
+    if OS_KERNEL_INTERRUPT_PRIO == 0 {
+        asm!("CPSID I", options(nomem, nostack, preserves_flags));
+    } else {
+        asm!(
+            "MSR     BASEPRI_MAX, R0",
+            in("r0") OS_KERNEL_INTERRUPT_PRIO as u32,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    #[cfg(feature = "cortex-m4f")]
+    asm!(
+        "MRS     R0, PSP",              // Get current Process Stack Pointer
+        "TST     R14, #0x10",           // EXC_RETURN bit 4 clear: the task has an FP context
+        "IT      EQ",
+        "VSTMDBEQ R0!, {{S16-S31}}",    // Save the callee-saved FP registers
+        "STMFD   R0!, {{R4-R11, R14}}", // Save callee-saved registers (R4-R11, LR) with full descending stack
+        "MSR     PSP, R0",              // Write back updated PSP
+        out("r0") _,
+        options(nostack)
+    );
+    #[cfg(not(feature = "cortex-m4f"))]
+    asm!(
+        "MRS     R0, PSP",              // Get current Process Stack Pointer
+        "STMFD   R0!, {{R4-R11, R14}}", // Save callee-saved registers (R4-R11, LR) with full descending stack
+        "MSR     PSP, R0",              // Write back updated PSP
+        out("r0") _,
+        options(nostack, preserves_flags)
+    );
+}
+
+/// Restore the task's registers, set the PSP and MSP, and return from the exception into the task.
+///
+/// With the `cortex-m4f` feature the task's own saved EXC_RETURN is used instead of `return_value`.
+///
+/// # Safety
+/// `stack_pointer` must point to a context saved by [`save_task_context`] or built by
+/// `super::context::init_task_stack`. Must be called from the PendSV handler.
+#[inline(always)]
+pub unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize, return_value: u32) {
+    #[cfg(feature = "cortex-m4f")]
+    asm!(
+        "LDMFD   R0!, {{R4-R11, R14}}", // Restore callee-saved registers and the task's EXC_RETURN
+        "TST     R14, #0x10",           // EXC_RETURN bit 4 clear: the task has an FP context
+        "IT      EQ",
+        "VLDMIAEQ R0!, {{S16-S31}}",    // Restore the callee-saved FP registers
+        "MSR     PSP, R0",              // Set task's Process Stack Pointer
+        "MSR     MSP, R1",              // Restore system Main Stack Pointer
+        "MSR     BASEPRI, R3",          // Unmask the interrupts below OS_KERNEL_INTERRUPT_PRIO
+        "CPSIE   I",                    // Re-enable interrupts
+        "BX      R14",                  // Branch to the task's EXC_RETURN value to resume it
+        in("r0") stack_pointer,
+        in("r1") interrupt_stack,
+        in("r2") return_value,          // unused, the EXC_RETURN comes from the task's context
+        in("r3") 0u32,
+        options(noreturn),
+    );
+    #[cfg(not(feature = "cortex-m4f"))]
+    asm!(
+        "LDMFD   R0!, {{R4-R11, R14}}", // Restore callee-saved registers from task stack
+        "MSR     PSP, R0",              // Set task's Process Stack Pointer
+        "MSR     MSP, R1",              // Restore system Main Stack Pointer
+        "MSR     BASEPRI, R3",          // Unmask the interrupts below OS_KERNEL_INTERRUPT_PRIO
+        "CPSIE   I",                    // Re-enable interrupts
+        "BX      R2",                   // Branch to EXC_RETURN value to resume task
+        in("r0") stack_pointer,
+        in("r1") interrupt_stack,
+        in("r2") return_value,
+        in("r3") 0u32,
+        options(noreturn),
+    );
+}
+
+/// Move the MSP to the interrupt stack and run Thread mode on the PSP.
+#[inline(never)]
+pub fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+    unsafe {
+        asm!(
+            "MSR     MSP, R1",              // Set MSP to interrupt stack pointer
+            "MRS     R0, CONTROL",          // Read current CONTROL register
+            "ORR     R0, R0, #2",           // Set bit 1 to use PSP in thread mode
+            "MSR     CONTROL, R0",          // Write back modified CONTROL
+            "ISB",
+            "BX      LR",                   // Return to caller
+            in("r1") interrupt_stack,
+            out("r0") _,
+            options(nostack, preserves_flags),
+        );
+    }
+}
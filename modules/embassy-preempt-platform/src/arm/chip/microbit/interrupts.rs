@@ -0,0 +1,112 @@
+//! nRF51 interrupts and device vector table
+//!
+//! There is no PAC for this chip, so the table for cortex-m-rt's `device` feature is defined here; the handlers
+//! default to `DefaultHandler` through `device.x`.
+
+use cortex_m::interrupt::InterruptNumber;
+
+/// nRF51 interrupt numbers
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Interrupt {
+    POWER_CLOCK = 0,
+    RADIO = 1,
+    UART0 = 2,
+    SPI0_TWI0 = 3,
+    SPI1_TWI1 = 4,
+    GPIOTE = 6,
+    ADC = 7,
+    TIMER0 = 8,
+    TIMER1 = 9,
+    TIMER2 = 10,
+    RTC0 = 11,
+    TEMP = 12,
+    RNG = 13,
+    ECB = 14,
+    CCM_AAR = 15,
+    WDT = 16,
+    RTC1 = 17,
+    QDEC = 18,
+    LPCOMP = 19,
+    SWI0 = 20,
+    SWI1 = 21,
+    SWI2 = 22,
+    SWI3 = 23,
+    SWI4 = 24,
+    SWI5 = 25,
+}
+
+unsafe impl InterruptNumber for Interrupt {
+    #[inline(always)]
+    fn number(self) -> u16 {
+        self as u16
+    }
+}
+
+#[allow(non_snake_case)]
+extern "C" {
+    fn POWER_CLOCK();
+    fn RADIO();
+    fn UART0();
+    fn SPI0_TWI0();
+    fn SPI1_TWI1();
+    fn GPIOTE();
+    fn ADC();
+    fn TIMER0();
+    fn TIMER1();
+    fn TIMER2();
+    fn RTC0();
+    fn TEMP();
+    fn RNG();
+    fn ECB();
+    fn CCM_AAR();
+    fn WDT();
+    fn RTC1();
+    fn QDEC();
+    fn LPCOMP();
+    fn SWI0();
+    fn SWI1();
+    fn SWI2();
+    fn SWI3();
+    fn SWI4();
+    fn SWI5();
+}
+
+#[doc(hidden)]
+pub union Vector {
+    handler: unsafe extern "C" fn(),
+    reserved: u32,
+}
+
+#[doc(hidden)]
+#[link_section = ".vector_table.interrupts"]
+#[no_mangle]
+pub static __INTERRUPTS: [Vector; 26] = [
+    Vector { handler: POWER_CLOCK },
+    Vector { handler: RADIO },
+    Vector { handler: UART0 },
+    Vector { handler: SPI0_TWI0 },
+    Vector { handler: SPI1_TWI1 },
+    Vector { reserved: 0 },
+    Vector { handler: GPIOTE },
+    Vector { handler: ADC },
+    Vector { handler: TIMER0 },
+    Vector { handler: TIMER1 },
+    Vector { handler: TIMER2 },
+    Vector { handler: RTC0 },
+    Vector { handler: TEMP },
+    Vector { handler: RNG },
+    Vector { handler: ECB },
+    Vector { handler: CCM_AAR },
+    Vector { handler: WDT },
+    Vector { handler: RTC1 },
+    Vector { handler: QDEC },
+    Vector { handler: LPCOMP },
+    Vector { handler: SWI0 },
+    Vector { handler: SWI1 },
+    Vector { handler: SWI2 },
+    Vector { handler: SWI3 },
+    Vector { handler: SWI4 },
+    Vector { handler: SWI5 },
+];
@@ -0,0 +1,9 @@
+//! BBC micro:bit (nRF51822, Cortex-M0), also emulated by `qemu-system-arm -M microbit`
+
+pub mod interrupts;
+mod platform;
+pub mod timer_driver;
+mod ucstk;
+
+pub use platform::PlatformImpl;
+pub use ucstk::UcStk;
@@ -0,0 +1,160 @@
+use core::ptr::NonNull;
+
+use cortex_m::peripheral::scb::SystemHandler;
+
+use super::interrupts::Interrupt;
+#[cfg(not(feature = "time_driver_systick"))]
+use super::timer_driver::Nrf51Timer;
+use crate::arm::{armv6m, context};
+#[cfg(feature = "time_driver_systick")]
+use crate::arm::systick::SysTickDriver;
+use crate::traits::memory_layout::PlatformMemoryLayout;
+use crate::traits::platform::PlatformStatic;
+use crate::Platform;
+
+/// micro:bit platform implementation
+///
+/// ## Hardware Configuration
+///
+/// - Core: Cortex-M0 (ARMv6-M), context switching in PendSV through `crate::arm::armv6m`
+/// - Timer: TIMER0 in 32-bit mode, or SysTick with `time_driver_systick`
+/// - Shutdown: semihosting exit (with the `semihosting` feature), so `qemu-system-arm -M microbit -semihosting`
+///   terminates at the end of a test
+pub struct PlatformImpl {
+    /// TIMER0 driver providing timing and alarm services
+    #[cfg(not(feature = "time_driver_systick"))]
+    pub timer: Nrf51Timer,
+
+    /// SysTick driver providing timing and alarm services
+    #[cfg(feature = "time_driver_systick")]
+    pub timer: SysTickDriver,
+}
+
+/// the core clock, the 16 MHz HFCLK
+#[cfg(feature = "time_driver_systick")]
+const CORE_HZ: u64 = 16_000_000;
+
+impl PlatformImpl {
+    /// Create and initialize a new micro:bit platform instance
+    ///
+    /// # Panics
+    /// Will panic if the core peripherals are already taken
+    pub(crate) fn new() -> Self {
+        os_log!(info, "Init Platform");
+        let mut cp = cortex_m::Peripherals::take().unwrap();
+
+        // nRF51 implements 2 priority bits: PendSV lowest, the time base above it
+        unsafe {
+            cp.SCB.set_priority(SystemHandler::PendSV, 0xc0);
+            cp.NVIC.set_priority(Interrupt::TIMER0, 0x40);
+            cp.SCB.set_priority(SystemHandler::SysTick, 0x40);
+        }
+
+        #[cfg(not(feature = "time_driver_systick"))]
+        let timer = {
+            let timer = Nrf51Timer::new();
+            timer.init();
+            timer
+        };
+        #[cfg(feature = "time_driver_systick")]
+        let timer = {
+            let timer = SysTickDriver::new();
+            timer.init(CORE_HZ);
+            timer
+        };
+
+        PlatformImpl { timer }
+    }
+}
+
+impl PlatformStatic for PlatformImpl {
+    fn trigger_context_switch() {
+        context::trigger_context_switch();
+    }
+
+    #[inline(always)]
+    unsafe fn save_task_context() {
+        armv6m::save_task_context();
+    }
+
+    #[inline(always)]
+    unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize, return_value: u32) {
+        armv6m::restore_task_context(stack_pointer, interrupt_stack, return_value);
+    }
+
+    fn set_program_stack_pointer(sp: *mut u8) {
+        context::set_program_stack_pointer(sp);
+    }
+
+    fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+        armv6m::configure_interrupt_stack(interrupt_stack);
+    }
+
+    fn init_task_stack(stk_ref: NonNull<usize>, executor_function: fn()) -> NonNull<usize> {
+        context::init_task_stack(stk_ref, executor_function)
+    }
+
+    fn enter_idle_state() {
+        cortex_m::asm::wfi();
+    }
+
+    fn shutdown() {
+        #[cfg(feature = "semihosting")]
+        {
+            // Use semihosting to exit cleanly for defmt-test and QEMU
+            use cortex_m_semihosting::debug;
+            loop {
+                debug::exit(debug::EXIT_SUCCESS);
+            }
+        }
+
+        #[cfg(not(feature = "semihosting"))]
+        {
+            os_log!(info, "Shutdown, please press Ctrl+C to stop the program");
+            loop {
+                cortex_m::asm::wfi();
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn get_current_stack_pointer() -> *mut usize {
+        context::get_current_stack_pointer()
+    }
+}
+
+/// The kernel stacks and heap take the top 6K of the 16K RAM, below the 1K reset stack; `.data`/`.bss` (and the task
+/// arena, so set `kernel.arena_size` to about 4096) live in the first 10K, see `memory.x`.
+impl PlatformMemoryLayout for PlatformImpl {
+    fn get_stack_start() -> usize {
+        0x2000_2800
+    }
+
+    fn get_max_programs() -> usize {
+        3
+    }
+
+    fn get_heap_size() -> usize {
+        1024 // 1 KiB
+    }
+
+    fn get_program_stack_size() -> usize {
+        1024 // 1 KiB
+    }
+
+    fn get_interrupt_stack_size() -> usize {
+        1024 // 1 KiB
+    }
+}
+
+impl Platform for PlatformImpl {
+    fn get_timer_driver(&'static self) -> &'static dyn crate::traits::timer::Driver {
+        &self.timer
+    }
+
+    /// Sleep mode: the time base keeps counting and its alarm wakes the core
+    fn sleep_until(&'static self, _wake_at: u64) -> bool {
+        crate::arm::low_power::wait_for_interrupt();
+        true
+    }
+}
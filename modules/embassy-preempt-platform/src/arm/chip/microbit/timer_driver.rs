@@ -0,0 +1,297 @@
+//! nRF51 Timer Driver Implementation
+//!
+//! The time base is TIMER0 in 32-bit mode, clocked from the 16 MHz HFCLK divided by a power of two, so `TICK_HZ`
+//! must be 16 MHz / 2^n with n in 0..=9 (e.g. `tick-hz-1_000_000`).
+//!
+//! The counter is extended to 64 bits like the STM32 `RtcDriver`, with a period of 2^31 ticks: CC[3] alternates
+//! between the half-overflow (0x8000_0000) and the overflow (0) point and counts the periods. The counter can only be
+//! read by capturing it, which takes CC[2]; CC[0] and CC[1] are the alarms.
+
+use core::mem;
+use core::sync::atomic::{compiler_fence, AtomicU32, AtomicU8, Ordering};
+
+use cortex_m::peripheral::NVIC;
+use critical_section::{CriticalSection, Mutex};
+use embassy_preempt_cfg::TICK_HZ;
+use embassy_preempt_log::{os_log, timer_log};
+
+use super::interrupts::Interrupt;
+use crate::traits::timer::{AlarmHandle, AlarmState, Driver};
+
+/// TIMER0 base address
+const TIMER0_BASE: usize = 0x4000_8000;
+
+const TASKS_START: usize = 0x000;
+const TASKS_STOP: usize = 0x004;
+const TASKS_CLEAR: usize = 0x00C;
+const TASKS_CAPTURE: usize = 0x040;
+const EVENTS_COMPARE: usize = 0x140;
+const INTENSET: usize = 0x304;
+const INTENCLR: usize = 0x308;
+const MODE: usize = 0x504;
+const BITMODE: usize = 0x508;
+const PRESCALER: usize = 0x510;
+const CC: usize = 0x540;
+
+/// BITMODE: 32 bit counter
+const BITMODE_32BIT: u32 = 3;
+/// the HFCLK frequency feeding the prescaler
+const HFCLK_HZ: u64 = 16_000_000;
+/// the prescaler giving `TICK_HZ`
+const PRESCALER_VAL: u32 = {
+    let div = HFCLK_HZ / TICK_HZ;
+    assert!(
+        HFCLK_HZ % TICK_HZ == 0 && div.is_power_of_two() && div <= 512,
+        "the nRF51 timer runs at 16 MHz / 2^n, n in 0..=9: choose another tick-hz-* feature"
+    );
+    div.trailing_zeros()
+};
+
+/// the channels of the alarms
+const ALARM_COUNT: usize = 2;
+/// the channel used to read the counter
+const CC_CAPTURE: usize = 2;
+/// the channel counting the periods
+const CC_PERIOD: usize = 3;
+
+#[inline(always)]
+fn reg(offset: usize) -> *mut u32 {
+    (TIMER0_BASE + offset) as *mut u32
+}
+
+#[inline(always)]
+fn read(offset: usize) -> u32 {
+    unsafe { reg(offset).read_volatile() }
+}
+
+#[inline(always)]
+fn write(offset: usize, value: u32) {
+    unsafe { reg(offset).write_volatile(value) }
+}
+
+/// INTEN bit of EVENTS_COMPARE[n]
+#[inline(always)]
+fn compare_int(n: usize) -> u32 {
+    1 << (16 + n)
+}
+
+/// Enable or disable the interrupt of EVENTS_COMPARE[n]
+fn set_compare_int(n: usize, enable: bool) {
+    write(if enable { INTENSET } else { INTENCLR }, compare_int(n));
+}
+
+/// TIMER0 interrupt handler
+#[unsafe(no_mangle)]
+pub extern "C" fn TIMER0() {
+    use crate::get_platform_trait;
+
+    os_log!(trace, "TIMER0 interrupt handler invoked");
+    unsafe {
+        get_platform_trait().get_timer_driver().on_interrupt();
+    }
+}
+
+/// nRF51 Timer Driver
+pub struct Nrf51Timer {
+    /// Number of 2^31 tick periods elapsed since system boot
+    period: AtomicU32,
+
+    /// Counter for tracking allocated alarm instances
+    alarm_count: AtomicU8,
+
+    /// Array of alarm states storing callbacks, contexts, and trigger timestamps
+    /// u64::MAX indicates no alarm is scheduled for that slot
+    alarms: Mutex<[AlarmState; ALARM_COUNT]>,
+}
+
+impl Nrf51Timer {
+    /// Create a new timer driver instance
+    pub(crate) fn new() -> Self {
+        const ALARM_STATE_NEW: AlarmState = AlarmState::new();
+        Nrf51Timer {
+            period: AtomicU32::new(0),
+            alarm_count: AtomicU8::new(0),
+            alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
+        }
+    }
+
+    /// Start TIMER0 from zero and enable its interrupt
+    pub fn init(&self) {
+        os_log!(trace, "Initializing Nrf51Timer");
+
+        write(TASKS_STOP, 1);
+        write(TASKS_CLEAR, 1);
+        // timer mode
+        write(MODE, 0);
+        write(BITMODE, BITMODE_32BIT);
+        write(PRESCALER, PRESCALER_VAL);
+
+        write(INTENCLR, u32::MAX);
+        for n in 0..4 {
+            write(EVENTS_COMPARE + 4 * n, 0);
+        }
+        // the first half-overflow
+        write(CC + 4 * CC_PERIOD, 0x8000_0000);
+        set_compare_int(CC_PERIOD, true);
+
+        NVIC::unpend(Interrupt::TIMER0);
+        unsafe {
+            compiler_fence(Ordering::SeqCst);
+            NVIC::unmask(Interrupt::TIMER0);
+        }
+
+        write(TASKS_START, 1);
+    }
+
+    fn counter(&self) -> u32 {
+        write(TASKS_CAPTURE + 4 * CC_CAPTURE, 1);
+        read(CC + 4 * CC_CAPTURE)
+    }
+
+    fn next_period(&self) {
+        // We only modify the period from the timer interrupt, so we know this can't race.
+        let period = self.period.load(Ordering::Relaxed) + 1;
+        self.period.store(period, Ordering::Relaxed);
+        let t = (period as u64) << 31;
+
+        // an odd period starts at the half-overflow, so it ends at the overflow
+        write(CC + 4 * CC_PERIOD, if period & 1 == 1 { 0 } else { 0x8000_0000 });
+
+        critical_section::with(move |cs| {
+            for n in 0..ALARM_COUNT {
+                let alarm = &self.alarms.borrow(cs)[n];
+                let at = alarm.timestamp.get();
+
+                if at < t + 0xc000_0000 {
+                    // just enable it. `set_alarm` has already set the correct CC val.
+                    set_compare_int(n, true);
+                }
+            }
+        })
+    }
+
+    fn get_alarm<'a>(&'a self, cs: CriticalSection<'a>, alarm: AlarmHandle) -> &'a AlarmState {
+        // safety: we're allowed to assume the AlarmState is created by us, and
+        // we never create one that's out of bounds.
+        unsafe { self.alarms.borrow(cs).get_unchecked(alarm.id() as usize) }
+    }
+
+    fn trigger_alarm(&self, n: usize, cs: CriticalSection) {
+        timer_log!(trace, "trigger_alarm");
+        let alarm = &self.alarms.borrow(cs)[n];
+        alarm.timestamp.set(u64::MAX);
+
+        // Call after clearing alarm, so the callback can set another alarm.
+
+        // safety:
+        // - we can ignore the possibility of `f` being unset (null) because of the safety contract of `allocate_alarm`.
+        // - other than that we only store valid function pointers into alarm.callback
+        let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
+        f(alarm.ctx.get());
+    }
+}
+
+impl Driver for Nrf51Timer {
+    fn now(&self) -> u64 {
+        let period = self.period.load(Ordering::Relaxed);
+        compiler_fence(Ordering::Acquire);
+        let counter = self.counter();
+        calc_now(period, counter)
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        critical_section::with(|_| {
+            let id = self.alarm_count.load(Ordering::Relaxed);
+            if id < ALARM_COUNT as u8 {
+                self.alarm_count.store(id + 1, Ordering::Relaxed);
+                Some(AlarmHandle::new(id))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+
+            alarm.callback.set(callback as *const ());
+            alarm.ctx.set(ctx);
+        })
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        timer_log!(trace, "set the alarm at {}", timestamp);
+        let n = alarm.id() as usize;
+        if timestamp == u64::MAX {
+            // no alarm needed
+            critical_section::with(|cs| {
+                self.get_alarm(cs, alarm).timestamp.set(u64::MAX);
+                set_compare_int(n, false);
+            });
+            return true;
+        }
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+            alarm.timestamp.set(timestamp);
+
+            let t = self.now();
+            if timestamp <= t {
+                // If alarm timestamp has passed the alarm will not fire.
+                // Disarm the alarm and return `false` to indicate that.
+                set_compare_int(n, false);
+                alarm.timestamp.set(u64::MAX);
+                return false;
+            }
+
+            // Write the CC value regardless of whether we're going to enable it now or not.
+            // This way, when we enable it later, the right value is already set.
+            write(EVENTS_COMPARE + 4 * n, 0);
+            write(CC + 4 * n, timestamp as u32);
+
+            // Enable it if it'll happen soon. Otherwise, `next_period` will enable it.
+            let diff = timestamp - t;
+            set_compare_int(n, diff < 0xc000_0000);
+
+            // Reevaluate if the alarm timestamp is still in the future
+            let t = self.now();
+            if timestamp <= t {
+                // If alarm timestamp has passed since we set it, we have a race condition and
+                // the alarm may or may not have fired.
+                // Disarm the alarm and return `false` to indicate that.
+                // It is the caller's responsibility to handle this ambiguity.
+                set_compare_int(n, false);
+                alarm.timestamp.set(u64::MAX);
+                return false;
+            }
+            // We're confident the alarm will ring in the future.
+            true
+        })
+    }
+
+    unsafe fn on_interrupt(&self) {
+        critical_section::with(|cs| {
+            let inten = read(INTENSET);
+
+            if read(EVENTS_COMPARE + 4 * CC_PERIOD) != 0 {
+                write(EVENTS_COMPARE + 4 * CC_PERIOD, 0);
+                self.next_period();
+            }
+
+            for n in 0..ALARM_COUNT {
+                if read(EVENTS_COMPARE + 4 * n) != 0 {
+                    write(EVENTS_COMPARE + 4 * n, 0);
+                    if inten & compare_int(n) != 0 {
+                        timer_log!(trace, "the alarm is triggered!!!");
+                        set_compare_int(n, false);
+                        self.trigger_alarm(n, cs);
+                    }
+                }
+            }
+        })
+    }
+}
+
+fn calc_now(period: u32, counter: u32) -> u64 {
+    ((period as u64) << 31) + ((counter ^ ((period & 1) << 31)) as u64)
+}
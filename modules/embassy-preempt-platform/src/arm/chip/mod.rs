@@ -1 +1,6 @@
-pub mod stm32f401re;
\ No newline at end of file
+#[cfg(feature = "microbit")]
+pub mod microbit;
+#[cfg(feature = "mps2-an386")]
+pub mod mps2_an386;
+#[cfg(feature = "stm32")]
+pub mod stm32;
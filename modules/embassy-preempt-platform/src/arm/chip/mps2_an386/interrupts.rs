@@ -0,0 +1,83 @@
+//! MPS2 AN386 interrupts and device vector table
+//!
+//! There is no PAC for this board, so the table for cortex-m-rt's `device` feature is defined here; the handlers
+//! default to `DefaultHandler` through `device.x`.
+
+use cortex_m::interrupt::InterruptNumber;
+
+/// AN386 interrupt numbers
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Interrupt {
+    UARTRX0 = 0,
+    UARTTX0 = 1,
+    UARTRX1 = 2,
+    UARTTX1 = 3,
+    UARTRX2 = 4,
+    UARTTX2 = 5,
+    PORT0_ALL = 6,
+    PORT1_ALL = 7,
+    TIMER0 = 8,
+    TIMER1 = 9,
+    DUALTIMER = 10,
+    SPI = 11,
+    UART_OVF = 12,
+    ETHERNET = 13,
+    I2S = 14,
+    TSC = 15,
+}
+
+unsafe impl InterruptNumber for Interrupt {
+    #[inline(always)]
+    fn number(self) -> u16 {
+        self as u16
+    }
+}
+
+#[allow(non_snake_case)]
+extern "C" {
+    fn UARTRX0();
+    fn UARTTX0();
+    fn UARTRX1();
+    fn UARTTX1();
+    fn UARTRX2();
+    fn UARTTX2();
+    fn PORT0_ALL();
+    fn PORT1_ALL();
+    fn TIMER0();
+    fn TIMER1();
+    fn DUALTIMER();
+    fn SPI();
+    fn UART_OVF();
+    fn ETHERNET();
+    fn I2S();
+    fn TSC();
+}
+
+#[doc(hidden)]
+pub union Vector {
+    handler: unsafe extern "C" fn(),
+}
+
+#[doc(hidden)]
+#[link_section = ".vector_table.interrupts"]
+#[no_mangle]
+pub static __INTERRUPTS: [Vector; 16] = [
+    Vector { handler: UARTRX0 },
+    Vector { handler: UARTTX0 },
+    Vector { handler: UARTRX1 },
+    Vector { handler: UARTTX1 },
+    Vector { handler: UARTRX2 },
+    Vector { handler: UARTTX2 },
+    Vector { handler: PORT0_ALL },
+    Vector { handler: PORT1_ALL },
+    Vector { handler: TIMER0 },
+    Vector { handler: TIMER1 },
+    Vector { handler: DUALTIMER },
+    Vector { handler: SPI },
+    Vector { handler: UART_OVF },
+    Vector { handler: ETHERNET },
+    Vector { handler: I2S },
+    Vector { handler: TSC },
+];
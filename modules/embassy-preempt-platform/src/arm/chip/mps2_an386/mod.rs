@@ -0,0 +1,9 @@
+//! Arm MPS2 with the AN386 image (Cortex-M4), emulated by `qemu-system-arm -M mps2-an386`
+
+pub mod interrupts;
+mod platform;
+pub mod timer_driver;
+mod ucstk;
+
+pub use platform::PlatformImpl;
+pub use ucstk::UcStk;
@@ -0,0 +1,169 @@
+use core::ptr::NonNull;
+
+use cortex_m::peripheral::scb::SystemHandler;
+
+use super::interrupts::Interrupt;
+#[cfg(not(feature = "time_driver_systick"))]
+use super::timer_driver::CmsdkTimerDriver;
+use crate::arm::{armv7m, context};
+#[cfg(feature = "time_driver_systick")]
+use crate::arm::systick::SysTickDriver;
+use crate::traits::memory_layout::PlatformMemoryLayout;
+use crate::traits::platform::PlatformStatic;
+use crate::Platform;
+
+/// The timers run at priority 0x20 and call the kernel, so they must not be above the kernel's masking level
+const _: () = assert!(
+    embassy_preempt_cfg::OS_KERNEL_INTERRUPT_PRIO <= 0x20,
+    "kernel.kernel_interrupt_prio must not be below the timer priority (0x20)"
+);
+
+/// MPS2 AN386 platform implementation
+///
+/// ## Hardware Configuration
+///
+/// - Core: Cortex-M4 (ARMv7-M), context switching in PendSV through `crate::arm::armv7m`
+/// - Timer: CMSDK APB TIMER0 (time base) and TIMER1 (alarm), or SysTick with `time_driver_systick`
+/// - Shutdown and logs: semihosting, so `qemu-system-arm -M mps2-an386 -semihosting` runs the tests headless
+pub struct PlatformImpl {
+    /// CMSDK timer driver providing timing and alarm services
+    #[cfg(not(feature = "time_driver_systick"))]
+    pub timer: CmsdkTimerDriver,
+
+    /// SysTick driver providing timing and alarm services
+    #[cfg(feature = "time_driver_systick")]
+    pub timer: SysTickDriver,
+}
+
+/// the core clock
+#[cfg(feature = "time_driver_systick")]
+const CORE_HZ: u64 = 25_000_000;
+
+impl PlatformImpl {
+    /// Create and initialize a new MPS2 AN386 platform instance
+    ///
+    /// # Panics
+    /// Will panic if the core peripherals are already taken
+    pub(crate) fn new() -> Self {
+        os_log!(info, "Init Platform");
+        let mut cp = cortex_m::Peripherals::take().unwrap();
+
+        // 3 priority bits: PendSV lowest, the timers above it (at or below OS_KERNEL_INTERRUPT_PRIO, they call the kernel)
+        unsafe {
+            cp.SCB.set_priority(SystemHandler::PendSV, 0xe0);
+            cp.NVIC.set_priority(Interrupt::TIMER0, 0x20);
+            cp.NVIC.set_priority(Interrupt::TIMER1, 0x20);
+            cp.SCB.set_priority(SystemHandler::SysTick, 0x20);
+        }
+
+        // Let the hardware stack the FP context of preempted tasks
+        #[cfg(feature = "cortex-m4f")]
+        crate::arm::fpu::enable_lazy_stacking();
+
+        #[cfg(not(feature = "time_driver_systick"))]
+        let timer = {
+            let timer = CmsdkTimerDriver::new();
+            timer.init();
+            timer
+        };
+        #[cfg(feature = "time_driver_systick")]
+        let timer = {
+            let timer = SysTickDriver::new();
+            timer.init(CORE_HZ);
+            timer
+        };
+
+        PlatformImpl { timer }
+    }
+}
+
+impl PlatformStatic for PlatformImpl {
+    fn trigger_context_switch() {
+        context::trigger_context_switch();
+    }
+
+    #[inline(always)]
+    unsafe fn save_task_context() {
+        armv7m::save_task_context();
+    }
+
+    #[inline(always)]
+    unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize, return_value: u32) {
+        armv7m::restore_task_context(stack_pointer, interrupt_stack, return_value);
+    }
+
+    fn set_program_stack_pointer(sp: *mut u8) {
+        context::set_program_stack_pointer(sp);
+    }
+
+    fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+        armv7m::configure_interrupt_stack(interrupt_stack);
+    }
+
+    fn init_task_stack(stk_ref: NonNull<usize>, executor_function: fn()) -> NonNull<usize> {
+        context::init_task_stack(stk_ref, executor_function)
+    }
+
+    fn enter_idle_state() {
+        cortex_m::asm::wfi();
+    }
+
+    fn shutdown() {
+        #[cfg(feature = "semihosting")]
+        {
+            // Use semihosting to exit QEMU cleanly for defmt-test
+            use cortex_m_semihosting::debug;
+            loop {
+                debug::exit(debug::EXIT_SUCCESS);
+            }
+        }
+
+        #[cfg(not(feature = "semihosting"))]
+        {
+            os_log!(info, "Shutdown, please press Ctrl+C to stop the program");
+            loop {
+                cortex_m::asm::wfi();
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn get_current_stack_pointer() -> *mut usize {
+        context::get_current_stack_pointer()
+    }
+}
+
+/// The kernel stacks and heap start 1M into SSRAM2, above `.data`/`.bss` and the reset stack, see `memory.x`.
+impl PlatformMemoryLayout for PlatformImpl {
+    fn get_stack_start() -> usize {
+        0x2010_0000
+    }
+
+    fn get_max_programs() -> usize {
+        10
+    }
+
+    fn get_heap_size() -> usize {
+        64 * 1024 // 64 KiB
+    }
+
+    fn get_program_stack_size() -> usize {
+        4096 // 4 KiB
+    }
+
+    fn get_interrupt_stack_size() -> usize {
+        4096 // 4 KiB
+    }
+}
+
+impl Platform for PlatformImpl {
+    fn get_timer_driver(&'static self) -> &'static dyn crate::traits::timer::Driver {
+        &self.timer
+    }
+
+    /// Sleep mode: the time base keeps counting and its alarm wakes the core
+    fn sleep_until(&'static self, _wake_at: u64) -> bool {
+        crate::arm::low_power::wait_for_interrupt();
+        true
+    }
+}
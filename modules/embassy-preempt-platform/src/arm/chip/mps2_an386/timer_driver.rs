@@ -0,0 +1,262 @@
+//! CMSDK APB Timer Driver Implementation
+//!
+//! The CMSDK timers are 32-bit down counters on the 25 MHz peripheral clock, without prescaler or compare channel:
+//! - TIMER0 free-runs from `u32::MAX` and counts the wraps, which extends it to a 64-bit cycle counter. The time in
+//!   ticks is the cycle count divided by `PCLK_HZ / TICK_HZ`, so `TICK_HZ` must divide 25 MHz.
+//! - TIMER1 is the (single) alarm: it is loaded with the cycles left until the alarm and stopped when it fires. An
+//!   alarm further away than one TIMER1 period is re-armed until it is due.
+
+use core::mem;
+use core::sync::atomic::{compiler_fence, AtomicU32, AtomicU8, Ordering};
+
+use cortex_m::peripheral::NVIC;
+use critical_section::{CriticalSection, Mutex};
+use embassy_preempt_cfg::TICK_HZ;
+use embassy_preempt_log::{os_log, timer_log};
+
+use super::interrupts::Interrupt;
+use crate::traits::timer::{AlarmHandle, AlarmState, Driver};
+
+/// CMSDK APB timer register block
+struct CmsdkTimer(usize);
+
+const TIMER0: CmsdkTimer = CmsdkTimer(0x4000_0000);
+const TIMER1: CmsdkTimer = CmsdkTimer(0x4000_1000);
+
+const CTRL: usize = 0x00;
+const VALUE: usize = 0x04;
+const RELOAD: usize = 0x08;
+const INTSTATUS: usize = 0x0C;
+
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_IRQ_ENABLE: u32 = 1 << 3;
+
+/// the peripheral clock of the timers
+pub const PCLK_HZ: u64 = 25_000_000;
+/// timer cycles per tick
+const CYCLES_PER_TICK: u64 = {
+    assert!(PCLK_HZ % TICK_HZ == 0, "TICK_HZ must divide the 25 MHz timer clock: choose another tick-hz-* feature");
+    PCLK_HZ / TICK_HZ
+};
+
+/// the number of alarms, TIMER1 is the only one
+const ALARM_COUNT: usize = 1;
+
+impl CmsdkTimer {
+    #[inline(always)]
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.0 + offset) as *const u32).read_volatile() }
+    }
+
+    #[inline(always)]
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.0 + offset) as *mut u32).write_volatile(value) }
+    }
+
+    /// Whether the counter reached zero since the flag was last cleared
+    #[inline(always)]
+    fn pending(&self) -> bool {
+        self.read(INTSTATUS) & 1 != 0
+    }
+
+    #[inline(always)]
+    fn clear(&self) {
+        self.write(INTSTATUS, 1);
+    }
+}
+
+/// TIMER0 interrupt handler, the counter wrapped
+#[unsafe(no_mangle)]
+pub extern "C" fn TIMER0() {
+    use crate::get_platform_trait;
+
+    os_log!(trace, "TIMER0 interrupt handler invoked");
+    unsafe {
+        get_platform_trait().get_timer_driver().on_interrupt();
+    }
+}
+
+/// TIMER1 interrupt handler, the alarm timer expired
+#[unsafe(no_mangle)]
+pub extern "C" fn TIMER1() {
+    use crate::get_platform_trait;
+
+    os_log!(trace, "TIMER1 interrupt handler invoked");
+    unsafe {
+        get_platform_trait().get_timer_driver().on_interrupt();
+    }
+}
+
+/// CMSDK Timer Driver
+pub struct CmsdkTimerDriver {
+    /// Number of TIMER0 wraps (2^32 cycles) since system boot
+    period: AtomicU32,
+
+    /// Counter for tracking allocated alarm instances
+    alarm_count: AtomicU8,
+
+    /// Array of alarm states storing callbacks, contexts, and trigger timestamps
+    /// u64::MAX indicates no alarm is scheduled for that slot
+    alarms: Mutex<[AlarmState; ALARM_COUNT]>,
+}
+
+impl CmsdkTimerDriver {
+    /// Create a new timer driver instance
+    pub(crate) fn new() -> Self {
+        const ALARM_STATE_NEW: AlarmState = AlarmState::new();
+        CmsdkTimerDriver {
+            period: AtomicU32::new(0),
+            alarm_count: AtomicU8::new(0),
+            alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
+        }
+    }
+
+    /// Start TIMER0 and enable the interrupts of both timers
+    pub fn init(&self) {
+        os_log!(trace, "Initializing CmsdkTimerDriver");
+
+        TIMER1.write(CTRL, 0);
+        TIMER1.clear();
+
+        TIMER0.write(CTRL, 0);
+        TIMER0.clear();
+        // writing RELOAD also loads the counter
+        TIMER0.write(RELOAD, u32::MAX);
+
+        for irq in [Interrupt::TIMER0, Interrupt::TIMER1] {
+            NVIC::unpend(irq);
+            unsafe {
+                compiler_fence(Ordering::SeqCst);
+                NVIC::unmask(irq);
+            }
+        }
+
+        TIMER0.write(CTRL, CTRL_ENABLE | CTRL_IRQ_ENABLE);
+    }
+
+    /// The 64-bit timer cycle count
+    fn cycles(&self) -> u64 {
+        critical_section::with(|_| {
+            let mut period = self.period.load(Ordering::Relaxed);
+            compiler_fence(Ordering::Acquire);
+            let mut value = TIMER0.read(VALUE);
+            // the wrap interrupt has not been handled yet, read the counter again after the wrap
+            if TIMER0.pending() {
+                period += 1;
+                value = TIMER0.read(VALUE);
+            }
+            ((period as u64) << 32) + (u32::MAX - value) as u64
+        })
+    }
+
+    /// Load TIMER1 with the cycles left until `timestamp`, at most one TIMER1 period
+    fn arm(&self, timestamp: u64, now: u64) {
+        let cycles = (timestamp - now).saturating_mul(CYCLES_PER_TICK).min(u32::MAX as u64) as u32;
+        TIMER1.write(CTRL, 0);
+        TIMER1.clear();
+        TIMER1.write(RELOAD, cycles.max(1));
+        TIMER1.write(CTRL, CTRL_ENABLE | CTRL_IRQ_ENABLE);
+    }
+
+    fn disarm(&self) {
+        TIMER1.write(CTRL, 0);
+        TIMER1.clear();
+    }
+
+    fn get_alarm<'a>(&'a self, cs: CriticalSection<'a>, alarm: AlarmHandle) -> &'a AlarmState {
+        // safety: we're allowed to assume the AlarmState is created by us, and
+        // we never create one that's out of bounds.
+        unsafe { self.alarms.borrow(cs).get_unchecked(alarm.id() as usize) }
+    }
+
+    fn trigger_alarm(&self, n: usize, cs: CriticalSection) {
+        timer_log!(trace, "trigger_alarm");
+        let alarm = &self.alarms.borrow(cs)[n];
+        alarm.timestamp.set(u64::MAX);
+
+        // Call after clearing alarm, so the callback can set another alarm.
+
+        // safety:
+        // - we can ignore the possibility of `f` being unset (null) because of the safety contract of `allocate_alarm`.
+        // - other than that we only store valid function pointers into alarm.callback
+        let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
+        f(alarm.ctx.get());
+    }
+}
+
+impl Driver for CmsdkTimerDriver {
+    fn now(&self) -> u64 {
+        self.cycles() / CYCLES_PER_TICK
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        critical_section::with(|_| {
+            let id = self.alarm_count.load(Ordering::Relaxed);
+            if id < ALARM_COUNT as u8 {
+                self.alarm_count.store(id + 1, Ordering::Relaxed);
+                Some(AlarmHandle::new(id))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+
+            alarm.callback.set(callback as *const ());
+            alarm.ctx.set(ctx);
+        })
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        timer_log!(trace, "set the alarm at {}", timestamp);
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+            if timestamp == u64::MAX {
+                // no alarm needed
+                alarm.timestamp.set(u64::MAX);
+                self.disarm();
+                return true;
+            }
+
+            let t = self.now();
+            if timestamp <= t {
+                // If alarm timestamp has passed the alarm will not fire.
+                // Disarm the alarm and return `false` to indicate that.
+                alarm.timestamp.set(u64::MAX);
+                self.disarm();
+                return false;
+            }
+            alarm.timestamp.set(timestamp);
+            self.arm(timestamp, t);
+            true
+        })
+    }
+
+    unsafe fn on_interrupt(&self) {
+        critical_section::with(|cs| {
+            if TIMER0.pending() {
+                TIMER0.clear();
+                // We only modify the period from the timer interrupt, so we know this can't race.
+                self.period.store(self.period.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+            }
+
+            if TIMER1.pending() {
+                self.disarm();
+                let at = self.alarms.borrow(cs)[0].timestamp.get();
+                if at != u64::MAX {
+                    let t = self.now();
+                    if at <= t {
+                        timer_log!(trace, "the alarm is triggered!!!");
+                        self.trigger_alarm(0, cs);
+                    } else {
+                        // further away than one TIMER1 period
+                        self.arm(at, t);
+                    }
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,236 @@
+//! STOP mode for the tickless idle, with the `low-power` feature
+//!
+//! In STOP mode every clock but the LSE stops, the TIM time base included. The RTC runs on from the 32.768 kHz LSE
+//! (the X2 crystal of the Nucleo-64 boards): its wakeup timer ends the sleep in time, and its calendar measures the
+//! sleep, which is added back to the time base by [`RtcDriver::resume`].
+//!
+//! - wakeup timer: RTCCLK / 16, 2048 Hz, at most 32 s per sleep
+//! - calendar: PREDIV_A + 1 = 8 and PREDIV_S + 1 = 4096, the sub-seconds count at 4096 Hz
+//!
+//! The sleep is measured from one sub-second edge to another, so the time base does not drift by the 244 us
+//! resolution of the calendar, at the cost of up to 244 us of spinning on each side. The sleep is added to the time
+//! base in whole ticks, rounded down: the fraction of a tick left over is carried into the next sleep, so the time
+//! base does not lose up to a tick on every sleep either.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::NVIC;
+use embassy_preempt_cfg::TICK_HZ;
+use stm32_metapac::rcc::vals::Rtcsel;
+use stm32_metapac::rtc::vals::Wucksel;
+#[cfg(feature = "stm32g4")]
+use stm32_metapac::rtc::vals::Calrf;
+#[cfg(feature = "stm32f4")]
+use stm32_metapac::pwr::vals::Pdds;
+#[cfg(feature = "stm32g4")]
+use stm32_metapac::pwr::vals::Lpms;
+use stm32_metapac::{Interrupt, EXTI, PWR, RCC, RTC};
+
+use super::rcc::{self, Config};
+use super::timer_driver::RtcDriver;
+use crate::arm::low_power::wait_for_interrupt_deep;
+
+/// the LSE frequency
+const LSE_HZ: u64 = 32_768;
+/// the wakeup timer clock, RTCCLK / 16
+const WAKEUP_HZ: u64 = LSE_HZ / 16;
+/// the longest sleep of the 16-bit wakeup timer, in wakeup timer periods
+const MAX_WAKEUP_COUNT: u64 = 1 << 16;
+/// the sub-second frequency of the calendar
+const SUBSECOND_HZ: u64 = 4096;
+const PREDIV_S: u16 = SUBSECOND_HZ as u16 - 1;
+const PREDIV_A: u8 = (LSE_HZ / SUBSECOND_HZ) as u8 - 1;
+const SECONDS_PER_DAY: u64 = 86_400;
+/// the time to leave STOP mode and restart the HSE and the PLL, the wakeup timer fires this much earlier
+const WAKEUP_LATENCY_US: u64 = 1000;
+/// the fraction of a tick the last sleeps have left over, in 1/`SUBSECOND_HZ` ticks
+static CARRY: AtomicU32 = AtomicU32::new(0);
+/// the EXTI line of the RTC wakeup event
+#[cfg(feature = "stm32f4")]
+const WAKEUP_EXTI_LINE: usize = 22;
+#[cfg(feature = "stm32g4")]
+const WAKEUP_EXTI_LINE: usize = 20;
+
+/// Start the LSE and the RTC, and route the RTC wakeup event to its interrupt
+///
+/// # Panics
+/// Never returns if the board has no LSE crystal, the LSE does not become ready
+pub(crate) fn init() {
+    // the backup domain (LSE, RTC) is write protected
+    #[cfg(feature = "stm32f4")]
+    RCC.apb1enr().modify(|w| w.set_pwren(true));
+    #[cfg(feature = "stm32g4")]
+    RCC.apb1enr1().modify(|w| {
+        w.set_pwren(true);
+        w.set_rtcapben(true);
+    });
+    PWR.cr1().modify(|w| w.set_dbp(true));
+
+    RCC.bdcr().modify(|w| w.set_lseon(true));
+    while !RCC.bdcr().read().lserdy() {}
+    RCC.bdcr().modify(|w| {
+        w.set_rtcsel(Rtcsel::LSE);
+        w.set_rtcen(true);
+    });
+
+    // unlock the RTC registers
+    RTC.wpr().write(|w| w.set_key(0xCA));
+    RTC.wpr().write(|w| w.set_key(0x53));
+
+    // the prescalers can only be written in the initialization mode
+    set_init_mode(true);
+    RTC.prer().write(|w| {
+        w.set_prediv_s(PREDIV_S);
+        w.set_prediv_a(PREDIV_A);
+    });
+    set_init_mode(false);
+
+    // the wakeup clock can only be written while the wakeup timer is off
+    RTC.cr().modify(|w| w.set_wute(false));
+    while !wakeup_timer_writable() {}
+    RTC.cr().modify(|w| {
+        w.set_wucksel(Wucksel::DIV16);
+        w.set_wutie(true);
+    });
+
+    EXTI.rtsr(0).modify(|w| w.set_line(WAKEUP_EXTI_LINE, true));
+    EXTI.imr(0).modify(|w| w.set_line(WAKEUP_EXTI_LINE, true));
+
+    // the same as the time base
+    unsafe {
+        let mut nvic = cortex_m::Peripherals::steal().NVIC;
+        nvic.set_priority(Interrupt::RTC_WKUP, 32);
+        NVIC::unmask(Interrupt::RTC_WKUP);
+    }
+}
+
+/// Sleep in STOP mode until `wake_at`, or any interrupt, then restore the clocks from `config` and the time base
+///
+/// Must be called inside a kernel critical section. Returns `false` without sleeping if `wake_at` is too close for
+/// STOP mode.
+pub(crate) fn stop_until(timer: &RtcDriver, config: &Config, wake_at: u64) -> bool {
+    let latency = WAKEUP_LATENCY_US * TICK_HZ / 1_000_000;
+    let max_sleep = MAX_WAKEUP_COUNT * TICK_HZ / WAKEUP_HZ;
+    let sleep = wake_at.saturating_sub(timer.now()).saturating_sub(latency).min(max_sleep);
+    let count = sleep * WAKEUP_HZ / TICK_HZ;
+    if count == 0 {
+        return false;
+    }
+    start_wakeup_timer(count as u32);
+
+    let before = next_subsecond();
+    timer.pause();
+
+    #[cfg(feature = "stm32f4")]
+    PWR.cr1().modify(|w| {
+        w.set_pdds(Pdds::STOP_MODE);
+        w.set_lpds(true);
+    });
+    #[cfg(feature = "stm32g4")]
+    PWR.cr1().modify(|w| w.set_lpms(Lpms::STOP1));
+    wait_for_interrupt_deep();
+
+    rcc::restore(config);
+    // the calendar shadow registers are stale after STOP mode
+    resync_calendar();
+    let after = next_subsecond();
+    RTC.cr().modify(|w| w.set_wute(false));
+
+    let elapsed = (after + SECONDS_PER_DAY * SUBSECOND_HZ - before) % (SECONDS_PER_DAY * SUBSECOND_HZ);
+    timer.resume(elapsed_ticks(elapsed));
+    true
+}
+
+/// Turn `elapsed` sub-seconds into whole ticks, carrying the fraction of a tick left over into the next call
+fn elapsed_ticks(elapsed: u64) -> u64 {
+    // only called in a kernel critical section
+    let scaled = elapsed * TICK_HZ + CARRY.load(Ordering::Relaxed) as u64;
+    CARRY.store((scaled % SUBSECOND_HZ) as u32, Ordering::Relaxed);
+    scaled / SUBSECOND_HZ
+}
+
+/// Fire the wakeup interrupt `count` wakeup timer periods from now
+fn start_wakeup_timer(count: u32) {
+    RTC.cr().modify(|w| w.set_wute(false));
+    while !wakeup_timer_writable() {}
+    RTC.wutr().write(|w| w.set_wut((count - 1) as u16));
+    clear_wakeup_flag();
+    RTC.cr().modify(|w| w.set_wute(true));
+}
+
+/// Wait for the next sub-second of the calendar, and return the time of day in sub-seconds
+fn next_subsecond() -> u64 {
+    let start = time_of_day();
+    loop {
+        let now = time_of_day();
+        if now != start {
+            return now;
+        }
+    }
+}
+
+/// The time of day in sub-seconds
+fn time_of_day() -> u64 {
+    // reading SSR locks TR and DR until DR is read
+    let ss = RTC.ssr().read().ss() as u64;
+    let tr = RTC.tr().read();
+    let _ = RTC.dr().read();
+    let hours = tr.ht() as u64 * 10 + tr.hu() as u64;
+    let minutes = tr.mnt() as u64 * 10 + tr.mnu() as u64;
+    let seconds = tr.st() as u64 * 10 + tr.su() as u64;
+    (hours * 3600 + minutes * 60 + seconds) * SUBSECOND_HZ + (PREDIV_S as u64 - ss)
+}
+
+#[cfg(feature = "stm32f4")]
+fn set_init_mode(init: bool) {
+    RTC.isr().modify(|w| w.set_init(init));
+    while RTC.isr().read().initf() != init {}
+}
+
+#[cfg(feature = "stm32g4")]
+fn set_init_mode(init: bool) {
+    RTC.icsr().modify(|w| w.set_init(init));
+    while RTC.icsr().read().initf() != init {}
+}
+
+#[cfg(feature = "stm32f4")]
+fn wakeup_timer_writable() -> bool {
+    RTC.isr().read().wutwf()
+}
+
+#[cfg(feature = "stm32g4")]
+fn wakeup_timer_writable() -> bool {
+    RTC.icsr().read().wutwf()
+}
+
+#[cfg(feature = "stm32f4")]
+fn resync_calendar() {
+    RTC.isr().modify(|w| w.set_rsf(false));
+    while !RTC.isr().read().rsf() {}
+}
+
+#[cfg(feature = "stm32g4")]
+fn resync_calendar() {
+    RTC.icsr().modify(|w| w.set_rsf(false));
+    while !RTC.icsr().read().rsf() {}
+}
+
+#[cfg(feature = "stm32f4")]
+fn clear_wakeup_flag() {
+    RTC.isr().modify(|w| w.set_wutf(false));
+}
+
+#[cfg(feature = "stm32g4")]
+fn clear_wakeup_flag() {
+    RTC.scr().write(|w| w.set_cwutf(Calrf::CLEAR));
+}
+
+/// RTC wakeup interrupt handler
+///
+/// Only clears the wakeup event: the sleep is accounted for by [`stop_until`], and the kernel is entered through the
+/// time base interrupt.
+#[no_mangle]
+pub extern "C" fn RTC_WKUP() {
+    clear_wakeup_flag();
+    EXTI.pr(0).write(|w| w.set_line(WAKEUP_EXTI_LINE, true));
+}
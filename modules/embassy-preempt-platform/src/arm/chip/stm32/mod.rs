@@ -0,0 +1,13 @@
+//! STM32F4 and STM32G4 chips, the chip is selected through its `stm32-metapac` feature (`stm32f401re`,
+//! `stm32f411re`, `stm32f446re`, `stm32g474re`)
+
+#[cfg(all(feature = "low-power", not(feature = "time_driver_systick")))]
+mod low_power;
+mod platform;
+pub mod rcc;
+pub mod timer_driver;
+mod ucstk;
+
+pub use platform::{configure, PlatformImpl};
+pub use rcc::{clocks, Clocks, Config};
+pub use ucstk::UcStk;
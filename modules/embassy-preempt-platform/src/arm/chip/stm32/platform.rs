@@ -0,0 +1,253 @@
+use core::ptr::NonNull;
+
+#[cfg(feature = "log-base")]
+use cortex_m::asm::delay;
+use cortex_m::peripheral::scb::SystemHandler;
+use cortex_m::peripheral::{NVIC, SCB};
+use spin::Once;
+
+#[cfg(all(feature = "low-power", not(feature = "time_driver_systick")))]
+use super::low_power;
+use super::rcc::{self, Config};
+#[cfg(not(feature = "time_driver_systick"))]
+use super::timer_driver::{RtcDriver, TIMER_IRQS};
+use crate::arm::{armv7m, context};
+#[cfg(feature = "time_driver_systick")]
+use crate::arm::systick::SysTickDriver;
+use crate::traits::memory_layout::PlatformMemoryLayout;
+use crate::traits::platform::PlatformStatic;
+use crate::Platform;
+
+/// The time base runs at priority 32 and calls the kernel, so it must not be above the kernel's masking level
+const _: () = assert!(
+    embassy_preempt_cfg::OS_KERNEL_INTERRUPT_PRIO <= 32,
+    "kernel.kernel_interrupt_prio must not be below the time base priority (32)"
+);
+
+/// The clock configuration given by the application
+static CONFIG: Once<Config> = Once::new();
+
+/// Set the clock configuration the platform initializes the chip with
+///
+/// Must be called before the kernel starts (`OSInit()`), otherwise the platform runs on [`Config::default`].
+///
+/// # Panics
+/// If the platform is already initialized, or a configuration is already set
+pub fn configure(config: Config) {
+    assert!(crate::__PLATFORM.get().is_none(), "configure() must be called before OSInit()");
+    assert!(CONFIG.get().is_none(), "the clock configuration is already set");
+    CONFIG.call_once(|| config);
+}
+
+/// STM32 platform implementation, for any STM32F4 or STM32G4 chip selected through its `stm32-metapac` feature
+///
+/// ## Hardware Configuration
+///
+/// - Core: Cortex-M4 (ARMv7-M), context switching in PendSV through `crate::arm::armv7m`
+/// - Clocks: from the application's [`Config`] (see [`configure`]), the frequencies are in [`rcc::clocks`]
+/// - Timer: the `time_driver_tim*` timer, or SysTick with `time_driver_systick`
+/// - Tickless idle: STOP mode woken by the RTC with `low-power` (and a TIM time base), otherwise Sleep mode
+///
+/// Board peripherals are not part of the platform, see `crate::driver` for the Nucleo ones.
+pub struct PlatformImpl {
+    /// TIM timer driver providing timing and alarm services
+    #[cfg(not(feature = "time_driver_systick"))]
+    pub timer: RtcDriver,
+
+    /// SysTick driver providing timing and alarm services, leaving every TIM to the application
+    #[cfg(feature = "time_driver_systick")]
+    pub timer: SysTickDriver,
+}
+
+impl PlatformImpl {
+    /// Create and initialize a new STM32 platform instance
+    ///
+    /// Sets up the clocks from the configuration given to [`configure`], the interrupt priorities and the time base.
+    ///
+    /// # Panics
+    /// Will panic if the core peripherals are already taken, or the clock configuration is invalid
+    pub(crate) fn new() -> Self {
+        os_log!(info, "Init Platform");
+        let mut cp = cortex_m::Peripherals::take().unwrap();
+
+        rcc::init(CONFIG.get().unwrap_or(&Config::default()));
+        #[cfg(all(feature = "low-power", not(feature = "time_driver_systick")))]
+        low_power::init();
+
+        // Configure interrupt priorities for RTOS operation
+        PlatformImpl::set_interupt_prio(&mut cp.SCB, &mut cp.NVIC);
+
+        // Let the hardware stack the FP context of preempted tasks
+        #[cfg(feature = "cortex-m4f")]
+        crate::arm::fpu::enable_lazy_stacking();
+
+        #[cfg(not(feature = "time_driver_systick"))]
+        let timer = {
+            let timer = RtcDriver::new();
+            timer.init();
+            timer
+        };
+        // or SysTick on the core clock
+        #[cfg(feature = "time_driver_systick")]
+        let timer = {
+            let timer = SysTickDriver::new();
+            timer.init(rcc::clocks().hclk as u64);
+            timer
+        };
+
+        PlatformImpl { timer }
+    }
+
+    /// Configure interrupt priorities for RTOS operation
+    ///
+    /// Sets up the NVIC and SCB interrupt priorities to ensure proper
+    /// preemption behavior:
+    /// - PendSV: Lowest priority (context switching)
+    /// - The `time_driver_tim*` timer (or SysTick): Medium priority (timer interrupts)
+    ///
+    /// Every interrupt that calls the kernel must be at `OS_KERNEL_INTERRUPT_PRIO` or below (numerically at least).
+    ///
+    /// # Parameters
+    /// - `scb`: System Control Block for system-wide interrupts
+    /// - `nvic`: Nested Vectored Interrupt Controller for peripheral interrupts
+    fn set_interupt_prio(scb: &mut SCB, _nvic: &mut NVIC) {
+        unsafe {
+            // Set the NVIC group as 2-2 (same as port implementation)
+            let aircr = scb.aircr.read();
+            let mut aircr = aircr & !(0b1111 << 8);
+            aircr = aircr | (0b101 << 8);
+            scb.aircr.write(aircr);
+
+            // Set the timer's priority as 3 (same as port)
+            #[cfg(not(feature = "time_driver_systick"))]
+            for &irq in TIMER_IRQS {
+                _nvic.set_priority(irq, 32);
+            }
+            // the same for SysTick when it is the time base
+            scb.set_priority(SystemHandler::SysTick, 32);
+
+            // Set PendSV priority (lowest priority)
+            scb.set_priority(SystemHandler::PendSV, 0xf << 4);
+        }
+    }
+}
+
+impl PlatformStatic for PlatformImpl {
+    fn trigger_context_switch() {
+        context::trigger_context_switch();
+    }
+
+    #[inline(always)]
+    unsafe fn save_task_context() {
+        armv7m::save_task_context();
+    }
+
+    #[inline(always)]
+    unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize, return_value: u32) {
+        armv7m::restore_task_context(stack_pointer, interrupt_stack, return_value);
+    }
+
+    fn set_program_stack_pointer(sp: *mut u8) {
+        context::set_program_stack_pointer(sp);
+    }
+
+    fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+        armv7m::configure_interrupt_stack(interrupt_stack);
+    }
+
+    fn init_task_stack(stk_ref: NonNull<usize>, executor_function: fn()) -> NonNull<usize> {
+        context::init_task_stack(stk_ref, executor_function)
+    }
+
+    fn enter_idle_state() {
+        // After WFE, probe-rs reports that the RTT read pointer has been modified.
+        // Therefore, when logging is enabled, avoid WFE in idle to prevent interference.
+        #[cfg(not(feature = "log-base"))]
+        cortex_m::asm::wfe();
+
+        #[cfg(feature = "log-base")]
+        delay(500);
+    }
+
+    fn shutdown() {
+        #[cfg(feature = "semihosting")]
+        {
+            // Use semihosting to exit cleanly for defmt-test
+            use cortex_m_semihosting::debug;
+            loop {
+                debug::exit(debug::EXIT_SUCCESS);
+            }
+        }
+
+        #[cfg(not(feature = "semihosting"))]
+        {
+            os_log!(info, "Shutdown, please press Ctrl+C to stop the program");
+            loop {
+                cortex_m::asm::wfi();
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn get_current_stack_pointer() -> *mut usize {
+        context::get_current_stack_pointer()
+    }
+
+    /// Move the MPU stack guard to the bottom of the incoming stack
+    ///
+    /// With the `OS_STK_MPU_GUARD_EN` feature, a 32-byte no-access MPU region covers the bottom of the running
+    /// task's stack, so an overflow raises a MemManage fault. Without it, this does nothing.
+    #[inline(always)]
+    fn set_stack_guard(_stack_bottom: *mut u8) {
+        #[cfg(feature = "OS_STK_MPU_GUARD_EN")]
+        crate::arm::mpu::set_stack_guard(_stack_bottom);
+    }
+}
+
+/// The kernel stacks and heap take 32K from 46K into the RAM, which fits the 96K of the smallest supported chip
+/// (STM32F401RE).
+impl PlatformMemoryLayout for PlatformImpl {
+    fn get_stack_start() -> usize {
+        0x2000_B800
+    }
+
+    fn get_max_programs() -> usize {
+        10
+    }
+
+    fn get_heap_size() -> usize {
+        10 * 1024 // 10 KiB
+    }
+
+    fn get_program_stack_size() -> usize {
+        2048 // 2 KiB
+    }
+
+    fn get_interrupt_stack_size() -> usize {
+        2048 // 2 KiB
+    }
+}
+
+impl Platform for PlatformImpl {
+    fn get_timer_driver(&'static self) -> &'static dyn crate::traits::timer::Driver {
+        &self.timer
+    }
+
+    /// STOP mode with the `low-power` feature if `wake_at` is far enough for it (see `super::low_power`), otherwise
+    /// Sleep mode, in which the time base keeps counting
+    fn sleep_until(&'static self, _wake_at: u64) -> bool {
+        // WFI upsets the RTT reads of probe-rs like WFE, see `enter_idle_state`
+        #[cfg(feature = "log-base")]
+        return false;
+
+        #[cfg(not(feature = "log-base"))]
+        {
+            #[cfg(all(feature = "low-power", not(feature = "time_driver_systick")))]
+            if low_power::stop_until(&self.timer, CONFIG.get().unwrap_or(&Config::default()), _wake_at) {
+                return true;
+            }
+            crate::arm::low_power::wait_for_interrupt();
+            true
+        }
+    }
+}
@@ -0,0 +1,399 @@
+//! STM32 Clock Configuration
+//!
+//! The clock tree is set up once, by the platform, from the [`Config`] given to [`super::configure`] before the
+//! kernel starts (or [`Config::default`], 84 MHz from the PLL on the HSI). The resulting frequencies are kept in
+//! [`Clocks`] for the drivers, see [`clocks`].
+//!
+//! Only what the kernel needs is covered: HSI or HSE, the main PLL and the bus prescalers. The PLL output for the
+//! system clock is P on the STM32F4 and R on the STM32G4.
+
+use spin::Once;
+use stm32_metapac::flash::vals::Latency;
+use stm32_metapac::rcc::vals::{Hpre, Pllm, Plln, Pllq, Pllsrc, Ppre, Sw};
+#[cfg(feature = "stm32f4")]
+use stm32_metapac::rcc::vals::Pllp;
+#[cfg(feature = "stm32g4")]
+use stm32_metapac::rcc::vals::Pllr;
+use stm32_metapac::{FLASH, RCC};
+#[cfg(any(feature = "stm32f446re", feature = "stm32g4"))]
+use stm32_metapac::PWR;
+
+/// the HSI frequency, 16 MHz on both families
+pub const HSI_HZ: u32 = 16_000_000;
+
+/// The HSE oscillator
+#[derive(Clone, Copy, Debug)]
+pub struct Hse {
+    /// the crystal (or external clock) frequency in Hz
+    pub freq: u32,
+    /// an external clock on OSC_IN instead of a crystal, like the ST-LINK MCO on the Nucleo boards
+    pub bypass: bool,
+}
+
+/// The input of the main PLL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PllSource {
+    Hsi,
+    Hse,
+}
+
+/// The main PLL: `vco = input / m * n`
+#[derive(Clone, Copy, Debug)]
+pub struct Pll {
+    pub source: PllSource,
+    /// input divider, the VCO input must be 1-2 MHz on the STM32F4 and 2.66-8 MHz on the STM32G4
+    pub m: u8,
+    /// VCO multiplier
+    pub n: u16,
+    /// system clock divider (PLLP on the STM32F4: 2, 4, 6 or 8)
+    #[cfg(feature = "stm32f4")]
+    pub p: u8,
+    /// system clock divider (PLLR on the STM32G4: 2, 4, 6 or 8)
+    #[cfg(feature = "stm32g4")]
+    pub r: u8,
+    /// the 48 MHz clock divider (USB, SDIO, RNG), off if `None`
+    pub q: Option<u8>,
+}
+
+/// The system clock source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysClkSource {
+    Hsi,
+    Hse,
+    Pll,
+}
+
+/// Clock configuration, supplied by the application through [`super::configure`]
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// the HSE oscillator, off if `None`
+    pub hse: Option<Hse>,
+    /// the main PLL, off if `None`
+    pub pll: Option<Pll>,
+    pub sys: SysClkSource,
+    /// AHB prescaler: 1, 2, 4, 8, 16, 64, 128, 256 or 512
+    pub ahb_div: u16,
+    /// APB1 prescaler: 1, 2, 4, 8 or 16
+    pub apb1_div: u8,
+    /// APB2 prescaler: 1, 2, 4, 8 or 16
+    pub apb2_div: u8,
+}
+
+impl Default for Config {
+    /// 84 MHz from the PLL on the 16 MHz HSI, with APB1 at 42 MHz and APB2 at 84 MHz, the clocks the examples are
+    /// written for. On the STM32F4 the PLL Q output is 48 MHz.
+    fn default() -> Self {
+        Config {
+            hse: None,
+            pll: Some(Pll {
+                source: PllSource::Hsi,
+                #[cfg(feature = "stm32f4")]
+                m: 16,
+                #[cfg(feature = "stm32f4")]
+                n: 336,
+                #[cfg(feature = "stm32f4")]
+                p: 4,
+                #[cfg(feature = "stm32g4")]
+                m: 4,
+                #[cfg(feature = "stm32g4")]
+                n: 42,
+                #[cfg(feature = "stm32g4")]
+                r: 2,
+                #[cfg(feature = "stm32f4")]
+                q: Some(7),
+                #[cfg(feature = "stm32g4")]
+                q: None,
+            }),
+            sys: SysClkSource::Pll,
+            ahb_div: 1,
+            apb1_div: 2,
+            apb2_div: 1,
+        }
+    }
+}
+
+/// The frequencies set up from the [`Config`], in Hz
+#[derive(Clone, Copy, Debug)]
+pub struct Clocks {
+    pub sysclk: u32,
+    /// the core and the AHB bus
+    pub hclk: u32,
+    pub pclk1: u32,
+    pub pclk2: u32,
+    /// the timers on APB1, twice `pclk1` if APB1 is divided
+    pub pclk1_tim: u32,
+    /// the timers on APB2, twice `pclk2` if APB2 is divided
+    pub pclk2_tim: u32,
+    /// the PLL Q output
+    pub pll_q: Option<u32>,
+}
+
+static CLOCKS: Once<Clocks> = Once::new();
+
+/// The frequencies the platform has set up
+///
+/// # Panics
+/// If the platform has not been initialized yet
+pub fn clocks() -> &'static Clocks {
+    CLOCKS.get().expect("the clocks are set up by the platform initialization")
+}
+
+/// HPRE bits of the AHB prescaler
+fn hpre(div: u16) -> Hpre {
+    let bits = match div {
+        1 => 0b0000,
+        2 => 0b1000,
+        4 => 0b1001,
+        8 => 0b1010,
+        16 => 0b1011,
+        64 => 0b1100,
+        128 => 0b1101,
+        256 => 0b1110,
+        512 => 0b1111,
+        _ => panic!("invalid AHB prescaler {}", div),
+    };
+    Hpre::from_bits(bits)
+}
+
+/// PPRE bits of an APB prescaler
+fn ppre(div: u8) -> Ppre {
+    let bits = match div {
+        1 => 0b000,
+        2 => 0b100,
+        4 => 0b101,
+        8 => 0b110,
+        16 => 0b111,
+        _ => panic!("invalid APB prescaler {}", div),
+    };
+    Ppre::from_bits(bits)
+}
+
+/// The bits of the PLL P/R/Q dividers of 2, 4, 6 and 8
+#[cfg(feature = "stm32g4")]
+fn even_div(div: u8) -> u8 {
+    assert!(matches!(div, 2 | 4 | 6 | 8), "invalid PLL divider {}", div);
+    div / 2 - 1
+}
+
+/// The highest HCLK of the STM32G4 in the range 1 normal mode, above it the boost mode is needed
+#[cfg(feature = "stm32g4")]
+const G4_NORMAL_MAX_HZ: u32 = 150_000_000;
+
+/// Flash wait states for `hclk` at 2.7-3.6 V (range 1 on the STM32G4, in the boost mode above 150 MHz)
+fn flash_latency(hclk: u32) -> Latency {
+    #[cfg(feature = "stm32f4")]
+    let hz_per_wait_state: u32 = 30_000_000;
+    #[cfg(feature = "stm32g4")]
+    let hz_per_wait_state: u32 = if hclk > G4_NORMAL_MAX_HZ { 34_000_000 } else { 30_000_000 };
+    Latency::from_bits(((hclk - 1) / hz_per_wait_state) as u8)
+}
+
+/// Program the flash wait states, with the prefetch and the caches on
+fn set_flash_latency(latency: Latency) {
+    FLASH.acr().modify(|w| {
+        w.set_latency(latency);
+        w.set_prften(true);
+        w.set_icen(true);
+        w.set_dcen(true);
+    });
+    while FLASH.acr().read().latency() != latency {}
+}
+
+/// The system clock switch value of the PLL output
+fn sw_pll() -> Sw {
+    #[cfg(feature = "stm32f4")]
+    let sw = Sw::PLL1_P;
+    #[cfg(feature = "stm32g4")]
+    let sw = Sw::PLL1_R;
+    sw
+}
+
+/// Set up the clock tree from `config`, and store the frequencies for [`clocks`]
+///
+/// Also stores `SYSCLK_HZ` (the core clock) and `APB_HZ` (the APB1 timer clock) of `embassy_preempt_cfg`.
+///
+/// # Panics
+/// If a divider is out of range
+pub(crate) fn init(config: &Config) -> &'static Clocks {
+    let hse_hz = config.hse.map(|hse| hse.freq);
+
+    if let Some(hse) = config.hse {
+        RCC.cr().modify(|w| {
+            w.set_hsebyp(hse.bypass);
+            w.set_hseon(true);
+        });
+        while !RCC.cr().read().hserdy() {}
+    }
+
+    // The PLL can only be configured while it is off, so a system clock running from it moves to the HSI first. The
+    // flash latency in use is enough for the HSI.
+    if RCC.cfgr().read().sws() == sw_pll() {
+        RCC.cr().modify(|w| w.set_hsion(true));
+        while !RCC.cr().read().hsirdy() {}
+        RCC.cfgr().modify(|w| w.set_sw(Sw::HSI));
+        while RCC.cfgr().read().sws() != Sw::HSI {}
+    }
+    RCC.cr().modify(|w| w.set_pllon(false));
+    while RCC.cr().read().pllrdy() {}
+
+    let mut pll_out = None;
+    let mut pll_q = None;
+    if let Some(pll) = config.pll {
+        let input = match pll.source {
+            PllSource::Hsi => HSI_HZ,
+            PllSource::Hse => hse_hz.expect("the PLL source HSE is not configured"),
+        };
+        let vco = input / pll.m as u32 * pll.n as u32;
+
+        #[cfg(feature = "stm32f4")]
+        {
+            assert!(matches!(pll.p, 2 | 4 | 6 | 8), "invalid PLLP {}", pll.p);
+            pll_out = Some(vco / pll.p as u32);
+            RCC.pllcfgr().write(|w| {
+                w.set_pllsrc(match pll.source {
+                    PllSource::Hsi => Pllsrc::HSI,
+                    PllSource::Hse => Pllsrc::HSE,
+                });
+                w.set_pllm(Pllm::from_bits(pll.m));
+                w.set_plln(Plln::from_bits(pll.n));
+                w.set_pllp(Pllp::from_bits(pll.p / 2 - 1));
+                w.set_pllq(Pllq::from_bits(pll.q.unwrap_or(15)));
+            });
+            pll_q = pll.q.map(|q| vco / q as u32);
+        }
+        #[cfg(feature = "stm32g4")]
+        {
+            pll_out = Some(vco / pll.r as u32);
+            RCC.pllcfgr().write(|w| {
+                w.set_pllsrc(match pll.source {
+                    PllSource::Hsi => Pllsrc::HSI,
+                    PllSource::Hse => Pllsrc::HSE,
+                });
+                w.set_pllm(Pllm::from_bits(pll.m - 1));
+                w.set_plln(Plln::from_bits(pll.n as u8));
+                w.set_pllr(Pllr::from_bits(even_div(pll.r)));
+                w.set_pllren(true);
+                if let Some(q) = pll.q {
+                    w.set_pllq(Pllq::from_bits(even_div(q)));
+                    w.set_pllqen(true);
+                }
+            });
+            pll_q = pll.q.map(|q| vco / q as u32);
+        }
+
+        RCC.cr().modify(|w| w.set_pllon(true));
+        while !RCC.cr().read().pllrdy() {}
+    }
+
+    let sysclk = match config.sys {
+        SysClkSource::Hsi => HSI_HZ,
+        SysClkSource::Hse => hse_hz.expect("the system clock HSE is not configured"),
+        SysClkSource::Pll => pll_out.expect("the system clock PLL is not configured"),
+    };
+    let hclk = sysclk / config.ahb_div as u32;
+    let pclk1 = hclk / config.apb1_div as u32;
+    let pclk2 = hclk / config.apb2_div as u32;
+
+    // The system clock is the HSI or the HSE here, slow enough for any voltage scaling.
+    // Above 168 MHz the STM32F446 needs the over-drive, above 150 MHz the STM32G4 needs the range 1 boost mode
+    #[cfg(feature = "stm32f446re")]
+    if hclk > 168_000_000 {
+        enable_over_drive();
+    }
+    #[cfg(feature = "stm32g4")]
+    {
+        RCC.apb1enr1().modify(|w| w.set_pwren(true));
+        PWR.cr5().modify(|w| w.set_r1mode(hclk <= G4_NORMAL_MAX_HZ));
+    }
+
+    // more wait states before the clock goes up, fewer only after it has come down
+    let latency = flash_latency(hclk);
+    let raise_latency = latency.to_bits() > FLASH.acr().read().latency().to_bits();
+    if raise_latency {
+        set_flash_latency(latency);
+    }
+
+    RCC.cfgr().modify(|w| {
+        w.set_ppre1(ppre(config.apb1_div));
+        w.set_ppre2(ppre(config.apb2_div));
+    });
+    switch_sysclk(config, hclk);
+
+    if !raise_latency {
+        set_flash_latency(latency);
+    }
+
+    let clocks = Clocks {
+        sysclk,
+        hclk,
+        pclk1,
+        pclk2,
+        pclk1_tim: if config.apb1_div == 1 { pclk1 } else { pclk1 * 2 },
+        pclk2_tim: if config.apb2_div == 1 { pclk2 } else { pclk2 * 2 },
+        pll_q,
+    };
+    os_log!(info, "sysclk {} Hz, hclk {} Hz, pclk1 {} Hz, pclk2 {} Hz", sysclk, hclk, pclk1, pclk2);
+
+    embassy_preempt_cfg::SYSCLK_HZ().set(hclk as u64);
+    embassy_preempt_cfg::APB_HZ().set(clocks.pclk1_tim as u64);
+
+    CLOCKS.call_once(|| clocks)
+}
+
+/// Restart the clock tree after STOP mode, which leaves the HSI as the system clock
+///
+/// The prescalers, the PLL configuration and the flash latency are kept: only the HSE, the PLL and the over-drive
+/// are turned on again before switching back.
+#[cfg(feature = "low-power")]
+pub(crate) fn restore(config: &Config) {
+    if config.hse.is_some() {
+        RCC.cr().modify(|w| w.set_hseon(true));
+        while !RCC.cr().read().hserdy() {}
+    }
+    if config.pll.is_some() {
+        RCC.cr().modify(|w| w.set_pllon(true));
+        while !RCC.cr().read().pllrdy() {}
+    }
+    let hclk = clocks().hclk;
+    #[cfg(feature = "stm32f446re")]
+    if hclk > 168_000_000 {
+        enable_over_drive();
+    }
+    switch_sysclk(config, hclk);
+}
+
+/// Switch the system clock to the source of `config`, with its AHB prescaler
+///
+/// The STM32G4 must step through AHB / 2 for 1 us when switching above 80 MHz.
+fn switch_sysclk(config: &Config, hclk: u32) {
+    let sw = match config.sys {
+        SysClkSource::Hsi => Sw::HSI,
+        SysClkSource::Hse => Sw::HSE,
+        SysClkSource::Pll => sw_pll(),
+    };
+    #[cfg(feature = "stm32g4")]
+    let step = hclk > 80_000_000 && config.ahb_div == 1;
+    #[cfg(feature = "stm32f4")]
+    let step = false;
+
+    RCC.cfgr().modify(|w| {
+        w.set_hpre(hpre(if step { 2 } else { config.ahb_div }));
+        w.set_sw(sw);
+    });
+    while RCC.cfgr().read().sws() != sw {}
+
+    if step {
+        cortex_m::asm::delay(hclk / 2 / 1_000_000);
+        RCC.cfgr().modify(|w| w.set_hpre(hpre(1)));
+    }
+}
+
+/// Turn on the over-drive of the STM32F446, needed above 168 MHz and lost in STOP mode
+#[cfg(feature = "stm32f446re")]
+fn enable_over_drive() {
+    RCC.apb1enr().modify(|w| w.set_pwren(true));
+    PWR.cr1().modify(|w| w.set_oden(true));
+    while !PWR.csr1().read().odrdy() {}
+    PWR.cr1().modify(|w| w.set_odswen(true));
+    while !PWR.csr1().read().odswrdy() {}
+}
@@ -1,37 +1,38 @@
 /*
 *********************************************************************************************************
-*                                 Platform Timer Driver - STM32F401RE实现
+*                                 Platform Timer Driver - STM32实现
 *********************************************************************************************************
 */
 
-//! STM32F401RE Timer Driver Implementation
+//! STM32 Timer Driver Implementation
 //!
-//! This module provides a hardware-specific timer driver for the STM32F401RE microcontroller
+//! This module provides a hardware-specific timer driver for the STM32F4 and STM32G4 microcontrollers
 //! that implements the `Driver` trait. It uses general-purpose timers (TIM2, TIM3, TIM4, TIM5)
 //! to provide high-precision timing and alarm functionality for the RTOS.
 //!
 //! ## Timer Architecture
 //!
-//! The driver uses a 16-bit timer with overflow and half-overflow interrupts to create
+//! The driver uses a 16-bit or 32-bit timer with overflow and half-overflow interrupts to create
 //! a 64-bit timestamp counter:
-//! - **Timer period**: 16-bit counter (0-65535)
+//! - **Timer period**: 16-bit counter (0-65535), or 32-bit for TIM2 and TIM5
 //! - **Period tracking**: 32-bit period counter for overflow handling
-//! - **Combined timestamp**: 64-bit (32-bit period << 15 | 15-bit counter)
+//! - **Combined timestamp**: 64-bit (period << 15 | 15-bit counter), (period << 31 | 31-bit counter) for 32-bit
+//!   timers, which interrupt 65536 times less often
 //! - **Alarm support**: Up to 3 concurrent alarms (1 for limited timers)
 //!
 //! ## Hardware Configuration
 //!
-//! - **Clock source**: APB1 bus clock (42MHz) with programmable prescaler
+//! - **Clock source**: APB1 (or APB2) timer clock with programmable prescaler
 //! - **Timer frequency**: Configurable via TICK_HZ (typically 1kHz)
 //! - **Interrupts**: Overflow, half-overflow, and capture/compare interrupts
-//! - **Timer selection**: Configurable via feature flags (time_driver_tim*)
+//! - **Timer selection**: Configurable via feature flags (time_driver_tim*), which also select the interrupt
+//!   handlers and the NVIC lines to unmask (`TIMER_IRQS`)
 //!
 //! ## Clock Configuration Integration
 //!
-//! The timer driver integrates with the HAL library for clock configuration:
-//! - System clock configuration is handled by the platform initialization using HAL
-//! - Clock frequencies are provided via `store_clock_config()` during platform setup
-//! - Timer prescaler calculation uses the stored APB1 frequency from HAL
+//! The clocks are set up by the platform from the application's `Config` (see `super::rcc`) before the timer:
+//! - The prescaler is calculated from the timer clock of the bus the selected timer is on, twice the bus clock
+//!   when the bus is divided
 //!
 //! ## Features
 //!
@@ -40,20 +41,18 @@
 //! - **Interrupt-driven**: Efficient event handling with minimal CPU overhead
 //! - **Hardware abstraction**: Clean interface for the RTOS scheduler
 //! - **Debug support**: Optional GPIO toggling for timing analysis
-//! - **HAL integration**: Uses HAL library for clock configuration
 use core::sync::atomic::{compiler_fence, AtomicU32, AtomicU8, Ordering};
 use core::mem;
 
 use cortex_m::peripheral::NVIC;
 use critical_section::{CriticalSection, Mutex};
-use embassy_preempt_cfg::{APB_HZ, SYSCLK_HZ, TICK_HZ};
+use embassy_preempt_cfg::TICK_HZ;
 use embassy_preempt_log::{os_log, timer_log};
 // use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use stm32_metapac::flash::vals::Latency;
-use stm32_metapac::rcc::vals::*;
-use stm32_metapac::timer::{regs, vals};
-use stm32_metapac::{Interrupt, FLASH, RCC};
+use stm32_metapac::timer::vals;
+use stm32_metapac::{Interrupt, RCC};
 
+use super::rcc;
 use crate::traits::timer::{AlarmHandle, AlarmState, Driver};
 
 /// Timer peripheral selection based on feature flags
@@ -78,6 +77,44 @@ pub const TIMER: stm32_metapac::timer::TimGp16 = stm32_metapac::TIM9;
 #[cfg(feature = "time_driver_tim12")]
 pub const TIMER: stm32_metapac::timer::TimGp16 = stm32_metapac::TIM12;
 
+/// NVIC lines of the selected timer
+///
+/// Advanced and limited timers share their lines with other timers, so there can be more than one. The platform
+/// sets their priority, [`RtcDriver::init`] unmasks them.
+#[cfg(all(feature = "time_driver_tim1", feature = "stm32f4"))]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM1_CC, Interrupt::TIM1_UP_TIM10];
+#[cfg(all(feature = "time_driver_tim1", feature = "stm32g4"))]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM1_CC, Interrupt::TIM1_UP_TIM16];
+#[cfg(feature = "time_driver_tim2")]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM2];
+#[cfg(feature = "time_driver_tim3")]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM3];
+#[cfg(feature = "time_driver_tim4")]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM4];
+#[cfg(feature = "time_driver_tim5")]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM5];
+#[cfg(all(feature = "time_driver_tim8", feature = "stm32f4"))]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM8_CC, Interrupt::TIM8_UP_TIM13];
+#[cfg(all(feature = "time_driver_tim8", feature = "stm32g4"))]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM8_CC, Interrupt::TIM8_UP];
+#[cfg(feature = "time_driver_tim9")]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM1_BRK_TIM9];
+#[cfg(feature = "time_driver_tim12")]
+pub const TIMER_IRQS: &[Interrupt] = &[Interrupt::TIM8_BRK_TIM12];
+
+/// Width of the selected timer's counter
+#[cfg(any(feature = "time_driver_tim2", feature = "time_driver_tim5"))]
+const COUNTER_BITS: u32 = 32;
+#[cfg(not(any(feature = "time_driver_tim2", feature = "time_driver_tim5")))]
+const COUNTER_BITS: u32 = 16;
+
+/// A period is half the counter range, it starts at the overflow and at the half-overflow
+const PERIOD_SHIFT: u32 = COUNTER_BITS - 1;
+/// The half-overflow point, compared on CC1
+const HALF_OVERFLOW: u32 = 1 << PERIOD_SHIFT;
+/// An alarm less than 1.5 periods away can be enabled, its compare value cannot match early
+const ALARM_WINDOW: u64 = 3 << (PERIOD_SHIFT - 1);
+
 // Type aliases for consistency with uC/OS-II naming conventions
 pub type BOOLEAN = bool;
 pub type INT16U = u16;
@@ -152,32 +189,58 @@ const ALARM_COUNT: USIZE = 1;
     feature = "time_driver_tim22"
 )))]
 const ALARM_COUNT: USIZE = 3;
-/// TIM3 interrupt handler
+/// Generate the interrupt handlers of the selected timer
 ///
-/// This function is called when the TIM3 timer generates an interrupt.
-/// It handles timer overflow, half-overflow, and alarm callback events.
-/// The interrupt handler delegates to the timer driver's on_interrupt method.
-#[cfg(feature = "time_driver_tim3")]
-#[unsafe(no_mangle)]
-pub extern "C" fn TIM3() {
-    use crate::get_platform_trait;
-
-    // Toggle debug GPIO pin high for timing analysis
-    interrupt_pin_high();
-
-    os_log!(trace, "TIM3 interrupt handler invoked");
-
-    // Delegate to the timer driver for interrupt processing
-    unsafe {
-        get_platform_trait().get_timer_driver().on_interrupt();
-    }
+/// Each handler toggles the debug GPIO and delegates to the timer driver, which handles timer overflow,
+/// half-overflow, and alarm callback events.
+macro_rules! timer_interrupt_handlers {
+    ($($irq:ident),*) => {
+        $(
+            /// Timer interrupt handler, see `RtcDriver::on_interrupt`
+            #[unsafe(no_mangle)]
+            pub extern "C" fn $irq() {
+                use crate::get_platform_trait;
+
+                // Toggle debug GPIO pin high for timing analysis
+                interrupt_pin_high();
+
+                os_log!(trace, "{} interrupt handler invoked", stringify!($irq));
+
+                // Delegate to the timer driver for interrupt processing
+                unsafe {
+                    get_platform_trait().get_timer_driver().on_interrupt();
+                }
 
-    os_log!(trace, "exiting TIM3 interrupt handler");
+                os_log!(trace, "exiting {} interrupt handler", stringify!($irq));
 
-    // Toggle debug GPIO pin low
-    interrupt_pin_low();
+                // Toggle debug GPIO pin low
+                interrupt_pin_low();
+            }
+        )*
+    };
 }
 
+#[cfg(all(feature = "time_driver_tim1", feature = "stm32f4"))]
+timer_interrupt_handlers!(TIM1_CC, TIM1_UP_TIM10);
+#[cfg(all(feature = "time_driver_tim1", feature = "stm32g4"))]
+timer_interrupt_handlers!(TIM1_CC, TIM1_UP_TIM16);
+#[cfg(feature = "time_driver_tim2")]
+timer_interrupt_handlers!(TIM2);
+#[cfg(feature = "time_driver_tim3")]
+timer_interrupt_handlers!(TIM3);
+#[cfg(feature = "time_driver_tim4")]
+timer_interrupt_handlers!(TIM4);
+#[cfg(feature = "time_driver_tim5")]
+timer_interrupt_handlers!(TIM5);
+#[cfg(all(feature = "time_driver_tim8", feature = "stm32f4"))]
+timer_interrupt_handlers!(TIM8_CC, TIM8_UP_TIM13);
+#[cfg(all(feature = "time_driver_tim8", feature = "stm32g4"))]
+timer_interrupt_handlers!(TIM8_CC, TIM8_UP);
+#[cfg(feature = "time_driver_tim9")]
+timer_interrupt_handlers!(TIM1_BRK_TIM9);
+#[cfg(feature = "time_driver_tim12")]
+timer_interrupt_handlers!(TIM8_BRK_TIM12);
+
 /*
 *********************************************************************************************************
 *                                           type definitions
@@ -187,27 +250,26 @@ pub extern "C" fn TIM3() {
 const DISABLE: bool = false;
 const ENABLE: bool = true;
 
-/// STM32F401RE Timer Driver
+/// STM32 Timer Driver
 ///
 /// Implements the Driver trait using a hardware general-purpose timer.
 /// Provides high-precision timing and alarm functionality for the RTOS.
 ///
 /// ## Architecture
 ///
-/// The driver creates a 64-bit timestamp counter from a 16-bit hardware timer:
-/// - **Timer overflow**: Increment period counter every 2^15 ticks
+/// The driver creates a 64-bit timestamp counter from a 16-bit (or 32-bit) hardware timer:
+/// - **Timer overflow**: Increment period counter every 2^15 (2^31) ticks
 /// - **Half-overflow**: Handle wraparound and maintain continuity
-/// - **64-bit timestamp**: (period << 15) | (counter ^ ((period & 1) << 15))
+/// - **64-bit timestamp**: (period << 15) | (counter ^ ((period & 1) << 15)), 31 instead of 15 for 32-bit timers
 ///
 /// ## Fields
 ///
 /// - `period`: Atomic counter tracking timer overflow periods
 /// - `alarm_count`: Number of allocated alarm instances
 /// - `alarms`: Array of alarm states with callbacks and timestamps
-/// - `rtc`: Optional RTC reference for low-power mode support
 pub struct RtcDriver {
-    /// Number of 2^15 (32768) tick periods elapsed since system boot
-    /// Each period represents one full overflow cycle of the 16-bit timer
+    /// Number of 2^15 (32768) tick periods elapsed since system boot, 2^31 for 32-bit timers
+    /// Each period represents half an overflow cycle of the timer
     period: AtomicU32,
 
     /// Counter for tracking allocated alarm instances
@@ -216,10 +278,6 @@ pub struct RtcDriver {
     /// Array of alarm states storing callbacks, contexts, and trigger timestamps
     /// u64::MAX indicates no alarm is scheduled for that slot
     alarms: Mutex<[AlarmState; ALARM_COUNT]>,
-
-    /// Optional RTC reference for low-power mode (feature-gated)
-    #[cfg(feature = "low-power")]
-    rtc: Mutex<CriticalSectionRawMutex, Cell<Option<&'static Rtc>>>,
 }
 
 /*
@@ -244,8 +302,6 @@ impl RtcDriver {
             period: AtomicU32::new(0),
             alarm_count: AtomicU8::new(0),
             alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
-            #[cfg(feature = "low-power")]
-            rtc: Mutex::const_new(CriticalSectionRawMutex::new(), Cell::new(None)),
         }
     }
     /// Initialize the timer driver and hardware
@@ -267,17 +323,15 @@ impl RtcDriver {
         // Enable timer peripheral clock
         enable_Timer();
 
-        // Get APB frequency for prescaler calculation
-        get_APBfreq();
 
         // Temporarily disable timer for configuration
         TIMER.cr1().modify(|w| w.set_cen(false));
 
         // Clear counter to start from zero
-        TIMER.cnt().write(|w| w.set_cnt(0));
+        write_cnt(0);
 
         // Calculate prescaler to achieve desired tick frequency
-        let psc = (*APB_HZ().get() / TICK_HZ) as INT32U - 1;
+        let psc = (timer_clock() as u64 / TICK_HZ) as INT32U - 1;
         let psc: INT16U = match psc.try_into() {
             Err(_) => panic!("psc division overflow: {}", psc),
             Ok(n) => n,
@@ -286,8 +340,8 @@ impl RtcDriver {
         // Set the prescaler divider
         TIMER.psc().write_value(psc);
 
-        // Set auto-reload register to maximum for 16-bit timer
-        TIMER.arr().write(|w| w.set_arr(INT16U::MAX));
+        // Set auto-reload register to the maximum of the counter
+        write_arr_max();
 
         // Set URS, generate update and clear URS
         // by noah： when set ug bit, a update event will be generated immediately.
@@ -297,8 +351,8 @@ impl RtcDriver {
         TIMER.egr().write(|w| w.set_ug(true));
         TIMER.cr1().modify(|w| w.set_urs(vals::Urs::ANYEVENT));
 
-        // Mid-way point, there will be a cc(capture/compare) interrupt at 0x8000
-        TIMER.ccr(0).write(|w| w.set_ccr(0x8000));
+        // Mid-way point, there will be a cc(capture/compare) interrupt at 0x8000 (0x8000_0000)
+        write_ccr(0, HALF_OVERFLOW);
 
         TIMER.dier().write(|w| {
             // Enable overflow
@@ -309,45 +363,11 @@ impl RtcDriver {
 
         // by noah：the InterruptNumber trait is implemented by the stm32_metapac crate
         // so in embassy, the InterruptExt trait will be implemented for the interrupt in pac
-        #[cfg(feature = "time_driver_tim3")]
-        NVIC::unpend(Interrupt::TIM3);
-        #[cfg(feature = "time_driver_tim3")]
-        unsafe {
-            compiler_fence(Ordering::SeqCst);
-            NVIC::unmask(Interrupt::TIM3);
-        }
-        #[cfg(feature = "time_driver_tim1")]
-        {
-            NVIC::unpend(Interrupt::TIM1_CC);
-            NVIC::unpend(Interrupt::TIM1_UP_TIM10);
+        for &irq in TIMER_IRQS {
+            NVIC::unpend(irq);
             unsafe {
                 compiler_fence(Ordering::SeqCst);
-                NVIC::unmask(Interrupt::TIM1_CC);
-                NVIC::unmask(Interrupt::TIM1_UP_TIM10);
-            }
-        }
-        #[cfg(feature = "time_driver_tim2")]
-        {
-            NVIC::unpend(Interrupt::TIM2);
-            unsafe {
-                compiler_fence(Ordering::SeqCst);
-                NVIC::unmask(Interrupt::TIM2);
-            }
-        }
-        #[cfg(feature = "time_driver_tim4")]
-        {
-            NVIC::unpend(Interrupt::TIM4);
-            unsafe {
-                compiler_fence(Ordering::SeqCst);
-                NVIC::unmask(Interrupt::TIM4);
-            }
-        }
-        #[cfg(feature = "time_driver_tim5")]
-        {
-            NVIC::unpend(Interrupt::TIM5);
-            unsafe {
-                compiler_fence(Ordering::SeqCst);
-                NVIC::unmask(Interrupt::TIM5);
+                NVIC::unmask(irq);
             }
         }
 
@@ -355,6 +375,44 @@ impl RtcDriver {
         TIMER.cr1().modify(|w| w.set_cen(ENABLE));
     }
 
+    /// Stop the counter for STOP mode, the time spent asleep is added back by [`RtcDriver::resume`]
+    #[cfg(feature = "low-power")]
+    pub(crate) fn pause(&self) {
+        TIMER.cr1().modify(|w| w.set_cen(DISABLE));
+    }
+
+    /// Move the time base `ticks` forward and restart the counter
+    ///
+    /// The period and the counter are set as if the counter had kept running. The alarms which have come meanwhile
+    /// have missed their compare value, they are fired through a software compare event.
+    #[cfg(feature = "low-power")]
+    pub(crate) fn resume(&self, ticks: u64) {
+        critical_section::with(|cs| {
+            let now = self.now() + ticks;
+            let period = (now >> PERIOD_SHIFT) as INT32U;
+            self.period.store(period, Ordering::Relaxed);
+            write_cnt((now as INT32U & (HALF_OVERFLOW - 1)) ^ ((period & 1) << PERIOD_SHIFT));
+            // the overflows and half-overflows while asleep are in `period` already
+            TIMER.sr().modify(|w| {
+                w.set_uif(false);
+                w.set_ccif(0, false);
+            });
+
+            TIMER.dier().modify(|w| {
+                for n in 0..ALARM_COUNT {
+                    w.set_ccie(n + 1, self.alarms.borrow(cs)[n].timestamp.get() < now + ALARM_WINDOW);
+                }
+            });
+            for n in 0..ALARM_COUNT {
+                if self.alarms.borrow(cs)[n].timestamp.get() <= now {
+                    TIMER.egr().write(|w| w.set_ccg(n + 1, true));
+                }
+            }
+
+            TIMER.cr1().modify(|w| w.set_cen(ENABLE));
+        })
+    }
+
     fn next_period(&self) {
         // let r = regs_gp16();
         // We only modify the period from the timer interrupt, so we know this can't race.
@@ -362,7 +420,7 @@ impl RtcDriver {
         // #[cfg(feature = "alarm_test")]
         // os_log!(info, "RTC's period is {}", self.period.load(Ordering::Relaxed));
         self.period.store(period, Ordering::Relaxed);
-        let t = (period as u64) << 15;
+        let t = (period as u64) << PERIOD_SHIFT;
 
         critical_section::with(move |cs| {
             TIMER.dier().modify(move |w| {
@@ -370,7 +428,7 @@ impl RtcDriver {
                     let alarm = &self.alarms.borrow(cs)[n];
                     let at = alarm.timestamp.get();
 
-                    if at < t + 0xc000 {
+                    if at < t + ALARM_WINDOW {
                         // just enable it. `set_alarm` has already set the correct CCR val.
                         w.set_ccie(n + 1, true);
                     }
@@ -406,7 +464,7 @@ impl Driver for RtcDriver {
 
         let period = self.period.load(Ordering::Relaxed);
         compiler_fence(Ordering::Acquire);
-        let counter = TIMER.cnt().read().cnt();
+        let counter = read_cnt();
         calc_now(period, counter)
     }
 
@@ -470,11 +528,11 @@ impl Driver for RtcDriver {
 
             // Write the CCR value regardless of whether we're going to enable it now or not.
             // This way, when we enable it later, the right value is already set.
-            TIMER.ccr(n + 1).write(|w| w.set_ccr(timestamp as u16));
+            write_ccr(n + 1, timestamp as u32);
 
             // Enable it if it'll happen soon. Otherwise, `next_period` will enable it.
             let diff = timestamp - t;
-            TIMER.dier().modify(|w| w.set_ccie(n + 1, diff < 0xc000));
+            TIMER.dier().modify(|w| w.set_ccie(n + 1, diff < ALARM_WINDOW));
 
             // Reevaluate if the alarm timestamp is still in the future
             let t = self.now();
@@ -506,7 +564,9 @@ impl Driver for RtcDriver {
             // Clear all interrupt flags. Bits in SR are "write 0 to clear", so write the bitwise NOT.
             // Other approaches such as writing all zeros, or RMWing won't work, they can
             // miss interrupts.
-            TIMER.sr().write_value(regs::SrGp16(!sr.0));
+            let mut clear = sr;
+            clear.0 = !sr.0;
+            TIMER.sr().write_value(clear);
 
             // Overflow
             if sr.uif() {
@@ -514,9 +574,12 @@ impl Driver for RtcDriver {
             }
 
             // Half overflow
-            // if sr.ccif(0) {
-            //     self.next_period();
-            // }
+            // An overflow interrupt fires at each half period (2^PERIOD_SHIFT ticks), the update at the wrap and this
+            // one at the middle of the counter range. `now()` stays correct as long as one of them is serviced
+            // within a half period.
+            if sr.ccif(0) {
+                self.next_period();
+            }
 
             for n in 0..ALARM_COUNT {
                 if sr.ccif(n + 1) && dier.ccie(n + 1) {
@@ -534,56 +597,45 @@ impl Driver for RtcDriver {
 *********************************************************************************************************
 */
 
-// RCC configuration has been moved to platform.rs using HAL library
-
-/// Store clock configuration from HAL library for timer driver use
-///
-/// This function should be called during platform initialization to provide
-/// the timer driver with the current clock configuration.
-///
-/// # Parameters
-/// - `clocks`: Clock configuration from HAL library
-pub fn store_clock_config(clocks: &stm32f4xx_hal::rcc::Clocks) {
-    // Store system clock frequency
-    SYSCLK_HZ().set(clocks.sysclk().raw() as u64);
-
-    // Store APB frequency (used for timer prescaler calculation)
-    // Note: TIM3 is on APB1, so we use pclk1
-    APB_HZ().set(clocks.pclk1().raw() as u64);
-}
-
-/// Get the frequency of the APB bus using stored HAL configuration
-pub fn get_APBfreq() {
-    // The clock configuration should now be set by store_clock_config()
-    // This function is kept for compatibility but now just validates the configuration
-    let apb_freq = *APB_HZ().get();
-    if apb_freq == 0 {
-        panic!("APB frequency not set. Call store_clock_config() first.");
-    }
-
-    os_log!(info, "APB frequency: {} Hz", apb_freq);
+/// The kernel clock of the selected timer, the timer clock of its bus
+fn timer_clock() -> INT32U {
+    let clocks = rcc::clocks();
+    #[cfg(any(feature = "time_driver_tim1", feature = "time_driver_tim8", feature = "time_driver_tim9"))]
+    let hz = clocks.pclk2_tim;
+    #[cfg(not(any(feature = "time_driver_tim1", feature = "time_driver_tim8", feature = "time_driver_tim9")))]
+    let hz = clocks.pclk1_tim;
+    os_log!(info, "timer clock: {} Hz", hz);
+    hz
 }
 
 fn enable_Timer() {
+    // the APB1 enable register is split in two on the STM32G4
+    #[cfg(feature = "stm32f4")]
+    #[allow(unused_variables)]
+    let apb1enr = RCC.apb1enr();
+    #[cfg(feature = "stm32g4")]
+    #[allow(unused_variables)]
+    let apb1enr = RCC.apb1enr1();
+
     #[cfg(feature = "time_driver_tim1")]
     RCC.apb2enr().modify(|v| v.set_tim1en(ENABLE));
     #[cfg(feature = "time_driver_tim2")]
-    RCC.apb1enr().modify(|v| v.set_tim2en(ENABLE));
+    apb1enr.modify(|v| v.set_tim2en(ENABLE));
 
     // by noah: in current project, we use Timer 3 as the time driver
     #[cfg(feature = "time_driver_tim3")]
-    RCC.apb1enr().modify(|v| v.set_tim3en(ENABLE));
+    apb1enr.modify(|v| v.set_tim3en(ENABLE));
 
     #[cfg(feature = "time_driver_tim4")]
-    RCC.apb1enr().modify(|v| v.set_tim4en(ENABLE));
+    apb1enr.modify(|v| v.set_tim4en(ENABLE));
     #[cfg(feature = "time_driver_tim5")]
-    RCC.apb1enr().modify(|v| v.set_tim5en(ENABLE));
+    apb1enr.modify(|v| v.set_tim5en(ENABLE));
     #[cfg(feature = "time_driver_tim8")]
     RCC.apb2enr().modify(|v| v.set_tim8en(ENABLE));
     #[cfg(feature = "time_driver_tim9")]
     RCC.apb2enr().modify(|v| v.set_tim9en(ENABLE));
     #[cfg(feature = "time_driver_tim12")]
-    RCC.apb1enr().modify(|v| v.set_tim12en(ENABLE));
+    apb1enr.modify(|v| v.set_tim12en(ENABLE));
 
     // #[cfg(feature = "time_driver_tim15")]
 
@@ -611,8 +663,49 @@ fn enable_Timer() {
 }
 
 // by noah: here the period is shifted 15 bit because period will be increased when the counter is overflowed or half-overflowed
-fn calc_now(period: INT32U, counter: INT16U) -> INT64U {
-    ((period as INT64U) << 15) + ((counter as u32 ^ ((period & 1) << 15)) as u64)
+// (31 bit for a 32-bit timer)
+fn calc_now(period: INT32U, counter: INT32U) -> INT64U {
+    ((period as INT64U) << PERIOD_SHIFT) + ((counter ^ ((period & 1) << PERIOD_SHIFT)) as u64)
 }
 
-// Old get_APBfreq() implementation removed - now using HAL library configuration
\ No newline at end of file
+// The 32-bit timers have plain u32 CNT/ARR/CCR registers, the 16-bit ones have register types with a 16-bit field.
+
+#[cfg(any(feature = "time_driver_tim2", feature = "time_driver_tim5"))]
+fn read_cnt() -> INT32U {
+    TIMER.cnt().read()
+}
+
+#[cfg(any(feature = "time_driver_tim2", feature = "time_driver_tim5"))]
+fn write_cnt(value: INT32U) {
+    TIMER.cnt().write_value(value);
+}
+
+#[cfg(any(feature = "time_driver_tim2", feature = "time_driver_tim5"))]
+fn write_arr_max() {
+    TIMER.arr().write_value(INT32U::MAX);
+}
+
+#[cfg(any(feature = "time_driver_tim2", feature = "time_driver_tim5"))]
+fn write_ccr(n: USIZE, value: INT32U) {
+    TIMER.ccr(n).write_value(value);
+}
+
+#[cfg(not(any(feature = "time_driver_tim2", feature = "time_driver_tim5")))]
+fn read_cnt() -> INT32U {
+    TIMER.cnt().read().cnt() as INT32U
+}
+
+#[cfg(not(any(feature = "time_driver_tim2", feature = "time_driver_tim5")))]
+fn write_cnt(value: INT32U) {
+    TIMER.cnt().write(|w| w.set_cnt(value as INT16U));
+}
+
+#[cfg(not(any(feature = "time_driver_tim2", feature = "time_driver_tim5")))]
+fn write_arr_max() {
+    TIMER.arr().write(|w| w.set_arr(INT16U::MAX));
+}
+
+#[cfg(not(any(feature = "time_driver_tim2", feature = "time_driver_tim5")))]
+fn write_ccr(n: USIZE, value: INT32U) {
+    TIMER.ccr(n).write(|w| w.set_ccr(value as INT16U));
+}
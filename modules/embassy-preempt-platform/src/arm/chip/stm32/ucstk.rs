@@ -0,0 +1,26 @@
+/// The context of a preempted task, see `crate::arm::armv7m`
+///
+/// With the `cortex-m4f` feature, a task switched out with an active FP context also has S16-S31 saved between R14
+/// and R0, and the hardware extends the exception frame with S0-S15 and FPSCR.
+#[repr(C, align(4))]
+pub struct UcStk {
+    // below are the remaining part of the task's context
+    pub r4: u32,
+    pub r5: u32,
+    pub r6: u32,
+    pub r7: u32,
+    pub r8: u32,
+    pub r9: u32,
+    pub r10: u32,
+    pub r11: u32,
+    pub r14: u32,
+    // below are stored when the interrupt occurs
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
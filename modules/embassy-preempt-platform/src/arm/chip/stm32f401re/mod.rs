@@ -1,5 +0,0 @@
-mod ucstk;
-mod platform;
-pub mod timer_driver;
-
-pub use platform::{PlatformImpl};
\ No newline at end of file
@@ -0,0 +1,94 @@
+//! Context switch pieces shared by the Cortex-M profiles
+//!
+//! A preempted task's stack holds the hardware exception frame (R0-R3, R12, LR, PC, xPSR) with R4-R11 and R14
+//! (EXC_RETURN) saved below it by the PendSV handler. Saving and restoring that part needs different instructions on
+//! ARMv6-M and ARMv7-M, see `armv6m` and `armv7m`; the rest is here.
+
+use core::ptr::NonNull;
+
+/// Interrupt Control and State Register
+const ICSR: u32 = 0xE000_ED04;
+/// PendSV set-pending bit of ICSR
+const ICSR_PENDSVSET: u32 = 1 << 28;
+/// EXC_RETURN: return to Thread mode with the PSP and a basic (non-FP) exception frame
+pub const EXC_RETURN_THREAD_PSP: u32 = 0xFFFF_FFFD;
+/// R4-R11 and R14 saved below the hardware frame (R0-R3, R12, LR, PC, xPSR)
+pub const CONTEXT_STACK_SIZE: usize = 17;
+
+// PendSV jumps to the kernel's context switch, LR still holds the EXC_RETURN of the interrupted task
+core::arch::global_asm!(
+    ".section .text.PendSV, \"ax\"",
+    ".global PendSV",
+    ".type PendSV, %function",
+    ".thumb_func",
+    "PendSV:",
+    "LDR     R0, =__ContextSwitchHandler",
+    "BX      R0",
+    ".ltorg",
+);
+
+/// Pend PendSV, which runs the context switch.
+#[inline(always)]
+pub fn trigger_context_switch() {
+    unsafe {
+        (ICSR as *mut u32).write_volatile(ICSR_PENDSVSET);
+    }
+}
+
+/// Set the PSP.
+#[inline(always)]
+pub fn set_program_stack_pointer(sp: *mut u8) {
+    unsafe {
+        cortex_m::register::psp::write(sp as u32);
+    }
+}
+
+/// Read the PSP.
+#[inline(always)]
+pub unsafe fn get_current_stack_pointer() -> *mut usize {
+    cortex_m::register::psp::read() as *mut usize
+}
+
+/// Build the initial context of a task on the stack ending at `stk_ref`: a hardware exception frame returning to
+/// `executor_function` in Thumb state, with R4-R11 and R14 (EXC_RETURN) below it.
+///
+/// A new task has no FP context, so it always starts from the basic frame.
+pub fn init_task_stack(stk_ref: NonNull<usize>, executor_function: fn()) -> NonNull<usize> {
+    scheduler_log!(trace, "init_task_stack");
+    let executor_function_ptr = executor_function as *const () as usize;
+    scheduler_log!(info, "the executor function ptr is 0x{:x}", executor_function_ptr);
+
+    // Get stack pointer and align to 8-byte boundary
+    let ptos = stk_ref.as_ptr();
+    let ptos = ((unsafe { ptos.offset(1) } as usize) & 0xFFFFFFF8) as *mut usize;
+    // Reserve space for the context frame
+    let ptos = unsafe { ptos.sub(CONTEXT_STACK_SIZE) };
+
+    let frame: [usize; CONTEXT_STACK_SIZE] = [
+        // R4-R11
+        0x04040404,
+        0x05050505,
+        0x06060606,
+        0x07070707,
+        0x08080808,
+        0x09090909,
+        0x10101010,
+        0x11111111,
+        // R14: return to Thread mode, PSP
+        EXC_RETURN_THREAD_PSP as usize,
+        // R0-R3, R12, LR
+        0,
+        0x01010101,
+        0x02020202,
+        0x03030303,
+        0x12121212,
+        0,
+        // PC: task entry point
+        executor_function_ptr,
+        // xPSR: T-bit set for Thumb mode
+        0x01000000,
+    ];
+    unsafe { ptos.cast::<[usize; CONTEXT_STACK_SIZE]>().write(frame) };
+
+    NonNull::new(ptos).unwrap()
+}
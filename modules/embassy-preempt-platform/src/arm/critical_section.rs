@@ -0,0 +1,102 @@
+//! The `critical-section` implementation of the Cortex-M platforms
+//!
+//! With `OS_KERNEL_INTERRUPT_PRIO` at 0 a critical section masks every interrupt (PRIMASK). Otherwise (ARMv7-M
+//! only) it raises BASEPRI to `OS_KERNEL_INTERRUPT_PRIO`: the interrupts above it keep running, with no latency added
+//! by the kernel, and must never call the kernel. With `debug_assertions`, entering a critical section from such an
+//! interrupt panics, since the section would not exclude it.
+//!
+//! The restore state is a bool, so BASEPRI goes back to 0 when the outermost section ends: the kernel owns BASEPRI.
+
+use core::arch::asm;
+#[cfg(feature = "armv7m")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use critical_section::{set_impl, Impl, RawRestoreState};
+use embassy_preempt_cfg::OS_KERNEL_INTERRUPT_PRIO;
+
+#[cfg(feature = "armv6m")]
+const _: () = assert!(OS_KERNEL_INTERRUPT_PRIO == 0, "ARMv6-M has no BASEPRI: kernel.kernel_interrupt_prio must be 0");
+
+/// NVIC interrupt priority registers, one byte per interrupt
+const NVIC_IPR: usize = 0xE000_E400;
+/// System handler priority registers, one byte per exception from MemManage (4)
+const SCB_SHPR: usize = 0xE000_ED18;
+
+struct KernelCriticalSection;
+set_impl!(KernelCriticalSection);
+
+unsafe impl Impl for KernelCriticalSection {
+    unsafe fn acquire() -> RawRestoreState {
+        #[cfg(feature = "armv7m")]
+        if OS_KERNEL_INTERRUPT_PRIO != 0 {
+            #[cfg(debug_assertions)]
+            check_kernel_interrupt_prio();
+
+            let basepri: u32;
+            asm!("MRS {}, BASEPRI", out(reg) basepri, options(nomem, nostack, preserves_flags));
+            // BASEPRI_MAX only ever raises the masking level
+            asm!("MSR BASEPRI_MAX, {}", in(reg) OS_KERNEL_INTERRUPT_PRIO as u32, options(nomem, nostack, preserves_flags));
+            return basepri == 0;
+        }
+
+        let primask: u32;
+        asm!("MRS {}, PRIMASK", out(reg) primask, options(nomem, nostack, preserves_flags));
+        asm!("CPSID I", options(nomem, nostack, preserves_flags));
+        // PRIMASK was clear: interrupts were enabled
+        primask & 1 == 0
+    }
+
+    unsafe fn release(was_active: RawRestoreState) {
+        // Only unmask if this is the outermost critical section
+        if !was_active {
+            return;
+        }
+        #[cfg(feature = "armv7m")]
+        if OS_KERNEL_INTERRUPT_PRIO != 0 {
+            asm!("MSR BASEPRI, {}", in(reg) 0u32, options(nomem, nostack, preserves_flags));
+            return;
+        }
+        asm!("CPSIE I", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// The current exception number (IPSR), 0 in Thread mode
+#[inline(always)]
+pub fn active_exception() -> u32 {
+    let ipsr: u32;
+    unsafe { asm!("MRS {}, IPSR", out(reg) ipsr, options(nomem, nostack, preserves_flags)) };
+    ipsr & 0x1FF
+}
+
+/// The priority byte of exception `exception`, `None` for Reset, NMI and HardFault, whose priority is fixed
+#[cfg(feature = "armv7m")]
+pub fn exception_priority(exception: u32) -> Option<u8> {
+    match exception {
+        0..=3 => None,
+        4..=15 => Some(unsafe { ((SCB_SHPR + exception as usize - 4) as *const u8).read_volatile() }),
+        _ => Some(unsafe { ((NVIC_IPR + exception as usize - 16) as *const u8).read_volatile() }),
+    }
+}
+
+/// set once the check has failed, so that the panic handler's own critical sections do not fail it again
+#[cfg(feature = "armv7m")]
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Panic if the running interrupt is above `OS_KERNEL_INTERRUPT_PRIO`, i.e. it must not call the kernel
+#[cfg(feature = "armv7m")]
+#[inline]
+pub fn check_kernel_interrupt_prio() {
+    if OS_KERNEL_INTERRUPT_PRIO == 0 || REPORTED.load(Ordering::Relaxed) {
+        return;
+    }
+    let exception = active_exception();
+    if let Some(prio) = exception_priority(exception) {
+        if prio < OS_KERNEL_INTERRUPT_PRIO {
+            REPORTED.store(true, Ordering::Relaxed);
+            panic!(
+                "exception {} (priority {}) is above OS_KERNEL_INTERRUPT_PRIO ({}) and must not call the kernel",
+                exception, prio, OS_KERNEL_INTERRUPT_PRIO
+            );
+        }
+    }
+}
@@ -1,50 +1,72 @@
 use core::{cell::UnsafeCell, sync::atomic::{AtomicBool, Ordering}};
 
+use cortex_m::peripheral::NVIC;
 use critical_section::Mutex;
-use stm32f4xx_hal::{gpio::{ExtiPin, Pin}, pac::{self, EXTI, NVIC}, rcc::Rcc, syscfg::SysCfg};
+use spin::Once;
+use stm32_metapac::gpio::vals::{Idr, Moder, Pupdr};
+use stm32_metapac::{Interrupt, EXTI, GPIOC, RCC, SYSCFG};
 
-use crate::get_platform;
+use crate::isr;
 
+/// the button pin, PC13
+const PIN: usize = 13;
+/// the EXTICR port number of GPIOC
+const PORT_C: u8 = 2;
 
-/// Button driver using HAL GPIO but manual EXTI configuration
+static BUTTON: Once<Button> = Once::new();
+
+/// The user button (B1), configured on first use
+pub fn button() -> &'static Button {
+    BUTTON.call_once(Button::new)
+}
+
+/// Button driver on PC13 with the EXTI interrupt on its falling edge
+///
+/// B1 pulls PC13 low when pressed, the board has the pull-up.
 pub struct Button {
-    pin: UnsafeCell<Pin<'C', 13>>,
     pressed: AtomicBool,
     waker: Mutex<UnsafeCell<Option<core::task::Waker>>>,
 }
 
 impl Button {
     /// Create a new button driver using PC13
-    pub fn new(rcc: &mut Rcc, exti: &mut EXTI, nvic: &mut NVIC, syscfg: &mut SysCfg, pc13: Pin<'C', 13>) -> Self {
-
+    fn new() -> Self {
         // Enable GPIOC clock
-        rcc.ahb1enr().modify(|_r, w| w.gpiocen().enabled());
+        #[cfg(feature = "stm32f4")]
+        RCC.ahb1enr().modify(|w| w.set_gpiocen(true));
+        #[cfg(feature = "stm32g4")]
+        RCC.ahb2enr().modify(|w| w.set_gpiocen(true));
 
         // Enable SYSCFG clock for EXTI
-        rcc.apb2enr().modify(|_r, w| w.syscfgen().enabled());
+        RCC.apb2enr().modify(|w| w.set_syscfgen(true));
 
-        // Configure PC13 as input with pull-down
-        let mut pin = pc13.into_pull_down_input();
+        // Configure PC13 as a floating input
+        GPIOC.moder().modify(|w| w.set_moder(PIN, Moder::INPUT));
+        GPIOC.pupdr().modify(|w| w.set_pupdr(PIN, Pupdr::FLOATING));
 
-        pin.make_interrupt_source(syscfg);
-        pin.trigger_on_edge(exti, stm32f4xx_hal::gpio::Edge::Falling);
-        pin.enable_interrupt(exti);
+        // Route PC13 to EXTI13, on the falling edge
+        SYSCFG.exticr(PIN / 4).modify(|w| w.set_exti(PIN % 4, PORT_C));
+        EXTI.ftsr(0).modify(|w| w.set_line(PIN, true));
+        EXTI.rtsr(0).modify(|w| w.set_line(PIN, false));
+        EXTI.pr(0).write(|w| w.set_line(PIN, true));
+        EXTI.imr(0).modify(|w| w.set_line(PIN, true));
 
+        // the same as the time base: the handler calls the kernel, so it must not be above OS_KERNEL_INTERRUPT_PRIO
         unsafe {
-            nvic.set_priority(pac::interrupt::EXTI15_10, 1);
-            NVIC::unmask(pac::Interrupt::EXTI15_10);
+            let mut nvic = cortex_m::Peripherals::steal().NVIC;
+            nvic.set_priority(Interrupt::EXTI15_10, 32);
+            NVIC::unmask(Interrupt::EXTI15_10);
         }
 
         Self {
-            pin: UnsafeCell::new(pin),
             pressed: AtomicBool::new(false),
             waker: Mutex::new(UnsafeCell::new(None)),
         }
     }
 
-    /// Check if button is currently pressed (high level)
+    /// Check if button is currently pressed (low level)
     pub fn is_pressed(&self) -> bool {
-        unsafe{(*self.pin.get()).is_high()}
+        GPIOC.idr().read().idr(PIN) == Idr::LOW
     }
 
     /// Get the button state and reset if pressed
@@ -68,12 +90,15 @@ impl Button {
 }
 
 /// EXTI15_10 interrupt handler
+///
+/// Bracketed by `OSIntEnter()`/`OSIntExit()`, so the task waiting for the button runs as soon as the handler returns.
 #[no_mangle]
 pub unsafe extern "C" fn EXTI15_10() {
     os_log!(info, "click");
-    // let button = BUTTON.as_mut().unwrap();
+    isr::int_enter();
     critical_section::with(|cs| {
-        let button = get_platform().button.borrow(cs);
+        EXTI.pr(0).write(|w| w.set_line(PIN, true));
+        let button = button();
         let waker = button.waker.borrow(cs).get();
         let waker = (*waker).take();
         if let Some(waker) = waker {
@@ -82,9 +107,6 @@ pub unsafe extern "C" fn EXTI15_10() {
             os_log!(info, "none");
         }
         button.set_pressed();
-        let pin = button.pin.get();
-        unsafe {
-            (*pin).clear_interrupt_pending_bit();
-        }
     });
-}
\ No newline at end of file
+    isr::int_exit();
+}
@@ -1,7 +1,7 @@
 use core::task::{Context, Poll};
 use core::pin::Pin;
 
-use crate::get_platform;
+use super::driver::button;
 
 /// Future that completes when button is pressed
 pub struct ButtonFuture {
@@ -28,8 +28,8 @@ impl core::future::Future for ButtonFuture {
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         
-        critical_section::with(|cs| {
-            let button = get_platform().button.borrow(cs);
+        critical_section::with(|_| {
+            let button = button();
             // First poll - register waker
             if self.yielded_once {
                 os_log!(info, "ButtonFuture::poll: yielded once");
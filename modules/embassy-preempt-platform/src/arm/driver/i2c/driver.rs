@@ -0,0 +1,262 @@
+use core::future::poll_fn;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use cortex_m::peripheral::NVIC;
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource, Operation};
+use stm32_metapac::gpio::vals::{Moder, Ot, Pupdr};
+use stm32_metapac::{Interrupt, GPIOB, I2C1, RCC};
+
+#[cfg(feature = "stm32f4")]
+use super::v1 as family;
+#[cfg(feature = "stm32g4")]
+use super::v2 as family;
+use crate::driver::timeout;
+use crate::driver::waker::WakerSlot;
+use crate::{blocking, chip, isr};
+
+/// the alternate function of the I2C1 pins on port B
+const AF_I2C1: u8 = 4;
+const SCL_PIN: usize = 8;
+const SDA_PIN: usize = 9;
+
+/// I2C configuration
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// SCL frequency in Hz: standard mode up to 100 kHz, fast mode up to 400 kHz
+    pub frequency: u32,
+    /// how long a transaction may take before it fails with [`Error::Timeout`], in microseconds
+    pub timeout_us: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: 100_000,
+            timeout_us: 100_000,
+        }
+    }
+}
+
+/// An I2C error, the bus is released before it is returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A misplaced start or stop condition
+    Bus,
+    /// Another master won the bus
+    Arbitration,
+    /// The address or a data byte was not acknowledged
+    Nack(NoAcknowledgeSource),
+    /// A received byte was lost
+    Overrun,
+    /// The transaction did not complete within `Config::timeout_us`, the peripheral has been reset
+    Timeout,
+}
+
+impl embedded_hal::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Bus => ErrorKind::Bus,
+            Error::Arbitration => ErrorKind::ArbitrationLoss,
+            Error::Nack(source) => ErrorKind::NoAcknowledge(source),
+            Error::Overrun => ErrorKind::Overrun,
+            Error::Timeout => ErrorKind::Other,
+        }
+    }
+}
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+/// the task waiting on I2C1, woken by its event and error interrupts
+static WAKER: WakerSlot = WakerSlot::new();
+
+/// I2C1 master on PB8 (SCL) and PB9 (SDA), the Arduino D15/D14 pins of the Nucleo-64 boards
+///
+/// The driver owns the bus: tasks share it through a mutex, such as the devices of `embedded-hal-bus`. Must be
+/// called after the platform initialization, which sets up the clocks.
+///
+/// # Panics
+/// If I2C1 has already been taken, or `config.frequency` cannot be reached from the APB1 clock
+pub fn i2c1(config: Config) -> I2c {
+    assert!(!TAKEN.swap(true, Ordering::AcqRel), "I2C1 is already taken");
+
+    #[cfg(feature = "stm32f4")]
+    {
+        RCC.ahb1enr().modify(|w| w.set_gpioben(true));
+        RCC.apb1enr().modify(|w| w.set_i2c1en(true));
+    }
+    #[cfg(feature = "stm32g4")]
+    {
+        RCC.ahb2enr().modify(|w| w.set_gpioben(true));
+        RCC.apb1enr1().modify(|w| w.set_i2c1en(true));
+    }
+
+    // open drain, with the weak pull-ups in case the board has none
+    for pin in [SCL_PIN, SDA_PIN] {
+        GPIOB.otyper().modify(|w| w.set_ot(pin, Ot::OPENDRAIN));
+        GPIOB.pupdr().modify(|w| w.set_pupdr(pin, Pupdr::PULLUP));
+        GPIOB.afr(pin / 8).modify(|w| w.set_afr(pin % 8, AF_I2C1));
+        GPIOB.moder().modify(|w| w.set_moder(pin, Moder::ALTERNATE));
+    }
+
+    let i2c = I2c { config };
+    i2c.reset();
+
+    // the same as the time base: the handlers call the kernel, so they must not be above OS_KERNEL_INTERRUPT_PRIO
+    unsafe {
+        let mut nvic = cortex_m::Peripherals::steal().NVIC;
+        for interrupt in [Interrupt::I2C1_EV, Interrupt::I2C1_ER] {
+            nvic.set_priority(interrupt, 32);
+            NVIC::unmask(interrupt);
+        }
+    }
+
+    i2c
+}
+
+/// Interrupt-driven I2C master
+///
+/// The task drives the transfer and waits for each bus event with its interrupt enabled; the interrupt handlers
+/// disable it again and wake the task, bracketed by `OSIntEnter()`/`OSIntExit()`. The whole transaction has a
+/// deadline of `Config::timeout_us`, on the kernel timer queue. Async tasks use the `embedded_hal_async` trait, sync
+/// tasks the blocking `embedded_hal` trait, which leaves the ready list while waiting.
+pub struct I2c {
+    config: Config,
+}
+
+/// Resets the peripheral if the transaction is dropped or fails halfway
+struct ResetOnDrop<'a>(&'a I2c);
+
+impl Drop for ResetOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.reset();
+    }
+}
+
+impl I2c {
+    /// Reset the peripheral and set it up from the configuration
+    fn reset(&self) {
+        family::init(I2C1, chip::clocks().pclk1, self.config.frequency);
+    }
+
+    async fn transaction_async(&self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        let deadline = timeout::deadline_after_us(self.config.timeout_us);
+        let guard = ResetOnDrop(self);
+        let result = transfer(address, operations, deadline).await;
+        // a NACK ends the transaction with a stop condition, the peripheral is ready for the next one
+        if let Ok(()) | Err(Error::Nack(_)) = result {
+            mem::forget(guard);
+        }
+        result
+    }
+}
+
+/// Run `operations`, each run of adjacent reads or writes is one transfer after a (repeated) start condition
+async fn transfer(address: u8, operations: &mut [Operation<'_>], deadline: u64) -> Result<(), Error> {
+    let mut start = 0;
+    while start < operations.len() {
+        let read = matches!(operations[start], Operation::Read(_));
+        let end = start
+            + operations[start..]
+                .iter()
+                .take_while(|op| matches!(op, Operation::Read(_)) == read)
+                .count();
+        let first = start == 0;
+        let last = end == operations.len();
+        let segment = &mut operations[start..end];
+        let len = segment
+            .iter()
+            .map(|op| match op {
+                Operation::Read(buf) => buf.len(),
+                Operation::Write(buf) => buf.len(),
+            })
+            .sum();
+        if read {
+            let bytes = segment.iter_mut().flat_map(|op| match op {
+                Operation::Read(buf) => buf.iter_mut(),
+                Operation::Write(_) => Default::default(),
+            });
+            family::read(I2C1, address, bytes, len, first, last, deadline).await?;
+        } else {
+            let bytes = segment
+                .iter()
+                .flat_map(|op| match op {
+                    Operation::Write(buf) => buf.iter(),
+                    Operation::Read(_) => Default::default(),
+                })
+                .copied();
+            family::write(I2C1, address, bytes, len, first, last, deadline).await?;
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// Wait until `check` returns a result, with the interrupts that change it enabled by `listen`, or until `deadline`
+///
+/// `check` and `listen` run in a critical section, so an interrupt cannot come in between and be missed.
+pub(super) async fn wait_for<T>(
+    deadline: u64,
+    mut check: impl FnMut() -> Option<Result<T, Error>>,
+    listen: impl Fn(),
+) -> Result<T, Error> {
+    poll_fn(|cx| {
+        critical_section::with(|_| {
+            if let Some(result) = check() {
+                return Poll::Ready(result);
+            }
+            if timeout::expired(deadline) {
+                return Poll::Ready(Err(Error::Timeout));
+            }
+            WAKER.register(cx.waker());
+            timeout::schedule_wake(deadline, cx.waker());
+            listen();
+            Poll::Pending
+        })
+    })
+    .await
+}
+
+/// Busy-wait for `done`, for the few bit times of a stop condition
+pub(super) fn spin_until(deadline: u64, mut done: impl FnMut() -> bool) -> Result<(), Error> {
+    while !done() {
+        if timeout::expired(deadline) {
+            return Err(Error::Timeout);
+        }
+    }
+    Ok(())
+}
+
+impl embedded_hal::i2c::ErrorType for I2c {
+    type Error = Error;
+}
+
+impl embedded_hal::i2c::I2c for I2c {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        blocking::block_on(self.transaction_async(address, operations))
+    }
+}
+
+impl embedded_hal_async::i2c::I2c for I2c {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        self.transaction_async(address, operations).await
+    }
+}
+
+/// I2C1 event interrupt handler
+#[no_mangle]
+pub unsafe extern "C" fn I2C1_EV() {
+    isr::int_enter();
+    family::on_interrupt(I2C1);
+    WAKER.wake();
+    isr::int_exit();
+}
+
+/// I2C1 error interrupt handler
+#[no_mangle]
+pub unsafe extern "C" fn I2C1_ER() {
+    isr::int_enter();
+    family::on_interrupt(I2C1);
+    WAKER.wake();
+    isr::int_exit();
+}
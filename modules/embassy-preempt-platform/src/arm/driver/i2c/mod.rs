@@ -0,0 +1,5 @@
+pub mod driver;
+#[cfg(feature = "stm32f4")]
+mod v1;
+#[cfg(feature = "stm32g4")]
+mod v2;
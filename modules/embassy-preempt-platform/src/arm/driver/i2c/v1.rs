@@ -0,0 +1,226 @@
+//! The I2C peripheral of the STM32F4 (`i2c_v1`)
+//!
+//! The master steps through the events of SR1 one at a time: the start bit (SB), the address (ADDR), then each byte
+//! (TXE/RXNE, BTF). The reception of the last bytes follows the reference manual: ACK is cleared and the stop or
+//! repeated start is requested while the bus is stretched on BTF, so a task switched out between two events cannot
+//! make the master receive too many bytes.
+
+use embedded_hal::i2c::NoAcknowledgeSource;
+use stm32_metapac::i2c::regs::Sr1;
+use stm32_metapac::i2c::vals::{Duty, FS};
+use stm32_metapac::i2c::I2c as Regs;
+
+use super::driver::{spin_until, wait_for, Error};
+
+/// Reset the peripheral and set the SCL timings for `frequency` from the APB1 clock
+pub(super) fn init(regs: Regs, pclk1: u32, frequency: u32) {
+    let freq_mhz = pclk1 / 1_000_000;
+    assert!((2..=50).contains(&freq_mhz), "the APB1 clock must be 2-50 MHz for I2C");
+    assert!(frequency <= 400_000, "I2C frequency {} is above the fast mode", frequency);
+
+    // also clears the interrupt enables
+    regs.cr1().write(|w| w.set_swrst(true));
+    regs.cr1().write(|w| w.set_swrst(false));
+
+    regs.cr2().write(|w| w.set_freq(freq_mhz as u8));
+    if frequency <= 100_000 {
+        // T_high = T_low = CCR * T_pclk1
+        regs.ccr().write(|w| {
+            w.set_f_s(FS::STANDARD);
+            w.set_ccr((pclk1 / (2 * frequency)).max(4) as u16);
+        });
+        // 1000 ns maximum rise time
+        regs.trise().write(|w| w.set_trise(freq_mhz as u8 + 1));
+    } else {
+        // T_low = 2 * T_high = 2 * CCR * T_pclk1
+        regs.ccr().write(|w| {
+            w.set_f_s(FS::FAST);
+            w.set_duty(Duty::DUTY2_1);
+            w.set_ccr((pclk1 / (3 * frequency)).max(1) as u16);
+        });
+        // 300 ns maximum rise time
+        regs.trise().write(|w| w.set_trise((freq_mhz * 300 / 1000) as u8 + 1));
+    }
+    regs.cr1().write(|w| w.set_pe(true));
+}
+
+/// Disable the interrupts, from the interrupt handlers
+pub(super) fn on_interrupt(regs: Regs) {
+    regs.cr2().modify(|w| {
+        w.set_itevten(false);
+        w.set_itbufen(false);
+        w.set_iterren(false);
+    });
+}
+
+/// Wait for the event of SR1 `flag`, TXE and RXNE need the buffer interrupt
+///
+/// A NACK requests a stop condition and fails with `nack` as the source.
+async fn wait(
+    regs: Regs,
+    flag: fn(&Sr1) -> bool,
+    buffer: bool,
+    nack: NoAcknowledgeSource,
+    deadline: u64,
+) -> Result<(), Error> {
+    wait_for(
+        deadline,
+        || {
+            let sr1 = regs.sr1().read();
+            let error = if sr1.af() {
+                Error::Nack(nack)
+            } else if sr1.arlo() {
+                Error::Arbitration
+            } else if sr1.berr() {
+                Error::Bus
+            } else if sr1.ovr() {
+                Error::Overrun
+            } else {
+                return flag(&sr1).then_some(Ok(()));
+            };
+            // the error flags are cleared by writing 0
+            regs.sr1().modify(|w| {
+                w.set_af(false);
+                w.set_arlo(false);
+                w.set_berr(false);
+                w.set_ovr(false);
+            });
+            if let Error::Nack(_) = error {
+                regs.cr1().modify(|w| w.set_stop(true));
+            }
+            Some(Err(error))
+        },
+        || {
+            regs.cr2().modify(|w| {
+                w.set_itevten(true);
+                w.set_itbufen(buffer);
+                w.set_iterren(true);
+            })
+        },
+    )
+    .await
+}
+
+/// Send the (repeated) start condition and the address, up to ADDR, which the caller clears
+///
+/// The repeated start of a segment that is not the first has been requested at the end of the previous one.
+async fn start(regs: Regs, address: u8, read: bool, first: bool, deadline: u64) -> Result<(), Error> {
+    if first {
+        // the stop condition ending the previous transaction may still be on the bus
+        spin_until(deadline, || !regs.cr1().read().stop())?;
+        regs.cr1().modify(|w| w.set_start(true));
+    }
+    wait(regs, Sr1::sb, false, NoAcknowledgeSource::Unknown, deadline).await?;
+    regs.dr().write(|w| w.set_dr((address << 1) | read as u8));
+    wait(regs, Sr1::addr, false, NoAcknowledgeSource::Address, deadline).await
+}
+
+/// Clear ADDR, which releases the bus
+fn clear_addr(regs: Regs) {
+    let _ = regs.sr1().read();
+    let _ = regs.sr2().read();
+}
+
+/// End the segment with a stop condition if it is the `last`, else request a repeated start
+fn end(regs: Regs, last: bool) {
+    regs.cr1().modify(|w| {
+        if last {
+            w.set_stop(true);
+        } else {
+            w.set_start(true);
+        }
+    });
+}
+
+/// Write the `len` bytes of `bytes` to `address`
+pub(super) async fn write(
+    regs: Regs,
+    address: u8,
+    bytes: impl Iterator<Item = u8>,
+    len: usize,
+    first: bool,
+    last: bool,
+    deadline: u64,
+) -> Result<(), Error> {
+    start(regs, address, false, first, deadline).await?;
+    clear_addr(regs);
+    for byte in bytes {
+        wait(regs, Sr1::txe, true, NoAcknowledgeSource::Data, deadline).await?;
+        regs.dr().write(|w| w.set_dr(byte));
+    }
+    if len > 0 {
+        wait(regs, Sr1::btf, false, NoAcknowledgeSource::Data, deadline).await?;
+    }
+    end(regs, last);
+    Ok(())
+}
+
+/// Read `len` bytes from `address` into `bytes`
+///
+/// The slave cannot be addressed for reading without sending a byte, an empty read receives one and drops it.
+pub(super) async fn read<'b>(
+    regs: Regs,
+    address: u8,
+    mut bytes: impl Iterator<Item = &'b mut u8>,
+    len: usize,
+    first: bool,
+    last: bool,
+    deadline: u64,
+) -> Result<(), Error> {
+    let mut store = |byte: u8| {
+        if let Some(slot) = bytes.next() {
+            *slot = byte;
+        }
+    };
+    match len {
+        0 | 1 => {
+            regs.cr1().modify(|w| w.set_ack(false));
+            start(regs, address, true, first, deadline).await?;
+            // the stop must be requested right after ADDR is cleared, before the byte is received
+            critical_section::with(|_| {
+                clear_addr(regs);
+                end(regs, last);
+            });
+            wait(regs, Sr1::rxne, true, NoAcknowledgeSource::Unknown, deadline).await?;
+            store(regs.dr().read().dr());
+        }
+        2 => {
+            // NACK the byte after the one in the shift register
+            regs.cr1().modify(|w| {
+                w.set_ack(true);
+                w.set_pos(true);
+            });
+            start(regs, address, true, first, deadline).await?;
+            critical_section::with(|_| {
+                clear_addr(regs);
+                regs.cr1().modify(|w| w.set_ack(false));
+            });
+            // both bytes received, the first in DR and the second in the shift register
+            wait(regs, Sr1::btf, false, NoAcknowledgeSource::Unknown, deadline).await?;
+            end(regs, last);
+            store(regs.dr().read().dr());
+            store(regs.dr().read().dr());
+            regs.cr1().modify(|w| w.set_pos(false));
+        }
+        _ => {
+            regs.cr1().modify(|w| w.set_ack(true));
+            start(regs, address, true, first, deadline).await?;
+            clear_addr(regs);
+            for _ in 0..len - 3 {
+                wait(regs, Sr1::rxne, true, NoAcknowledgeSource::Unknown, deadline).await?;
+                store(regs.dr().read().dr());
+            }
+            // byte N-2 in DR and N-1 in the shift register: NACK byte N
+            wait(regs, Sr1::btf, false, NoAcknowledgeSource::Unknown, deadline).await?;
+            regs.cr1().modify(|w| w.set_ack(false));
+            store(regs.dr().read().dr());
+            // byte N-1 in DR and N in the shift register
+            wait(regs, Sr1::btf, false, NoAcknowledgeSource::Unknown, deadline).await?;
+            end(regs, last);
+            store(regs.dr().read().dr());
+            wait(regs, Sr1::rxne, true, NoAcknowledgeSource::Unknown, deadline).await?;
+            store(regs.dr().read().dr());
+        }
+    }
+    Ok(())
+}
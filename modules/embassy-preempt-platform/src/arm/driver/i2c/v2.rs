@@ -0,0 +1,209 @@
+//! The I2C peripheral of the STM32G4 (`i2c_v2`)
+//!
+//! The master sends the start condition and the address by itself and counts the bytes of a segment in NBYTES, 255
+//! at a time (RELOAD). The end of each segment is left to software (AUTOEND off), which then sends a repeated start
+//! or a stop condition.
+
+use embedded_hal::i2c::NoAcknowledgeSource;
+use stm32_metapac::i2c::regs::Isr;
+use stm32_metapac::i2c::vals::{Addmode, Autoend, Dir, Reload};
+use stm32_metapac::i2c::I2c as Regs;
+
+use super::driver::{spin_until, wait_for, Error};
+
+/// the most bytes NBYTES counts before a reload
+const MAX_NBYTES: usize = 255;
+
+/// Reset the peripheral and set the SCL timings for `frequency` from the I2C kernel clock, PCLK1 after reset
+pub(super) fn init(regs: Regs, i2cclk: u32, frequency: u32) {
+    assert!(frequency <= 400_000, "I2C frequency {} is above the fast mode", frequency);
+    let fast = frequency > 100_000;
+
+    // PE must stay low for 3 APB cycles to reset the state machine, also clears the interrupt enables
+    regs.cr1().write(|w| w.set_pe(false));
+    while regs.cr1().read().pe() {}
+
+    // a prescaled clock of about 4 MHz in standard mode and 8 MHz in fast mode, as in the timing examples of the
+    // reference manual
+    let target_hz = if fast { 8_000_000 } else { 4_000_000 };
+    let presc = i2cclk.div_ceil(target_hz).clamp(1, 16);
+    let presc_hz = (i2cclk / presc) as u64;
+    let cycles = |ns: u64| (presc_hz * ns).div_ceil(1_000_000_000) as u32;
+    // data setup time plus the maximum rise time, and data hold time
+    let (setup, hold) = if fast { (cycles(100 + 300), cycles(250)) } else { (cycles(250 + 1000), cycles(500)) };
+    let period = (presc_hz / frequency as u64) as u32;
+    // fast mode needs a longer low than high period
+    let low = if fast { (period * 2).div_ceil(3) } else { period.div_ceil(2) };
+    let high = period - low;
+    assert!(
+        (1..=16).contains(&setup) && hold <= 15 && (1..=256).contains(&low) && (1..=256).contains(&high),
+        "I2C frequency {} cannot be reached from {} Hz",
+        frequency,
+        i2cclk
+    );
+
+    regs.timingr().write(|w| {
+        w.set_presc(presc as u8 - 1);
+        w.set_scldel(setup as u8 - 1);
+        w.set_sdadel(hold as u8);
+        w.set_scll((low - 1) as u8);
+        w.set_sclh((high - 1) as u8);
+    });
+    regs.cr1().write(|w| w.set_pe(true));
+}
+
+/// Disable the interrupts, from the interrupt handlers
+pub(super) fn on_interrupt(regs: Regs) {
+    regs.cr1().modify(|w| {
+        w.set_txie(false);
+        w.set_rxie(false);
+        w.set_tcie(false);
+        w.set_nackie(false);
+        w.set_errie(false);
+    });
+}
+
+/// The interrupt enables of a wait
+#[derive(Clone, Copy)]
+enum Event {
+    Tx,
+    Rx,
+    TransferComplete,
+}
+
+/// Wait for the ISR `flag`, the interrupt of `event` sets it
+///
+/// A NACK fails with `nack` as the source, once the stop condition the master sends by itself is on the bus.
+async fn wait(
+    regs: Regs,
+    flag: fn(&Isr) -> bool,
+    event: Event,
+    nack: NoAcknowledgeSource,
+    deadline: u64,
+) -> Result<(), Error> {
+    wait_for(
+        deadline,
+        || {
+            let isr = regs.isr().read();
+            if isr.nackf() {
+                let stopped = wait_stop(regs, deadline);
+                regs.icr().write(|w| w.set_nackcf(true));
+                // flush the byte the slave refused
+                regs.isr().write(|w| w.set_txe(true));
+                return Some(stopped.and(Err(Error::Nack(nack))));
+            }
+            if isr.arlo() || isr.berr() {
+                regs.icr().write(|w| {
+                    w.set_arlocf(true);
+                    w.set_berrcf(true);
+                });
+                return Some(Err(if isr.arlo() { Error::Arbitration } else { Error::Bus }));
+            }
+            flag(&isr).then_some(Ok(()))
+        },
+        || {
+            regs.cr1().modify(|w| {
+                match event {
+                    Event::Tx => w.set_txie(true),
+                    Event::Rx => w.set_rxie(true),
+                    Event::TransferComplete => w.set_tcie(true),
+                }
+                w.set_nackie(true);
+                w.set_errie(true);
+            })
+        },
+    )
+    .await
+}
+
+/// Send a stop condition
+fn stop(regs: Regs, deadline: u64) -> Result<(), Error> {
+    regs.cr2().modify(|w| w.set_stop(true));
+    wait_stop(regs, deadline)
+}
+
+/// Wait for the stop condition, it takes a few bit times
+fn wait_stop(regs: Regs, deadline: u64) -> Result<(), Error> {
+    let result = spin_until(deadline, || regs.isr().read().stopf());
+    regs.icr().write(|w| w.set_stopcf(true));
+    result
+}
+
+/// Send the (repeated) start condition and the address, for a segment of `len` bytes
+fn start(regs: Regs, address: u8, dir: Dir, len: usize) {
+    regs.cr2().write(|w| {
+        w.set_add10(Addmode::BIT7);
+        w.set_sadd((address as u16) << 1);
+        w.set_dir(dir);
+        w.set_nbytes(len.min(MAX_NBYTES) as u8);
+        w.set_reload(if len > MAX_NBYTES { Reload::NOTCOMPLETED } else { Reload::COMPLETED });
+        w.set_autoend(Autoend::SOFTWARE);
+        w.set_start(true);
+    });
+}
+
+/// Before byte `done` of a segment of `len` bytes, count the next bytes once NBYTES has run out
+async fn reload(regs: Regs, done: usize, len: usize, deadline: u64) -> Result<(), Error> {
+    if done == 0 || done % MAX_NBYTES != 0 {
+        return Ok(());
+    }
+    wait(regs, Isr::tcr, Event::TransferComplete, NoAcknowledgeSource::Data, deadline).await?;
+    let remaining = len - done;
+    regs.cr2().modify(|w| {
+        w.set_nbytes(remaining.min(MAX_NBYTES) as u8);
+        w.set_reload(if remaining > MAX_NBYTES { Reload::NOTCOMPLETED } else { Reload::COMPLETED });
+    });
+    Ok(())
+}
+
+/// End the segment once its bytes are on the bus, with a stop condition if it is the `last`
+///
+/// The next segment sends the repeated start itself.
+async fn end(regs: Regs, len: usize, last: bool, deadline: u64) -> Result<(), Error> {
+    let nack = if len == 0 { NoAcknowledgeSource::Address } else { NoAcknowledgeSource::Data };
+    wait(regs, Isr::tc, Event::TransferComplete, nack, deadline).await?;
+    if last {
+        stop(regs, deadline)?;
+    }
+    Ok(())
+}
+
+/// Write the `len` bytes of `bytes` to `address`
+pub(super) async fn write(
+    regs: Regs,
+    address: u8,
+    bytes: impl Iterator<Item = u8>,
+    len: usize,
+    _first: bool,
+    last: bool,
+    deadline: u64,
+) -> Result<(), Error> {
+    start(regs, address, Dir::WRITE, len);
+    for (done, byte) in bytes.enumerate() {
+        reload(regs, done, len, deadline).await?;
+        let nack = if done == 0 { NoAcknowledgeSource::Address } else { NoAcknowledgeSource::Data };
+        wait(regs, Isr::txis, Event::Tx, nack, deadline).await?;
+        regs.txdr().write(|w| w.set_txdata(byte));
+    }
+    end(regs, len, last, deadline).await
+}
+
+/// Read `len` bytes from `address` into `bytes`
+pub(super) async fn read<'b>(
+    regs: Regs,
+    address: u8,
+    bytes: impl Iterator<Item = &'b mut u8>,
+    len: usize,
+    _first: bool,
+    last: bool,
+    deadline: u64,
+) -> Result<(), Error> {
+    start(regs, address, Dir::READ, len);
+    for (done, byte) in bytes.enumerate() {
+        reload(regs, done, len, deadline).await?;
+        // only the address is acknowledged by the slave
+        wait(regs, Isr::rxne, Event::Rx, NoAcknowledgeSource::Address, deadline).await?;
+        *byte = regs.rxdr().read().rxdata();
+    }
+    end(regs, len, last, deadline).await
+}
@@ -1,43 +1,54 @@
-use core::cell::UnsafeCell;
-use stm32f4xx_hal::{gpio::{Pin, Output, PushPull}, rcc::Rcc};
+use spin::Once;
+use stm32_metapac::gpio::vals::{Moder, Odr, Ot};
+use stm32_metapac::{GPIOA, RCC};
+
+/// the LED pin, PA5
+const PIN: usize = 5;
+
+static LED: Once<Led> = Once::new();
+
+/// The user LED (LD2), configured on first use
+pub fn led() -> &'static Led {
+    LED.call_once(Led::new)
+}
 
 /// LED driver using PA5
 pub struct Led {
-    pin: UnsafeCell<Pin<'A', 5, Output<PushPull>>>,
+    _private: (),
 }
 
 impl Led {
     /// Create a new LED driver using PA5
-    pub fn new(rcc: &mut Rcc, pa5: Pin<'A', 5>) -> Self {
+    fn new() -> Self {
         // Enable GPIOA clock
-        rcc.ahb1enr().modify(|_r, w| w.gpioaen().enabled());
+        #[cfg(feature = "stm32f4")]
+        RCC.ahb1enr().modify(|w| w.set_gpioaen(true));
+        #[cfg(feature = "stm32g4")]
+        RCC.ahb2enr().modify(|w| w.set_gpioaen(true));
 
         // Configure PA5 as output push-pull
-        let pin = pa5.into_push_pull_output();
+        GPIOA.otyper().modify(|w| w.set_ot(PIN, Ot::PUSHPULL));
+        GPIOA.moder().modify(|w| w.set_moder(PIN, Moder::OUTPUT));
 
-        Self {
-            pin: UnsafeCell::new(pin),
-        }
+        Self { _private: () }
     }
 
     /// Turn LED on
     pub fn on(&self) {
-        unsafe {
-            (*self.pin.get()).set_high();
-        }
+        GPIOA.bsrr().write(|w| w.set_bs(PIN, true));
     }
 
     /// Turn LED off
     pub fn off(&self) {
-        unsafe {
-            (*self.pin.get()).set_low();
-        }
+        GPIOA.bsrr().write(|w| w.set_br(PIN, true));
     }
 
     /// Toggle LED state
     pub fn toggle(&self) {
-        unsafe {
-            (*self.pin.get()).toggle();
+        if GPIOA.odr().read().odr(PIN) == Odr::HIGH {
+            self.off();
+        } else {
+            self.on();
         }
     }
-}
\ No newline at end of file
+}
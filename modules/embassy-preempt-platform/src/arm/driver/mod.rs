@@ -1,2 +1,15 @@
+// the user button and LED of the Nucleo-64 boards (B1 on PC13, LD2 on PA5), not part of the platform
+#[cfg(feature = "nucleo")]
 pub mod button;
-pub mod led;
\ No newline at end of file
+#[cfg(feature = "nucleo")]
+pub mod led;
+// interrupt-driven I2C1 master, implementing the embedded-hal and embedded-hal-async I2c traits
+#[cfg(feature = "i2c")]
+pub mod i2c;
+// interrupt-driven USART1/USART2 with ring buffers, implementing embedded-io and embedded-io-async
+#[cfg(feature = "uart")]
+pub mod uart;
+#[cfg(any(feature = "uart", feature = "i2c"))]
+mod waker;
+#[cfg(feature = "i2c")]
+mod timeout;
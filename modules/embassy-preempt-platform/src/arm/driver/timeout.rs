@@ -0,0 +1,35 @@
+//! Driver timeouts on the kernel timer queue
+//!
+//! A driver future checks its deadline against the time base each time it is polled, and has the kernel wake its
+//! task at the deadline through the hook of the kernel `Timer`, so a task waiting on a peripheral that never answers
+//! is still woken.
+
+use core::task::Waker;
+
+use embassy_preempt_cfg::TICK_HZ;
+
+use crate::get_platform_trait;
+use crate::traits::timer::Driver;
+
+extern "Rust" {
+    fn _embassy_time_schedule_wake(at: u64, waker: &Waker);
+}
+
+/// The time base tick `us` microseconds from now
+pub(crate) fn deadline_after_us(us: u64) -> u64 {
+    get_platform_trait().get_timer_driver().now() + (us * TICK_HZ).div_ceil(1_000_000)
+}
+
+/// Whether the time base has reached `deadline`
+pub(crate) fn expired(deadline: u64) -> bool {
+    get_platform_trait().get_timer_driver().now() >= deadline
+}
+
+/// Wake the task owning `waker` at `deadline`
+///
+/// Nothing to do for the no-op waker of `blocking::block_on` outside a task, which polls in a loop.
+pub(crate) fn schedule_wake(deadline: u64, waker: &Waker) {
+    if !waker.will_wake(Waker::noop()) {
+        unsafe { _embassy_time_schedule_wake(deadline, waker) }
+    }
+}
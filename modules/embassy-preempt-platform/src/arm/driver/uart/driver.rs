@@ -0,0 +1,382 @@
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::Poll;
+
+use cortex_m::peripheral::NVIC;
+use spin::Once;
+use stm32_metapac::gpio::vals::Moder;
+use stm32_metapac::usart::Usart;
+use stm32_metapac::{Interrupt, GPIOA, RCC, USART1, USART2};
+
+use super::ring_buffer::RingBuffer;
+use crate::driver::waker::WakerSlot;
+use crate::{blocking, chip, isr};
+
+/// the size of each ring buffer, in bytes
+const BUF_SIZE: usize = 256;
+/// the alternate function of the USART1 and USART2 pins on port A
+const AF_USART: u8 = 7;
+
+const ERR_OVERRUN: u8 = 1 << 0;
+const ERR_FRAMING: u8 = 1 << 1;
+const ERR_NOISE: u8 = 1 << 2;
+
+/// UART configuration: 8 data bits, no parity, 1 stop bit
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub baudrate: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { baudrate: 115_200 }
+    }
+}
+
+/// A receive error, reported once by the next read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Bytes were lost: the receive buffer was full, or a byte came in before the previous one was read
+    Overrun,
+    /// A stop bit was missing
+    Framing,
+    /// Noise was detected on a received byte
+    Noise,
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+/// The buffers and wakers shared by a UART and its interrupt handler
+struct State {
+    rx: RingBuffer<BUF_SIZE>,
+    tx: RingBuffer<BUF_SIZE>,
+    rx_waker: WakerSlot,
+    tx_waker: WakerSlot,
+    /// `ERR_*` bits recorded by the interrupt handler
+    errors: AtomicU8,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+            rx_waker: WakerSlot::new(),
+            tx_waker: WakerSlot::new(),
+            errors: AtomicU8::new(0),
+        }
+    }
+
+    /// Take one of the recorded errors
+    fn take_error(&self) -> Option<Error> {
+        let errors = self.errors.load(Ordering::Relaxed);
+        let (bit, error) = if errors & ERR_OVERRUN != 0 {
+            (ERR_OVERRUN, Error::Overrun)
+        } else if errors & ERR_FRAMING != 0 {
+            (ERR_FRAMING, Error::Framing)
+        } else if errors & ERR_NOISE != 0 {
+            (ERR_NOISE, Error::Noise)
+        } else {
+            return None;
+        };
+        self.errors.fetch_and(!bit, Ordering::Relaxed);
+        Some(error)
+    }
+}
+
+static USART1_STATE: State = State::new();
+static USART2_STATE: State = State::new();
+static USART1_UART: Once<Uart> = Once::new();
+static USART2_UART: Once<Uart> = Once::new();
+
+/// USART1 on PA9 (TX) and PA10 (RX), configured from `config` on first use
+///
+/// Must be called after the platform initialization, which sets up the clocks; `config` is ignored afterwards.
+pub fn usart1(config: &Config) -> &'static Uart {
+    USART1_UART.call_once(|| {
+        RCC.apb2enr().modify(|w| w.set_usart1en(true));
+        set_pins(9, 10);
+        Uart::new(USART1, Interrupt::USART1, &USART1_STATE, chip::clocks().pclk2, config)
+    })
+}
+
+/// USART2 on PA2 (TX) and PA3 (RX), the ST-LINK virtual COM port of the Nucleo-64 STM32F4 boards, configured from
+/// `config` on first use
+///
+/// Must be called after the platform initialization, which sets up the clocks; `config` is ignored afterwards.
+pub fn usart2(config: &Config) -> &'static Uart {
+    USART2_UART.call_once(|| {
+        #[cfg(feature = "stm32f4")]
+        RCC.apb1enr().modify(|w| w.set_usart2en(true));
+        #[cfg(feature = "stm32g4")]
+        RCC.apb1enr1().modify(|w| w.set_usart2en(true));
+        set_pins(2, 3);
+        Uart::new(USART2, Interrupt::USART2, &USART2_STATE, chip::clocks().pclk1, config)
+    })
+}
+
+/// Route the `tx` and `rx` pins of port A to the USART
+fn set_pins(tx: usize, rx: usize) {
+    #[cfg(feature = "stm32f4")]
+    RCC.ahb1enr().modify(|w| w.set_gpioaen(true));
+    #[cfg(feature = "stm32g4")]
+    RCC.ahb2enr().modify(|w| w.set_gpioaen(true));
+
+    for pin in [tx, rx] {
+        GPIOA.afr(pin / 8).modify(|w| w.set_afr(pin % 8, AF_USART));
+        GPIOA.moder().modify(|w| w.set_moder(pin, Moder::ALTERNATE));
+    }
+}
+
+/// Interrupt-driven UART
+///
+/// The interrupt handler moves the bytes between the data register and a receive and a transmit ring buffer, and
+/// wakes the task waiting on them; the handler is bracketed by `OSIntEnter()`/`OSIntExit()`, so a higher priority
+/// task woken by it runs as soon as it returns. Async tasks use the `embedded_io_async` traits, sync tasks the
+/// blocking `embedded_io` traits, which leave the ready list while waiting.
+///
+/// Every task gets the same UART. Tasks reading, or writing and flushing, at the same time contend for the waker of
+/// their direction: each is woken in turn until its bytes or its buffer space come, none is left waiting.
+pub struct Uart {
+    regs: Usart,
+    state: &'static State,
+}
+
+impl Uart {
+    fn new(regs: Usart, interrupt: Interrupt, state: &'static State, pclk: u32, config: &Config) -> Self {
+        regs.brr().write(|w| w.set_brr(((pclk + config.baudrate / 2) / config.baudrate) as u16));
+        regs.cr1().write(|w| {
+            w.set_te(true);
+            w.set_re(true);
+            w.set_rxneie(true);
+            w.set_ue(true);
+        });
+
+        // the same as the time base: the handler calls the kernel, so it must not be above OS_KERNEL_INTERRUPT_PRIO
+        unsafe {
+            let mut nvic = cortex_m::Peripherals::steal().NVIC;
+            nvic.set_priority(interrupt, 32);
+            NVIC::unmask(interrupt);
+        }
+
+        Self { regs, state }
+    }
+
+    async fn read_async(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // the buffer check and the waker registration are atomic with respect to the interrupt handler, which also
+        // serializes the readers of the ring buffer
+        poll_fn(|cx| {
+            critical_section::with(|_| {
+                if let Some(error) = self.state.take_error() {
+                    return Poll::Ready(Err(error));
+                }
+                match self.state.rx.pop_slice(buf) {
+                    0 => {
+                        self.state.rx_waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                    n => Poll::Ready(Ok(n)),
+                }
+            })
+        })
+        .await
+    }
+
+    async fn write_async(&self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        poll_fn(|cx| {
+            critical_section::with(|_| match self.state.tx.push_slice(buf) {
+                0 => {
+                    self.state.tx_waker.register(cx.waker());
+                    Poll::Pending
+                }
+                n => {
+                    self.regs.cr1().modify(|w| w.set_txeie(true));
+                    Poll::Ready(Ok(n))
+                }
+            })
+        })
+        .await
+    }
+
+    async fn flush_async(&self) -> Result<(), Error> {
+        poll_fn(|cx| {
+            critical_section::with(|_| {
+                if self.state.tx.is_empty() && transmission_complete(self.regs) {
+                    return Poll::Ready(Ok(()));
+                }
+                self.state.tx_waker.register(cx.waker());
+                self.regs.cr1().modify(|w| w.set_tcie(true));
+                Poll::Pending
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "stm32f4")]
+fn transmission_complete(regs: Usart) -> bool {
+    regs.sr().read().tc()
+}
+
+#[cfg(feature = "stm32g4")]
+fn transmission_complete(regs: Usart) -> bool {
+    regs.isr().read().tc()
+}
+
+impl embedded_io::ErrorType for &Uart {
+    type Error = Error;
+}
+
+impl embedded_io_async::Read for &Uart {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.read_async(buf).await
+    }
+}
+
+impl embedded_io_async::Write for &Uart {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.write_async(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.flush_async().await
+    }
+}
+
+impl embedded_io::Read for &Uart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        blocking::block_on(self.read_async(buf))
+    }
+}
+
+impl embedded_io::Write for &Uart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        blocking::block_on(self.write_async(buf))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        blocking::block_on(self.flush_async())
+    }
+}
+
+impl embedded_io::ReadReady for &Uart {
+    fn read_ready(&mut self) -> Result<bool, Error> {
+        Ok(!self.state.rx.is_empty())
+    }
+}
+
+impl embedded_io::WriteReady for &Uart {
+    fn write_ready(&mut self) -> Result<bool, Error> {
+        Ok(!self.state.tx.is_full())
+    }
+}
+
+/// The status bits the interrupt handler acts on
+struct Status {
+    rxne: bool,
+    txe: bool,
+    tc: bool,
+    errors: u8,
+}
+
+#[cfg(feature = "stm32f4")]
+fn read_status(regs: Usart) -> Status {
+    let sr = regs.sr().read();
+    let errors = if sr.ore() { ERR_OVERRUN } else { 0 }
+        | if sr.fe() { ERR_FRAMING } else { 0 }
+        | if sr.ne() { ERR_NOISE } else { 0 };
+    // the error flags are cleared by reading SR then DR
+    if errors != 0 && !sr.rxne() {
+        let _ = regs.dr().read();
+    }
+    Status { rxne: sr.rxne(), txe: sr.txe(), tc: sr.tc(), errors }
+}
+
+#[cfg(feature = "stm32g4")]
+fn read_status(regs: Usart) -> Status {
+    let isr = regs.isr().read();
+    let errors = if isr.ore() { ERR_OVERRUN } else { 0 }
+        | if isr.fe() { ERR_FRAMING } else { 0 }
+        | if isr.ne() { ERR_NOISE } else { 0 };
+    if errors != 0 {
+        regs.icr().write(|w| {
+            w.set_ore(true);
+            w.set_fe(true);
+            w.set_ne(true);
+        });
+    }
+    Status { rxne: isr.rxne(), txe: isr.txe(), tc: isr.tc(), errors }
+}
+
+#[cfg(feature = "stm32f4")]
+fn read_byte(regs: Usart) -> u8 {
+    regs.dr().read().dr() as u8
+}
+
+#[cfg(feature = "stm32g4")]
+fn read_byte(regs: Usart) -> u8 {
+    regs.rdr().read().dr() as u8
+}
+
+#[cfg(feature = "stm32f4")]
+fn write_byte(regs: Usart, byte: u8) {
+    regs.dr().write(|w| w.set_dr(byte as u16));
+}
+
+#[cfg(feature = "stm32g4")]
+fn write_byte(regs: Usart, byte: u8) {
+    regs.tdr().write(|w| w.set_dr(byte as u16));
+}
+
+/// The interrupt work shared by the USART handlers
+fn on_interrupt(regs: Usart, state: &State) {
+    let status = read_status(regs);
+    let cr1 = regs.cr1().read();
+
+    let mut errors = status.errors;
+    // a full receive buffer drops the byte
+    if status.rxne && !state.rx.push(read_byte(regs)) {
+        errors |= ERR_OVERRUN;
+    }
+    if status.rxne || errors != 0 {
+        state.errors.fetch_or(errors, Ordering::Relaxed);
+        state.rx_waker.wake();
+    }
+
+    if cr1.txeie() && status.txe {
+        match state.tx.pop() {
+            Some(byte) => write_byte(regs, byte),
+            None => regs.cr1().modify(|w| w.set_txeie(false)),
+        }
+        state.tx_waker.wake();
+    } else if cr1.tcie() && status.tc {
+        regs.cr1().modify(|w| w.set_tcie(false));
+        state.tx_waker.wake();
+    }
+}
+
+/// USART1 interrupt handler
+#[no_mangle]
+pub unsafe extern "C" fn USART1() {
+    isr::int_enter();
+    on_interrupt(USART1, &USART1_STATE);
+    isr::int_exit();
+}
+
+/// USART2 interrupt handler
+#[no_mangle]
+pub unsafe extern "C" fn USART2() {
+    isr::int_enter();
+    on_interrupt(USART2, &USART2_STATE);
+    isr::int_exit();
+}
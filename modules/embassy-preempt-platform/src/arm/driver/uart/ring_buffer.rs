@@ -0,0 +1,80 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Single-producer single-consumer byte queue between a task and an interrupt handler
+///
+/// `start` and `end` count the bytes popped and pushed, wrapping around: `N` must be a power of two so that the
+/// index `count % N` stays continuous across the wrap. Each side only stores its own counter, so no critical section
+/// is needed as long as there is one producer and one consumer.
+pub(crate) struct RingBuffer<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    start: AtomicUsize,
+    end: AtomicUsize,
+}
+
+unsafe impl<const N: usize> Sync for RingBuffer<N> {}
+
+impl<const N: usize> RingBuffer<N> {
+    const POWER_OF_TWO: () = assert!(N.is_power_of_two(), "the ring buffer size must be a power of two");
+
+    pub(crate) const fn new() -> Self {
+        let () = Self::POWER_OF_TWO;
+        Self {
+            buf: UnsafeCell::new([0; N]),
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.end.load(Ordering::Acquire).wrapping_sub(self.start.load(Ordering::Acquire))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Push a byte, `false` if the buffer is full (producer side)
+    pub(crate) fn push(&self, byte: u8) -> bool {
+        let end = self.end.load(Ordering::Relaxed);
+        if end.wrapping_sub(self.start.load(Ordering::Acquire)) == N {
+            return false;
+        }
+        unsafe { (*self.buf.get())[end % N] = byte };
+        self.end.store(end.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Pop a byte (consumer side)
+    pub(crate) fn pop(&self) -> Option<u8> {
+        let start = self.start.load(Ordering::Relaxed);
+        if self.end.load(Ordering::Acquire) == start {
+            return None;
+        }
+        let byte = unsafe { (*self.buf.get())[start % N] };
+        self.start.store(start.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+
+    /// Push as much of `data` as fits, returns the number of bytes pushed (producer side)
+    pub(crate) fn push_slice(&self, data: &[u8]) -> usize {
+        data.iter().take_while(|&&byte| self.push(byte)).count()
+    }
+
+    /// Pop up to `out.len()` bytes, returns the number of bytes popped (consumer side)
+    pub(crate) fn pop_slice(&self, out: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < out.len() {
+            match self.pop() {
+                Some(byte) => out[n] = byte,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+}
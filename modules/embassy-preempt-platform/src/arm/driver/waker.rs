@@ -0,0 +1,38 @@
+use core::cell::UnsafeCell;
+use core::task::Waker;
+
+use critical_section::Mutex;
+
+/// The waker of the task waiting on a driver, woken and cleared by the driver's interrupt handler
+pub(crate) struct WakerSlot(Mutex<UnsafeCell<Option<Waker>>>);
+
+impl WakerSlot {
+    pub(crate) const fn new() -> Self {
+        Self(Mutex::new(UnsafeCell::new(None)))
+    }
+
+    /// Register the waker of the waiting task
+    ///
+    /// The slot holds one waker: the waker of another task is woken before it is replaced, so that task polls again
+    /// and registers itself in turn. Several tasks waiting on the same slot contend for it, each woken in turn until
+    /// the driver's event comes.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let displaced = critical_section::with(|cs| {
+            let slot = unsafe { &mut *self.0.borrow(cs).get() };
+            match slot {
+                Some(old) if old.will_wake(waker) => None,
+                _ => slot.replace(waker.clone()),
+            }
+        });
+        if let Some(displaced) = displaced {
+            displaced.wake();
+        }
+    }
+
+    pub(crate) fn wake(&self) {
+        let waker = critical_section::with(|cs| unsafe { (*self.0.borrow(cs).get()).take() });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! FPU setup for Cortex-M4F
+//!
+//! With lazy stacking, an exception taken while the FPU context is active (CONTROL.FPCA) only reserves room for
+//! S0-S15 and FPSCR in the extended exception frame; the registers are written there by the first FP instruction of
+//! the handler. The context switch saves S16-S31 itself when bit 4 of EXC_RETURN is clear.
+
+use cortex_m::asm::{dsb, isb};
+
+/// Coprocessor Access Control Register
+const CPACR: *mut u32 = 0xE000_ED88 as *mut u32;
+/// Floating-Point Context Control Register
+const FPCCR: *mut u32 = 0xE000_EF34 as *mut u32;
+
+/// CP10 and CP11: full access
+const CPACR_FPU_FULL_ACCESS: u32 = 0b1111 << 20;
+/// save the FP context automatically on exception entry when CONTROL.FPCA is set
+const FPCCR_ASPEN: u32 = 1 << 31;
+/// only reserve the space for the FP context, it is saved on the first FP instruction of the handler
+const FPCCR_LSPEN: u32 = 1 << 30;
+
+/// Enable the FPU and the automatic, lazy stacking of the FP context on exception entry.
+pub fn enable_lazy_stacking() {
+    unsafe {
+        CPACR.write_volatile(CPACR.read_volatile() | CPACR_FPU_FULL_ACCESS);
+        FPCCR.write_volatile(FPCCR.read_volatile() | FPCCR_ASPEN | FPCCR_LSPEN);
+        dsb();
+        isb();
+    }
+}
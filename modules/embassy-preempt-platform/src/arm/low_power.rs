@@ -0,0 +1,46 @@
+//! Sleep from inside a kernel critical section, for `Platform::sleep_until`
+//!
+//! PRIMASK does not keep a pending interrupt from waking the core, BASEPRI does. So with `OS_KERNEL_INTERRUPT_PRIO`
+//! above 0 the BASEPRI mask of the critical section is swapped for PRIMASK around the WFI; either way the interrupt
+//! that woke the core is taken once the critical section ends.
+
+use core::arch::asm;
+
+#[cfg(feature = "armv7m")]
+use embassy_preempt_cfg::OS_KERNEL_INTERRUPT_PRIO;
+
+/// Wait for an interrupt, inside a kernel critical section
+#[inline]
+pub fn wait_for_interrupt() {
+    #[cfg(feature = "armv7m")]
+    if OS_KERNEL_INTERRUPT_PRIO != 0 {
+        unsafe {
+            asm!(
+                "CPSID   I",
+                "MSR     BASEPRI, {zero}",
+                "DSB",
+                "WFI",
+                "MSR     BASEPRI, {prio}",
+                "ISB",
+                "CPSIE   I",
+                zero = in(reg) 0u32,
+                prio = in(reg) OS_KERNEL_INTERRUPT_PRIO as u32,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+        return;
+    }
+    unsafe { asm!("DSB", "WFI", "ISB", options(nomem, nostack, preserves_flags)) };
+}
+
+/// Wait for an interrupt in the chip's deep sleep mode (SLEEPDEEP), inside a kernel critical section
+///
+/// What deep sleep stops, and how the chip wakes up from it, is chip-specific and must be set up by the caller.
+#[inline]
+pub fn wait_for_interrupt_deep() {
+    // Safety: only the SLEEPDEEP bit is touched, the SCB is not otherwise used concurrently
+    let mut scb = unsafe { cortex_m::Peripherals::steal().SCB };
+    scb.set_sleepdeep();
+    wait_for_interrupt();
+    scb.clear_sleepdeep();
+}
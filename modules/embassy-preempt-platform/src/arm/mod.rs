@@ -1,3 +1,19 @@
+#[cfg(feature = "armv6m")]
+pub mod armv6m;
+#[cfg(feature = "armv7m")]
+pub mod armv7m;
 pub mod chip;
+#[cfg(any(feature = "armv6m", feature = "armv7m"))]
+pub mod critical_section;
+#[cfg(any(feature = "armv6m", feature = "armv7m"))]
+pub mod context;
 pub mod driver;
-pub mod panic_handler;
\ No newline at end of file
+#[cfg(feature = "cortex-m4f")]
+pub mod fpu;
+#[cfg(any(feature = "armv6m", feature = "armv7m"))]
+pub mod low_power;
+#[cfg(feature = "OS_STK_MPU_GUARD_EN")]
+pub mod mpu;
+pub mod panic_handler;
+#[cfg(feature = "time_driver_systick")]
+pub mod systick;
\ No newline at end of file
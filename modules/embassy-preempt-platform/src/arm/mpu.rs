@@ -0,0 +1,50 @@
+//! MPU stack guard for ARMv7-M
+//!
+//! One MPU region (the highest numbered, so it wins over any other region) is kept as a no-access region over the
+//! bottom of the running task's stack. It is moved on each context switch, so a task overflowing its stack raises a
+//! MemManage fault before corrupting the stack below it.
+
+use cortex_m::asm::{dsb, isb};
+use cortex_m::peripheral::{MPU, SCB};
+
+/// the region used for the guard
+const GUARD_REGION: u32 = 7;
+/// the size of the guard in bytes, the smallest region of ARMv7-M
+pub const GUARD_SIZE: usize = 32;
+
+const CTRL_ENABLE: u32 = 1 << 0;
+/// use the default memory map as background region for privileged accesses
+const CTRL_PRIVDEFENA: u32 = 1 << 2;
+const RASR_ENABLE: u32 = 1 << 0;
+/// SIZE field: region size is 2^(SIZE + 1) bytes
+const RASR_SIZE_32B: u32 = 4 << 1;
+/// AP = 0b000: no access, XN: no instruction fetch
+const RASR_NO_ACCESS_XN: u32 = 1 << 28;
+const SHCSR_MEMFAULTENA: u32 = 1 << 16;
+
+/// Move the guard region to the first `GUARD_SIZE` aligned bytes of the stack starting at `stack_bottom`, or remove
+/// it if `stack_bottom` is null.
+///
+/// The first call enables the MPU (keeping the default memory map for privileged code) and the MemManage fault.
+pub fn set_stack_guard(stack_bottom: *mut u8) {
+    unsafe {
+        let mpu = &*MPU::PTR;
+        if mpu.ctrl.read() & CTRL_ENABLE == 0 {
+            let scb = &*SCB::PTR;
+            scb.shcsr.modify(|v| v | SHCSR_MEMFAULTENA);
+            mpu.ctrl.write(CTRL_ENABLE | CTRL_PRIVDEFENA);
+        }
+        mpu.rnr.write(GUARD_REGION);
+        if stack_bottom.is_null() {
+            mpu.rasr.write(0);
+        } else {
+            let base = (stack_bottom as usize + GUARD_SIZE - 1) & !(GUARD_SIZE - 1);
+            // disable the region while moving it
+            mpu.rasr.write(0);
+            mpu.rbar.write(base as u32);
+            mpu.rasr.write(RASR_NO_ACCESS_XN | RASR_SIZE_32B | RASR_ENABLE);
+        }
+        dsb();
+        isb();
+    }
+}
@@ -0,0 +1,225 @@
+//! SysTick Timer Driver Implementation
+//!
+//! A time base for any Cortex-M chip that needs no peripheral timer, selected with the `time_driver_systick` feature.
+//!
+//! SysTick is a 24-bit down counter on the core clock. The driver extends it in software to a 64-bit cycle count:
+//! `base` is the cycle count when the counter was last (re)started with the reload value `load`, so the time is
+//! `base + (load - VAL)`, plus one period if the wrap has not been handled yet. The time in ticks is the cycle count
+//! divided by `core_hz / TICK_HZ`, so `TICK_HZ` must divide the core clock.
+//!
+//! Alarms are one-shot reloads: the counter is restarted with the cycles left until the earliest alarm (at most
+//! 2^24), and the wrap at the end of that period is the alarm. Without an alarm it runs with the full 2^24 period.
+//! Each restart loses the few cycles between reading and restarting the counter.
+
+use core::cell::Cell;
+use core::mem;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::{SCB, SYST};
+use critical_section::{CriticalSection, Mutex};
+use embassy_preempt_cfg::TICK_HZ;
+use embassy_preempt_log::{os_log, timer_log};
+
+use crate::traits::timer::{AlarmHandle, AlarmState, Driver};
+
+/// the largest reload value of the 24-bit counter
+const MAX_RELOAD: u32 = 0x00FF_FFFF;
+
+/// the number of alarms, multiplexed on the reload
+const ALARM_COUNT: usize = 3;
+
+/// SysTick exception handler
+#[unsafe(no_mangle)]
+pub extern "C" fn SysTick() {
+    use crate::get_platform_trait;
+
+    os_log!(trace, "SysTick handler invoked");
+    unsafe {
+        get_platform_trait().get_timer_driver().on_interrupt();
+    }
+}
+
+/// SysTick Timer Driver
+pub struct SysTickDriver {
+    /// core clock cycles per tick (Cortex-M has no 64-bit atomics)
+    cycles_per_tick: AtomicU32,
+
+    /// cycle count when the counter was last (re)started
+    base: Mutex<Cell<u64>>,
+
+    /// reload value of the running period
+    load: Mutex<Cell<u32>>,
+
+    /// Counter for tracking allocated alarm instances
+    alarm_count: AtomicU8,
+
+    /// Array of alarm states storing callbacks, contexts, and trigger timestamps
+    /// u64::MAX indicates no alarm is scheduled for that slot
+    alarms: Mutex<[AlarmState; ALARM_COUNT]>,
+}
+
+impl SysTickDriver {
+    /// Create a new timer driver instance
+    pub(crate) fn new() -> Self {
+        const ALARM_STATE_NEW: AlarmState = AlarmState::new();
+        SysTickDriver {
+            cycles_per_tick: AtomicU32::new(1),
+            base: Mutex::new(Cell::new(0)),
+            load: Mutex::new(Cell::new(MAX_RELOAD)),
+            alarm_count: AtomicU8::new(0),
+            alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
+        }
+    }
+
+    /// Start SysTick on the core clock running at `core_hz`
+    ///
+    /// # Panics
+    /// If `TICK_HZ` does not divide `core_hz`
+    pub fn init(&self, core_hz: u64) {
+        os_log!(trace, "Initializing SysTickDriver");
+        assert!(core_hz % TICK_HZ == 0, "TICK_HZ must divide the core clock");
+        self.cycles_per_tick.store((core_hz / TICK_HZ) as u32, Ordering::Relaxed);
+
+        // safety: the driver owns SysTick, nothing else uses it
+        let mut syst = unsafe { cortex_m::Peripherals::steal() }.SYST;
+        syst.disable_counter();
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(MAX_RELOAD);
+        syst.clear_current();
+        SCB::clear_pendst();
+        syst.enable_interrupt();
+        syst.enable_counter();
+    }
+
+    /// The 64-bit cycle count
+    fn cycles(&self, cs: CriticalSection) -> u64 {
+        let load = self.load.borrow(cs).get();
+        let mut base = self.base.borrow(cs).get();
+        let mut value = SYST::get_current();
+        // the wrap has not been handled yet, read the counter again after the wrap
+        if SCB::is_pendst_pending() {
+            base += load as u64 + 1;
+            value = SYST::get_current();
+        }
+        base + (load - value) as u64
+    }
+
+    /// Restart the counter with the cycles left until the earliest alarm
+    fn rearm(&self, cs: CriticalSection) {
+        let cycles_per_tick = self.cycles_per_tick.load(Ordering::Relaxed) as u64;
+        let next = self.alarms.borrow(cs).iter().map(|alarm| alarm.timestamp.get()).min().unwrap_or(u64::MAX);
+        let now = self.cycles(cs);
+        let load = if next == u64::MAX {
+            MAX_RELOAD
+        } else {
+            // a reload of 0 stops the counter
+            let left = next.saturating_mul(cycles_per_tick).saturating_sub(now);
+            (left.clamp(2, MAX_RELOAD as u64 + 1) - 1) as u32
+        };
+
+        // safety: SYST is only written from inside the critical section
+        let mut syst = unsafe { cortex_m::Peripherals::steal() }.SYST;
+        syst.set_reload(load);
+        // the counter reloads on the next cycle, without an exception
+        syst.clear_current();
+        // a wrap that is pending is already in `now`
+        SCB::clear_pendst();
+        self.base.borrow(cs).set(now);
+        self.load.borrow(cs).set(load);
+    }
+
+    fn get_alarm<'a>(&'a self, cs: CriticalSection<'a>, alarm: AlarmHandle) -> &'a AlarmState {
+        // safety: we're allowed to assume the AlarmState is created by us, and
+        // we never create one that's out of bounds.
+        unsafe { self.alarms.borrow(cs).get_unchecked(alarm.id() as usize) }
+    }
+
+    fn trigger_alarm(&self, n: usize, cs: CriticalSection) {
+        timer_log!(trace, "trigger_alarm");
+        let alarm = &self.alarms.borrow(cs)[n];
+        alarm.timestamp.set(u64::MAX);
+
+        // Call after clearing alarm, so the callback can set another alarm.
+
+        // safety:
+        // - we can ignore the possibility of `f` being unset (null) because of the safety contract of `allocate_alarm`.
+        // - other than that we only store valid function pointers into alarm.callback
+        let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
+        f(alarm.ctx.get());
+    }
+}
+
+impl Driver for SysTickDriver {
+    fn now(&self) -> u64 {
+        critical_section::with(|cs| self.cycles(cs)) / self.cycles_per_tick.load(Ordering::Relaxed) as u64
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        critical_section::with(|_| {
+            let id = self.alarm_count.load(Ordering::Relaxed);
+            if id < ALARM_COUNT as u8 {
+                self.alarm_count.store(id + 1, Ordering::Relaxed);
+                Some(AlarmHandle::new(id))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+
+            alarm.callback.set(callback as *const ());
+            alarm.ctx.set(ctx);
+        })
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        timer_log!(trace, "set the alarm at {}", timestamp);
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+            if timestamp == u64::MAX {
+                // no alarm needed, the next wrap rearms for the remaining alarms
+                alarm.timestamp.set(u64::MAX);
+                return true;
+            }
+
+            if timestamp <= self.now() {
+                // If alarm timestamp has passed the alarm will not fire.
+                // Disarm the alarm and return `false` to indicate that.
+                alarm.timestamp.set(u64::MAX);
+                return false;
+            }
+            alarm.timestamp.set(timestamp);
+            self.rearm(cs);
+            true
+        })
+    }
+
+    unsafe fn on_interrupt(&self) {
+        critical_section::with(|cs| {
+            // the exception entry has cleared the pending wrap, count the period that ended
+            let base = self.base.borrow(cs);
+            base.set(base.get() + self.load.borrow(cs).get() as u64 + 1);
+
+            let t = self.now();
+            let mut armed = false;
+            for n in 0..ALARM_COUNT {
+                let at = self.alarms.borrow(cs)[n].timestamp.get();
+                if at <= t {
+                    timer_log!(trace, "the alarm is triggered!!!");
+                    self.trigger_alarm(n, cs);
+                } else if at != u64::MAX {
+                    armed = true;
+                }
+            }
+
+            // a callback may have set an alarm, which restarts the counter itself
+            if armed || self.load.borrow(cs).get() != MAX_RELOAD {
+                self.rearm(cs);
+            }
+        })
+    }
+}
@@ -0,0 +1,32 @@
+//! Blocking driver calls from sync tasks
+//!
+//! The drivers implement their operations as futures woken by their interrupt handlers. [`block_on`] runs one in a
+//! sync task: the task leaves the ready list while the future is pending, like in `OSTimeDly()`, instead of spinning
+//! on the peripheral.
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll};
+
+extern "Rust" {
+    fn __OSBlockOn(poll: &mut dyn FnMut(&mut Context<'_>) -> bool);
+}
+
+/// Run `future` to completion, blocking the current task while it is pending
+///
+/// Before `OSStart()`, in an interrupt handler or with the scheduler locked, the future is polled in a loop instead.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut output = None;
+    unsafe {
+        __OSBlockOn(&mut |cx| match future.as_mut().poll(cx) {
+            Poll::Ready(value) => {
+                output = Some(value);
+                true
+            }
+            Poll::Pending => false,
+        })
+    };
+    // `__OSBlockOn` only returns once the future is ready
+    output.unwrap()
+}
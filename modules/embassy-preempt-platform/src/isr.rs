@@ -0,0 +1,26 @@
+//! Kernel notification from interrupt handlers
+//!
+//! An interrupt handler that can make a task ready (a timer alarm, a driver waking a reader) brackets its work with
+//! [`int_enter`] and [`int_exit`], the kernel's `OSIntEnter()`/`OSIntExit()`: the context switch to the woken task
+//! is then requested once, when the outermost handler exits.
+
+extern "C" {
+    fn __OSIntEnter();
+    fn __OSIntExit();
+}
+
+/// Tell the kernel an interrupt handler is running.
+#[inline(always)]
+pub fn int_enter() {
+    unsafe { __OSIntEnter() }
+}
+
+/// Tell the kernel the interrupt handler is done, switching to a higher priority ready task if this is the
+/// outermost handler.
+///
+/// # Safety
+/// Must be paired with a previous [`int_enter`] in the same handler.
+#[inline(always)]
+pub unsafe fn int_exit() {
+    __OSIntExit()
+}
@@ -1,6 +1,7 @@
 #![no_std]
 #![feature(naked_functions_rustic_abi)]
 #![feature(decl_macro)]
+#![cfg_attr(feature = "qingke", feature(abi_riscv_interrupt))]
 
 //! Platform abstraction layer for embassy_preempt RTOS
 //!
@@ -13,11 +14,19 @@
 //! - [`traits`]: Platform trait definitions
 //!   - [`platform`]: Core platform functionality trait
 //!   - [`timer`]: Timer driver trait
-//! - [`stm32f401re`]: STM32F401RE platform implementation
+//! - [`stm32`]: STM32F4/STM32G4 platform implementation, generic over the `stm32-metapac` chip
+//! - [`microbit`]: micro:bit (nRF51822, Cortex-M0) platform implementation, runs on QEMU
+//! - [`mps2_an386`]: Arm MPS2 AN386 (Cortex-M4) platform implementation, runs on QEMU
+//! - [`qemu_virt`]: QEMU `virt` (RV32IMAC) platform implementation
 //!
 //! ## Platform Implementations
 //!
-//! - [`stm32f401re`]: STM32F401RE microcontroller support with timer driver
+//! - [`stm32`]: `stm32f401re`, `stm32f411re`, `stm32f446re` and `stm32g474re`, clocks from the application's
+//!   `Config`, TIM or SysTick timer driver; the Nucleo button and LED are in `driver` with the `nucleo` feature, the
+//!   interrupt-driven UART and I2C with the `uart` and `i2c` features
+//! - [`microbit`]: ARMv6-M support (`armv6m`), TIMER0 timer driver
+//! - [`mps2_an386`]: ARMv7-M support (`armv7m`), CMSDK timer driver, semihosting shutdown
+//! - [`qemu_virt`]: generic RV32 support (`riscv`), CLINT timer driver, test device shutdown
 
 // mod critical_section;
 
@@ -25,6 +34,8 @@
 extern crate embassy_preempt_log;
 
 // Declare modules
+pub mod blocking;
+pub mod isr;
 pub mod traits;
 
 use spin::Once;
@@ -41,22 +52,31 @@ pub mod arm;
 #[cfg(all(feature = "arm", feature = "cortex-m"))]
 pub use arm as arch;
 
-#[cfg(feature = "stm32f4xx")]
-pub use stm32f4xx_hal as hal;
+// STM32 platforms, the chip comes from the stm32-metapac feature
+#[cfg(feature = "stm32")]
+pub use arch::chip::stm32 as chip;
 
-// STM32F401RE platform
-#[cfg(feature = "stm32f401re")]
-pub use arch::chip::stm32f401re as chip;
-
-#[cfg(feature = "stm32f401re")]
+#[cfg(feature = "stm32")]
 pub use stm32_metapac as pac;
 
-// RISC-V platforms (placeholder for future implementation)
+// micro:bit (nRF51822, ARMv6-M)
+#[cfg(feature = "microbit")]
+pub use arch::chip::microbit as chip;
+
+// MPS2 AN386 (Cortex-M4, QEMU)
+#[cfg(feature = "mps2-an386")]
+pub use arch::chip::mps2_an386 as chip;
+
+// RISC-V platforms
 #[cfg(all(feature = "riscv", feature = "riscv32"))]
 pub mod riscv;
 
 #[cfg(all(feature = "riscv", feature = "riscv32"))]
-pub use riscv as arch;
+pub use self::riscv as arch;
+
+// QEMU virt (RV32IMAC)
+#[cfg(feature = "qemu-virt")]
+pub use arch::chip::qemu_virt as chip;
 
 
 // Qingke platforms
@@ -72,7 +92,7 @@ pub use arch::chip::ch32v307wcu6 as chip;
 // ===== RE-EXPORTS =====
 
 // Re-export panic handler for the selected architecture
-#[cfg(any(feature = "stm32f401re", all(feature = "riscv", feature = "riscv32")))]
+#[cfg(any(feature = "stm32", feature = "microbit", feature = "mps2-an386", all(feature = "riscv", feature = "riscv32")))]
 pub use arch::panic_handler;
 
 pub use arch::driver as driver;
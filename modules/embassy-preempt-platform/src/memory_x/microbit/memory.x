@@ -0,0 +1,11 @@
+/* nRF51822 (micro:bit v1) with 16K RAM and 256K Flash */
+
+MEMORY
+{
+  /* the top 6K of RAM hold the kernel stacks and heap, see PlatformMemoryLayout of the microbit chip */
+  RAM : ORIGIN = 0x20000000, LENGTH = 10K
+  FLASH : ORIGIN = 0x00000000, LENGTH = 256K
+}
+
+/* The reset stack takes the last 1K, it is only used until the kernel switches to its interrupt stack */
+_stack_start = 0x20004000;
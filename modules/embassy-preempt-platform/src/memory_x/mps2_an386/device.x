@@ -0,0 +1,16 @@
+PROVIDE(UARTRX0 = DefaultHandler);
+PROVIDE(UARTTX0 = DefaultHandler);
+PROVIDE(UARTRX1 = DefaultHandler);
+PROVIDE(UARTTX1 = DefaultHandler);
+PROVIDE(UARTRX2 = DefaultHandler);
+PROVIDE(UARTTX2 = DefaultHandler);
+PROVIDE(PORT0_ALL = DefaultHandler);
+PROVIDE(PORT1_ALL = DefaultHandler);
+PROVIDE(TIMER0 = DefaultHandler);
+PROVIDE(TIMER1 = DefaultHandler);
+PROVIDE(DUALTIMER = DefaultHandler);
+PROVIDE(SPI = DefaultHandler);
+PROVIDE(UART_OVF = DefaultHandler);
+PROVIDE(ETHERNET = DefaultHandler);
+PROVIDE(I2S = DefaultHandler);
+PROVIDE(TSC = DefaultHandler);
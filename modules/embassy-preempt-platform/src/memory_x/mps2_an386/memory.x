@@ -0,0 +1,11 @@
+/* MPS2 AN386: 4M SSRAM1 for the code, 4M SSRAM2/3 for the data */
+
+MEMORY
+{
+  /* the kernel stacks and heap start at 0x20100000, see PlatformMemoryLayout of the mps2_an386 chip */
+  RAM : ORIGIN = 0x20000000, LENGTH = 1M
+  FLASH : ORIGIN = 0x00000000, LENGTH = 4M
+}
+
+/* The reset stack is only used until the kernel switches to its interrupt stack */
+_stack_start = ORIGIN(RAM) + LENGTH(RAM);
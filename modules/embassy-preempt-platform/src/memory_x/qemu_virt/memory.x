@@ -0,0 +1,13 @@
+/* QEMU virt: the ELF is loaded into RAM at 0x80000000 */
+
+MEMORY
+{
+    /* the kernel stacks and heap start at 0x81000000, see PlatformMemoryLayout of the qemu_virt chip */
+    RAM : ORIGIN = 0x80000000, LENGTH = 16M
+}
+REGION_ALIAS("REGION_TEXT", RAM);
+REGION_ALIAS("REGION_RODATA", RAM);
+REGION_ALIAS("REGION_DATA", RAM);
+REGION_ALIAS("REGION_BSS", RAM);
+REGION_ALIAS("REGION_HEAP", RAM);
+REGION_ALIAS("REGION_STACK", RAM);
@@ -0,0 +1,10 @@
+/* STM32F446RETx with 128K RAM (SRAM1 + SRAM2) and 512K Flash */
+
+MEMORY
+{
+  RAM : ORIGIN = 0x20000000, LENGTH = 128K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 512K
+}
+
+/* This is where we store the stack start address */
+_stack_start = ORIGIN(RAM) + LENGTH(RAM);
@@ -5,10 +5,11 @@ pub mod timer_driver;
 pub use platform::{PlatformImpl};
 pub use ucstk::UcStk;
 
+// the software interrupt is the context switch, see `PlatformImpl::trigger_context_switch`
 core::arch::global_asm!(
         ".section .trap, \"ax\"",
-        ".global MachineEnvCall",
-        "MachineEnvCall:",
+        ".global Software",
+        "Software:",
         "csrrw sp, mscratch, sp",
         // 禁用硬件压栈
         // "li t0, 0x23",
@@ -1,9 +1,10 @@
 use core::arch::asm;
 use core::ptr::NonNull;
 
-use qingke::riscv::asm::{ecall, wfi};
+use qingke::riscv::asm::wfi;
 
 use crate::chip::ucstk::CONTEXT_STACK_SIZE;
+use crate::qingke::pfic;
 use crate::traits::memory_layout::PlatformMemoryLayout;
 use crate::traits::platform::PlatformStatic;
 use crate::Platform;
@@ -18,17 +19,20 @@ impl PlatformImpl {
         unsafe {
             asm!("li t0, 0x0", "csrw 0x804, t0",);
         }
-        let timer = crate::qingke::chip::ch32v307wcu6::timer_driver::Ch32v307Timer {};
+        // the context switch has the lowest priority, so it runs after every other pending interrupt
+        pfic::set_priority(pfic::SOFTWARE_IRQ, 0xf0);
+        pfic::enable(pfic::SOFTWARE_IRQ);
+
+        let timer = crate::qingke::chip::ch32v307wcu6::timer_driver::Ch32v307Timer::new();
+        timer.init();
         PlatformImpl { timer }
     }
 }
 
 impl PlatformStatic for PlatformImpl {
     fn trigger_context_switch() {
-        unsafe {
-            // qingke::riscv::register::mip::set_ssoft();
-            ecall();
-        }
+        // taken once interrupts are enabled again, so it also works from an interrupt handler
+        pfic::pend(pfic::SOFTWARE_IRQ);
     }
 
     #[inline(always)]
@@ -116,7 +120,6 @@ impl PlatformStatic for PlatformImpl {
             "lw x31, 120(sp)",
 
             "lw a0, 124(sp)",
-            "addi a0, a0, 4",
             "csrw mepc, a0",
             "lw a0, 128(sp)",
             "csrw mstatus, a0",
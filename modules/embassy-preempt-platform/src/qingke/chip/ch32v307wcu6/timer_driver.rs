@@ -1,25 +1,302 @@
-use crate::traits::timer::AlarmHandle;
+//! CH32V307 Timer Driver Implementation
+//!
+//! The time base is the 16-bit general-purpose timer TIM2, extended to 64 bits like the STM32 `RtcDriver`: the
+//! update (overflow) and CC1 (half-overflow at 0x8000) interrupts both start a new period of 2^15 ticks, and
+//! `now = (period << 15) + (counter ^ ((period & 1) << 15))`. CC2-CC4 are the alarms.
+//!
+//! TIM2 runs from PCLK1, which is the 8 MHz HSI as long as the clock tree is left at its reset configuration, so
+//! `TICK_HZ` must divide 8 MHz (the default 100 kHz does).
+//!
+//! The interrupt handler is bracketed by `OSIntEnter()`/`OSIntExit()`, so a task woken by an alarm preempts the
+//! running one as soon as the handler returns.
 
+use core::mem;
+use core::sync::atomic::{compiler_fence, AtomicU32, AtomicU8, Ordering};
+
+use critical_section::{CriticalSection, Mutex};
+use embassy_preempt_cfg::TICK_HZ;
+use embassy_preempt_log::{os_log, timer_log};
+
+use crate::isr;
+use crate::qingke::pfic;
+use crate::traits::timer::{AlarmHandle, AlarmState, Driver};
+
+/// TIM2 base address
+const TIM2_BASE: usize = 0x4000_0000;
+/// RCC APB1 peripheral clock enable register
+const RCC_APB1PCENR: usize = 0x4002_101C;
+/// TIM2EN in RCC_APB1PCENR
+const RCC_TIM2EN: u32 = 1 << 0;
+/// TIM2 interrupt number
+const TIM2_IRQ: u8 = 44;
+
+const CTLR1: usize = 0x00;
+const DMAINTENR: usize = 0x0C;
+const INTFR: usize = 0x10;
+const SWEVGR: usize = 0x14;
+const CNT: usize = 0x24;
+const PSC: usize = 0x28;
+const ATRLR: usize = 0x2C;
+const CHCVR: usize = 0x34;
+
+const CTLR1_CEN: u32 = 1 << 0;
+const CTLR1_URS: u32 = 1 << 2;
+const SWEVGR_UG: u32 = 1 << 0;
+/// UIE/UIF, the update interrupt
+const UPDATE: u32 = 1 << 0;
+
+/// PCLK1 at reset, the HSI
+const PCLK1_HZ: u64 = 8_000_000;
+/// the prescaler giving `TICK_HZ`
+const PSC_VAL: u32 = {
+    assert!(
+        PCLK1_HZ % TICK_HZ == 0 && PCLK1_HZ / TICK_HZ <= 0x1_0000,
+        "TIM2 runs at 8 MHz / (PSC + 1): choose another tick-hz-* feature"
+    );
+    (PCLK1_HZ / TICK_HZ - 1) as u32
+};
+
+/// the alarms on CC2-CC4
+const ALARM_COUNT: usize = 3;
+
+#[inline(always)]
+fn read(offset: usize) -> u32 {
+    unsafe { ((TIM2_BASE + offset) as *const u32).read_volatile() }
+}
+
+#[inline(always)]
+fn write(offset: usize, value: u32) {
+    unsafe { ((TIM2_BASE + offset) as *mut u32).write_volatile(value) }
+}
+
+#[inline(always)]
+fn modify(offset: usize, f: impl FnOnce(u32) -> u32) {
+    write(offset, f(read(offset)));
+}
+
+/// CCxIE/CCxIF of channel `n` (0 is CC1)
+#[inline(always)]
+fn cc(n: usize) -> u32 {
+    1 << (n + 1)
+}
+
+/// Enable or disable the interrupt of channel `n`
+fn set_ccie(n: usize, enable: bool) {
+    modify(DMAINTENR, |v| if enable { v | cc(n) } else { v & !cc(n) });
+}
+
+/// TIM2 interrupt handler
+#[qingke_rt::interrupt]
+fn TIM2() {
+    use crate::get_platform_trait;
+
+    os_log!(trace, "TIM2 interrupt handler invoked");
+    isr::int_enter();
+    unsafe {
+        get_platform_trait().get_timer_driver().on_interrupt();
+        isr::int_exit();
+    }
+}
+
+/// CH32V307 Timer Driver
 pub struct Ch32v307Timer {
+    /// Number of 2^15 tick periods elapsed since system boot
+    period: AtomicU32,
+
+    /// Counter for tracking allocated alarm instances
+    alarm_count: AtomicU8,
+
+    /// Array of alarm states storing callbacks, contexts, and trigger timestamps
+    /// u64::MAX indicates no alarm is scheduled for that slot
+    alarms: Mutex<[AlarmState; ALARM_COUNT]>,
 }
 
-impl crate::traits::timer::Driver for Ch32v307Timer {
-    fn now(&self) -> u64 {
-        0
+impl Ch32v307Timer {
+    /// Create a new timer driver instance
+    pub(crate) fn new() -> Self {
+        const ALARM_STATE_NEW: AlarmState = AlarmState::new();
+        Ch32v307Timer {
+            period: AtomicU32::new(0),
+            alarm_count: AtomicU8::new(0),
+            alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
+        }
     }
 
-    unsafe fn allocate_alarm(&self) -> Option<crate::traits::timer::AlarmHandle> {
-        Some(AlarmHandle::new(1))
+    /// Start TIM2 from zero and enable its interrupt
+    pub fn init(&self) {
+        os_log!(trace, "Initializing Ch32v307Timer");
+
+        unsafe {
+            let apb1pcenr = RCC_APB1PCENR as *mut u32;
+            apb1pcenr.write_volatile(apb1pcenr.read_volatile() | RCC_TIM2EN);
+        }
+
+        modify(CTLR1, |v| v & !CTLR1_CEN);
+        write(CNT, 0);
+        write(PSC, PSC_VAL);
+        write(ATRLR, u16::MAX as u32);
+
+        // load the prescaler and clear the counter without an update interrupt
+        modify(CTLR1, |v| v | CTLR1_URS);
+        write(SWEVGR, SWEVGR_UG);
+        modify(CTLR1, |v| v & !CTLR1_URS);
+
+        // the half-overflow
+        write(CHCVR, 0x8000);
+        write(INTFR, 0);
+        write(DMAINTENR, UPDATE | cc(0));
 
+        pfic::unpend(TIM2_IRQ);
+        compiler_fence(Ordering::SeqCst);
+        pfic::enable(TIM2_IRQ);
+
+        modify(CTLR1, |v| v | CTLR1_CEN);
+    }
+
+    fn next_period(&self) {
+        // We only modify the period from the timer interrupt, so we know this can't race.
+        let period = self.period.load(Ordering::Relaxed) + 1;
+        self.period.store(period, Ordering::Relaxed);
+        let t = (period as u64) << 15;
+
+        critical_section::with(move |cs| {
+            for n in 0..ALARM_COUNT {
+                let alarm = &self.alarms.borrow(cs)[n];
+                let at = alarm.timestamp.get();
+
+                if at < t + 0xc000 {
+                    // just enable it. `set_alarm` has already set the correct CCR val.
+                    set_ccie(n + 1, true);
+                }
+            }
+        })
+    }
+
+    fn get_alarm<'a>(&'a self, cs: CriticalSection<'a>, alarm: AlarmHandle) -> &'a AlarmState {
+        // safety: we're allowed to assume the AlarmState is created by us, and
+        // we never create one that's out of bounds.
+        unsafe { self.alarms.borrow(cs).get_unchecked(alarm.id() as usize) }
+    }
+
+    fn trigger_alarm(&self, n: usize, cs: CriticalSection) {
+        timer_log!(trace, "trigger_alarm");
+        let alarm = &self.alarms.borrow(cs)[n];
+        alarm.timestamp.set(u64::MAX);
+
+        // Call after clearing alarm, so the callback can set another alarm.
+
+        // safety:
+        // - we can ignore the possibility of `f` being unset (null) because of the safety contract of `allocate_alarm`.
+        // - other than that we only store valid function pointers into alarm.callback
+        let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
+        f(alarm.ctx.get());
+    }
+}
+
+impl Driver for Ch32v307Timer {
+    fn now(&self) -> u64 {
+        let period = self.period.load(Ordering::Relaxed);
+        compiler_fence(Ordering::Acquire);
+        let counter = read(CNT) as u16;
+        calc_now(period, counter)
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        critical_section::with(|_| {
+            let id = self.alarm_count.load(Ordering::Relaxed);
+            if id < ALARM_COUNT as u8 {
+                self.alarm_count.store(id + 1, Ordering::Relaxed);
+                Some(AlarmHandle::new(id))
+            } else {
+                None
+            }
+        })
     }
 
-    fn set_alarm_callback(&self, alarm: crate::traits::timer::AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+
+            alarm.callback.set(callback as *const ());
+            alarm.ctx.set(ctx);
+        })
     }
 
-    fn set_alarm(&self, alarm: crate::traits::timer::AlarmHandle, timestamp: u64) -> bool {
-        true
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        timer_log!(trace, "set the alarm at {}", timestamp);
+        let n = alarm.id() as usize;
+        if timestamp == u64::MAX {
+            // no alarm needed
+            critical_section::with(|cs| {
+                self.get_alarm(cs, alarm).timestamp.set(u64::MAX);
+                set_ccie(n + 1, false);
+            });
+            return true;
+        }
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+            alarm.timestamp.set(timestamp);
+
+            let t = self.now();
+            if timestamp <= t {
+                // If alarm timestamp has passed the alarm will not fire.
+                // Disarm the alarm and return `false` to indicate that.
+                set_ccie(n + 1, false);
+                alarm.timestamp.set(u64::MAX);
+                return false;
+            }
+
+            // Write the CCR value regardless of whether we're going to enable it now or not.
+            // This way, when we enable it later, the right value is already set.
+            write(CHCVR + 4 * (n + 1), timestamp as u16 as u32);
+
+            // Enable it if it'll happen soon. Otherwise, `next_period` will enable it.
+            let diff = timestamp - t;
+            set_ccie(n + 1, diff < 0xc000);
+
+            // Reevaluate if the alarm timestamp is still in the future
+            let t = self.now();
+            if timestamp <= t {
+                // If alarm timestamp has passed since we set it, we have a race condition and
+                // the alarm may or may not have fired.
+                // Disarm the alarm and return `false` to indicate that.
+                // It is the caller's responsibility to handle this ambiguity.
+                set_ccie(n + 1, false);
+                alarm.timestamp.set(u64::MAX);
+                return false;
+            }
+            // We're confident the alarm will ring in the future.
+            true
+        })
     }
 
     unsafe fn on_interrupt(&self) {
+        critical_section::with(|cs| {
+            let intfr = read(INTFR);
+            let dier = read(DMAINTENR);
+
+            // Clear the flags that are set, writing 1 leaves a flag unchanged, so no event is lost.
+            write(INTFR, !intfr);
+
+            // Overflow and half overflow
+            if intfr & UPDATE != 0 {
+                self.next_period();
+            }
+            if intfr & cc(0) != 0 {
+                self.next_period();
+            }
+
+            for n in 0..ALARM_COUNT {
+                if intfr & dier & cc(n + 1) != 0 {
+                    timer_log!(trace, "the alarm is triggered!!!");
+                    set_ccie(n + 1, false);
+                    self.trigger_alarm(n, cs);
+                }
+            }
+        })
     }
-}
\ No newline at end of file
+}
+
+fn calc_now(period: u32, counter: u16) -> u64 {
+    ((period as u64) << 15) + ((counter as u32 ^ ((period & 1) << 15)) as u64)
+}
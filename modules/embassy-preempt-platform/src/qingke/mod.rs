@@ -2,6 +2,6 @@
 
 pub mod panic_handler;
 
-// Placeholder for future Qingke platform implementations
 pub mod chip;
-pub mod driver;
\ No newline at end of file
+pub mod driver;
+pub mod pfic;
@@ -0,0 +1,41 @@
+//! QingKe Programmable Fast Interrupt Controller (PFIC)
+
+/// the base address of the PFIC
+const PFIC_BASE: usize = 0xE000_E000;
+/// interrupt enable set
+const IENR: usize = PFIC_BASE + 0x100;
+/// interrupt pending set
+const IPSR: usize = PFIC_BASE + 0x200;
+/// interrupt pending clear
+const IPRR: usize = PFIC_BASE + 0x280;
+/// interrupt priority, one byte per interrupt
+const IPRIOR: usize = PFIC_BASE + 0x400;
+
+/// the software interrupt of QingKe V4, the context switch
+pub const SOFTWARE_IRQ: u8 = 14;
+
+#[inline(always)]
+fn set_bit(base: usize, irq: u8) {
+    let reg = (base + 4 * (irq as usize / 32)) as *mut u32;
+    unsafe { reg.write_volatile(1 << (irq % 32)) }
+}
+
+/// Enable the interrupt `irq`.
+pub fn enable(irq: u8) {
+    set_bit(IENR, irq);
+}
+
+/// Set the interrupt `irq` pending.
+pub fn pend(irq: u8) {
+    set_bit(IPSR, irq);
+}
+
+/// Clear the pending interrupt `irq`.
+pub fn unpend(irq: u8) {
+    set_bit(IPRR, irq);
+}
+
+/// Set the priority of `irq`, a lower value is a higher priority.
+pub fn set_priority(irq: u8, priority: u8) {
+    unsafe { ((IPRIOR + irq as usize) as *mut u8).write_volatile(priority) }
+}
@@ -0,0 +1,2 @@
+#[cfg(feature = "qemu-virt")]
+pub mod qemu_virt;
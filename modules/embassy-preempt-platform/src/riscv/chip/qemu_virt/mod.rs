@@ -0,0 +1,6 @@
+mod platform;
+pub mod timer_driver;
+mod ucstk;
+
+pub use platform::{exit, PlatformImpl};
+pub use ucstk::UcStk;
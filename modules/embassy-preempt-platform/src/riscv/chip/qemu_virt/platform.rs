@@ -0,0 +1,122 @@
+use core::ptr::NonNull;
+
+use super::timer_driver::ClintTimer;
+use crate::riscv::context;
+use crate::traits::memory_layout::PlatformMemoryLayout;
+use crate::traits::platform::PlatformStatic;
+use crate::Platform;
+
+/// the SiFive test device of QEMU `virt`, writing it ends the emulation
+const TEST_DEVICE: usize = 0x0010_0000;
+const TEST_PASS: u32 = 0x5555;
+const TEST_FAIL: u32 = 0x3333;
+
+/// Exit QEMU with `code`, 0 being success.
+pub fn exit(code: u16) -> ! {
+    let value = if code == 0 { TEST_PASS } else { ((code as u32) << 16) | TEST_FAIL };
+    unsafe { (TEST_DEVICE as *mut u32).write_volatile(value) };
+    loop {
+        ::riscv::asm::wfi();
+    }
+}
+
+/// QEMU `virt` (RV32) platform implementation
+///
+/// ## Hardware Configuration
+///
+/// - Core: RV32IMAC hart 0 in machine mode, context switching in the machine software interrupt through
+///   `crate::riscv::context`
+/// - Timer: CLINT `mtime`/`mtimecmp` (10 MHz)
+/// - Shutdown: the SiFive test device, so `qemu-system-riscv32 -M virt` terminates at the end of a test
+pub struct PlatformImpl {
+    /// CLINT driver providing timing and alarm services
+    pub timer: ClintTimer,
+}
+
+impl PlatformImpl {
+    /// Create and initialize a new QEMU `virt` platform instance
+    pub(crate) fn new() -> Self {
+        os_log!(info, "Init Platform");
+        let timer = ClintTimer::new();
+        timer.init();
+
+        PlatformImpl { timer }
+    }
+}
+
+impl PlatformStatic for PlatformImpl {
+    fn trigger_context_switch() {
+        context::trigger_context_switch();
+    }
+
+    #[inline(always)]
+    unsafe fn save_task_context() {
+        context::save_task_context();
+    }
+
+    #[inline(always)]
+    unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize, _return_value: u32) {
+        context::restore_task_context(stack_pointer, interrupt_stack);
+    }
+
+    fn set_program_stack_pointer(sp: *mut u8) {
+        context::set_program_stack_pointer(sp);
+    }
+
+    fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+        context::configure_interrupt_stack(interrupt_stack);
+    }
+
+    fn init_task_stack(stk_ref: NonNull<usize>, executor_function: fn()) -> NonNull<usize> {
+        context::init_task_stack(stk_ref, executor_function)
+    }
+
+    fn enter_idle_state() {
+        ::riscv::asm::wfi();
+    }
+
+    fn shutdown() {
+        exit(0);
+    }
+
+    #[inline(always)]
+    unsafe fn get_current_stack_pointer() -> *mut usize {
+        context::get_current_stack_pointer()
+    }
+}
+
+/// The kernel stacks and heap start 16M into RAM, above the image and the riscv-rt stack, see `memory.x`.
+impl PlatformMemoryLayout for PlatformImpl {
+    fn get_stack_start() -> usize {
+        0x8100_0000
+    }
+
+    fn get_max_programs() -> usize {
+        10
+    }
+
+    fn get_heap_size() -> usize {
+        64 * 1024 // 64 KiB
+    }
+
+    fn get_program_stack_size() -> usize {
+        4096 // 4 KiB
+    }
+
+    fn get_interrupt_stack_size() -> usize {
+        4096 // 4 KiB
+    }
+}
+
+impl Platform for PlatformImpl {
+    fn get_timer_driver(&'static self) -> &'static dyn crate::traits::timer::Driver {
+        &self.timer
+    }
+
+    /// WFI: `mtime` keeps counting and `mtimecmp` wakes the hart
+    fn sleep_until(&'static self, _wake_at: u64) -> bool {
+        // with MIE clear, a pending enabled interrupt still ends WFI
+        ::riscv::asm::wfi();
+        true
+    }
+}
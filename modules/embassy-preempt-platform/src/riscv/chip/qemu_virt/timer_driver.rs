@@ -0,0 +1,160 @@
+//! CLINT Timer Driver Implementation
+//!
+//! The time base is the 64-bit `mtime` at 10 MHz, so `TICK_HZ` must divide 10 MHz. There is a single compare
+//! register, `mtimecmp`, which is always programmed with the earliest pending alarm (`u64::MAX` when there is none).
+
+use core::mem;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use critical_section::{CriticalSection, Mutex};
+use embassy_preempt_cfg::TICK_HZ;
+use embassy_preempt_log::{os_log, timer_log};
+
+use crate::riscv::clint;
+use crate::traits::timer::{AlarmHandle, AlarmState, Driver};
+
+/// the frequency of `mtime` on QEMU `virt`
+pub const MTIME_HZ: u64 = 10_000_000;
+/// `mtime` cycles per tick
+const CYCLES_PER_TICK: u64 = {
+    assert!(MTIME_HZ % TICK_HZ == 0, "TICK_HZ must divide the 10 MHz mtime: choose another tick-hz-* feature");
+    MTIME_HZ / TICK_HZ
+};
+
+/// the number of alarms, multiplexed on `mtimecmp`
+const ALARM_COUNT: usize = 3;
+
+/// Machine timer interrupt handler, dispatched by riscv-rt
+#[unsafe(no_mangle)]
+pub extern "C" fn MachineTimer() {
+    use crate::get_platform_trait;
+
+    os_log!(trace, "MachineTimer interrupt handler invoked");
+    unsafe {
+        get_platform_trait().get_timer_driver().on_interrupt();
+    }
+}
+
+/// CLINT Timer Driver
+pub struct ClintTimer {
+    /// Counter for tracking allocated alarm instances
+    alarm_count: AtomicU8,
+
+    /// Array of alarm states storing callbacks, contexts, and trigger timestamps
+    /// u64::MAX indicates no alarm is scheduled for that slot
+    alarms: Mutex<[AlarmState; ALARM_COUNT]>,
+}
+
+impl ClintTimer {
+    /// Create a new timer driver instance
+    pub(crate) fn new() -> Self {
+        const ALARM_STATE_NEW: AlarmState = AlarmState::new();
+        ClintTimer {
+            alarm_count: AtomicU8::new(0),
+            alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
+        }
+    }
+
+    /// Disarm `mtimecmp` and enable the machine timer interrupt
+    pub fn init(&self) {
+        os_log!(trace, "Initializing ClintTimer");
+
+        clint::set_mtimecmp(u64::MAX);
+        unsafe {
+            ::riscv::register::mie::set_mtimer();
+        }
+    }
+
+    /// Program `mtimecmp` with the earliest pending alarm
+    fn rearm(&self, cs: CriticalSection) {
+        let next = self.alarms.borrow(cs).iter().map(|alarm| alarm.timestamp.get()).min().unwrap_or(u64::MAX);
+        clint::set_mtimecmp(next.saturating_mul(CYCLES_PER_TICK));
+    }
+
+    fn get_alarm<'a>(&'a self, cs: CriticalSection<'a>, alarm: AlarmHandle) -> &'a AlarmState {
+        // safety: we're allowed to assume the AlarmState is created by us, and
+        // we never create one that's out of bounds.
+        unsafe { self.alarms.borrow(cs).get_unchecked(alarm.id() as usize) }
+    }
+
+    fn trigger_alarm(&self, n: usize, cs: CriticalSection) {
+        timer_log!(trace, "trigger_alarm");
+        let alarm = &self.alarms.borrow(cs)[n];
+        alarm.timestamp.set(u64::MAX);
+
+        // Call after clearing alarm, so the callback can set another alarm.
+
+        // safety:
+        // - we can ignore the possibility of `f` being unset (null) because of the safety contract of `allocate_alarm`.
+        // - other than that we only store valid function pointers into alarm.callback
+        let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
+        f(alarm.ctx.get());
+    }
+}
+
+impl Driver for ClintTimer {
+    fn now(&self) -> u64 {
+        clint::mtime() / CYCLES_PER_TICK
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        critical_section::with(|_| {
+            let id = self.alarm_count.load(Ordering::Relaxed);
+            if id < ALARM_COUNT as u8 {
+                self.alarm_count.store(id + 1, Ordering::Relaxed);
+                Some(AlarmHandle::new(id))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+
+            alarm.callback.set(callback as *const ());
+            alarm.ctx.set(ctx);
+        })
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        timer_log!(trace, "set the alarm at {}", timestamp);
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+            if timestamp == u64::MAX {
+                // no alarm needed
+                alarm.timestamp.set(u64::MAX);
+                self.rearm(cs);
+                return true;
+            }
+
+            if timestamp <= self.now() {
+                // If alarm timestamp has passed the alarm will not fire.
+                // Disarm the alarm and return `false` to indicate that.
+                alarm.timestamp.set(u64::MAX);
+                self.rearm(cs);
+                return false;
+            }
+            // mtimecmp fires whenever mtime >= mtimecmp, so a compare value that has just passed still fires
+            alarm.timestamp.set(timestamp);
+            self.rearm(cs);
+            true
+        })
+    }
+
+    unsafe fn on_interrupt(&self) {
+        critical_section::with(|cs| {
+            let t = self.now();
+            for n in 0..ALARM_COUNT {
+                let at = self.alarms.borrow(cs)[n].timestamp.get();
+                if at <= t {
+                    timer_log!(trace, "the alarm is triggered!!!");
+                    self.trigger_alarm(n, cs);
+                }
+            }
+            // also clears the interrupt, which is pending as long as mtime >= mtimecmp
+            self.rearm(cs);
+        })
+    }
+}
@@ -0,0 +1,38 @@
+//! SiFive Core-Local Interruptor (CLINT), as on QEMU `virt`, `sifive_e` and `sifive_u`
+//!
+//! Only hart 0 is used.
+
+/// the base address of the CLINT
+pub const CLINT_BASE: usize = 0x0200_0000;
+/// machine software interrupt pending of hart 0
+pub const MSIP: usize = CLINT_BASE;
+/// timer compare of hart 0
+const MTIMECMP: usize = CLINT_BASE + 0x4000;
+/// the machine timer
+const MTIME: usize = CLINT_BASE + 0xBFF8;
+
+/// Raise the machine software interrupt.
+#[inline(always)]
+pub fn set_msip() {
+    unsafe { (MSIP as *mut u32).write_volatile(1) }
+}
+
+/// Read the 64-bit `mtime` with two 32-bit reads.
+pub fn mtime() -> u64 {
+    loop {
+        let hi = unsafe { ((MTIME + 4) as *const u32).read_volatile() };
+        let lo = unsafe { (MTIME as *const u32).read_volatile() };
+        if hi == unsafe { ((MTIME + 4) as *const u32).read_volatile() } {
+            return ((hi as u64) << 32) | lo as u64;
+        }
+    }
+}
+
+/// Write the 64-bit `mtimecmp` without a spurious compare match in between.
+pub fn set_mtimecmp(value: u64) {
+    unsafe {
+        ((MTIMECMP + 4) as *mut u32).write_volatile(u32::MAX);
+        (MTIMECMP as *mut u32).write_volatile(value as u32);
+        ((MTIMECMP + 4) as *mut u32).write_volatile((value >> 32) as u32);
+    }
+}
@@ -0,0 +1,225 @@
+//! Context switch for RV32 in machine mode
+//!
+//! The kernel runs tasks and the executor in machine mode on the program stack, with `mscratch` holding the
+//! interrupt stack. The context switch is the machine software interrupt (the `PendSV` of RISC-V): it is raised by
+//! writing the hart's `msip`, and taken as soon as interrupts are enabled again, so the `mepc` it saves is the
+//! instruction to resume and needs no adjustment (unlike an `ecall`).
+//!
+//! The trap entry `__embassy_preempt_trap` swaps to the interrupt stack and jumps to `__ContextSwitchHandler` for the
+//! software interrupt; every other trap goes to riscv-rt's `_start_trap` on the interrupted stack, so the handlers of
+//! riscv-rt (`MachineTimer`, `MachineExternal`, exceptions) work unchanged.
+//!
+//! A chip with an RV32 core implements `PlatformStatic` by delegating to these functions.
+
+use core::arch::{asm, global_asm};
+use core::ptr::NonNull;
+
+use super::clint;
+
+/// the context saved by [`save_task_context`]: x1-x31, `mepc` and `mstatus`, in words
+pub const CONTEXT_STACK_SIZE: usize = 33;
+
+/// `mcause` of the machine software interrupt
+const MCAUSE_MACHINE_SOFT: usize = 0x8000_0003;
+/// `mie.MSIE`
+const MIE_MSIE: usize = 1 << 3;
+/// `mstatus` of a new task: MPP = machine, MPIE = 1, so `mret` enters it with interrupts enabled
+const MSTATUS_INIT: usize = 0x0000_1880;
+
+global_asm!(
+    ".section .trap, \"ax\"",
+    ".global __embassy_preempt_trap",
+    ".align 4",
+    "__embassy_preempt_trap:",
+    "csrrw sp, mscratch, sp",       // sp = interrupt stack, mscratch = interrupted stack
+    "addi sp, sp, -8",
+    "sw t0, 0(sp)",
+    "sw t1, 4(sp)",
+    "csrr t0, mcause",
+    "li t1, {mcause}",
+    "bne t0, t1, 1f",
+    // the context switch: clear the request, it may be raised again while switching
+    "li t0, {msip}",
+    "sw zero, 0(t0)",
+    "lw t0, 0(sp)",
+    "lw t1, 4(sp)",
+    "addi sp, sp, 8",
+    "j __ContextSwitchHandler",
+    // any other trap is handled by riscv-rt on the interrupted stack
+    "1:",
+    "lw t0, 0(sp)",
+    "lw t1, 4(sp)",
+    "addi sp, sp, 8",
+    "csrrw sp, mscratch, sp",
+    "j _start_trap",
+    mcause = const MCAUSE_MACHINE_SOFT,
+    msip = const clint::MSIP,
+);
+
+extern "C" {
+    fn __embassy_preempt_trap();
+}
+
+/// Request a context switch.
+#[inline(always)]
+pub fn trigger_context_switch() {
+    clint::set_msip();
+}
+
+/// Push x1-x31, `mepc` and `mstatus` of the interrupted task onto its stack and leave the new stack pointer in
+/// `mscratch`.
+///
+/// # Safety
+/// Must be called first thing in `__ContextSwitchHandler`, with `mscratch` holding the task's stack pointer.
+#[inline(always)]
+pub unsafe fn save_task_context() {
+    asm!(
+        "csrrw sp, mscratch, sp",
+        "addi sp, sp, -132",
+        "sw x1, 0(sp)",
+        "sw x2, 4(sp)",
+        "sw x3, 8(sp)",
+        "sw x4, 12(sp)",
+        "sw x5, 16(sp)",
+        "sw x6, 20(sp)",
+        "sw x7, 24(sp)",
+        "sw x8, 28(sp)",
+        "sw x9, 32(sp)",
+        "sw x10, 36(sp)",
+        "sw x11, 40(sp)",
+        "sw x12, 44(sp)",
+        "sw x13, 48(sp)",
+        "sw x14, 52(sp)",
+        "sw x15, 56(sp)",
+        "sw x16, 60(sp)",
+        "sw x17, 64(sp)",
+        "sw x18, 68(sp)",
+        "sw x19, 72(sp)",
+        "sw x20, 76(sp)",
+        "sw x21, 80(sp)",
+        "sw x22, 84(sp)",
+        "sw x23, 88(sp)",
+        "sw x24, 92(sp)",
+        "sw x25, 96(sp)",
+        "sw x26, 100(sp)",
+        "sw x27, 104(sp)",
+        "sw x28, 108(sp)",
+        "sw x29, 112(sp)",
+        "sw x30, 116(sp)",
+        "sw x31, 120(sp)",
+        // mepc and mstatus in the last two words, through t0 which is saved already
+        "csrr t0, mepc",
+        "sw t0, 124(sp)",
+        "csrr t0, mstatus",
+        "sw t0, 128(sp)",
+        "csrrw sp, mscratch, sp",
+        out("t0") _,
+    );
+}
+
+/// Load the task's context, put the interrupt stack back into `mscratch` and `mret` into the task.
+///
+/// # Safety
+/// `stack_pointer` must point to a context saved by [`save_task_context`] or built by [`init_task_stack`]. Must be
+/// called from `__ContextSwitchHandler`.
+#[inline(always)]
+pub unsafe fn restore_task_context(stack_pointer: *mut usize, interrupt_stack: *mut usize) -> ! {
+    asm!(
+        "csrw mscratch, a1",
+        "mv sp, a0",
+        "lw x1, 0(sp)",
+        "lw x3, 8(sp)",
+        "lw x4, 12(sp)",
+        "lw x5, 16(sp)",
+        "lw x6, 20(sp)",
+        "lw x7, 24(sp)",
+        "lw x8, 28(sp)",
+        "lw x9, 32(sp)",
+        "lw x11, 40(sp)",
+        "lw x12, 44(sp)",
+        "lw x13, 48(sp)",
+        "lw x14, 52(sp)",
+        "lw x15, 56(sp)",
+        "lw x16, 60(sp)",
+        "lw x17, 64(sp)",
+        "lw x18, 68(sp)",
+        "lw x19, 72(sp)",
+        "lw x20, 76(sp)",
+        "lw x21, 80(sp)",
+        "lw x22, 84(sp)",
+        "lw x23, 88(sp)",
+        "lw x24, 92(sp)",
+        "lw x25, 96(sp)",
+        "lw x26, 100(sp)",
+        "lw x27, 104(sp)",
+        "lw x28, 108(sp)",
+        "lw x29, 112(sp)",
+        "lw x30, 116(sp)",
+        "lw x31, 120(sp)",
+        // a0 is the scratch register for mepc and mstatus, it is loaded last
+        "lw a0, 124(sp)",
+        "csrw mepc, a0",
+        "lw a0, 128(sp)",
+        "csrw mstatus, a0",
+        "lw x10, 36(sp)",
+        "addi sp, sp, 132",
+        "mret",
+        in("a0") stack_pointer,
+        in("a1") interrupt_stack,
+        options(noreturn)
+    );
+}
+
+/// Set the stack the kernel switches to at [`configure_interrupt_stack`].
+pub fn set_program_stack_pointer(sp: *mut u8) {
+    unsafe {
+        asm!("csrw mscratch, {0}", in(reg) sp);
+    }
+}
+
+/// Install the trap entry, enable the software interrupt and interrupts globally, and continue on the program stack with the
+/// interrupt stack in `mscratch`.
+#[inline(never)]
+pub fn configure_interrupt_stack(interrupt_stack: *mut u8) {
+    unsafe {
+        asm!(
+            "csrw mtvec, {trap}",           // direct mode, the entry is 16-byte aligned
+            "csrs mie, {mie}",
+            "mv sp, a0",
+            "csrrw sp, mscratch, sp",       // sp = program stack, mscratch = interrupt stack
+            "csrsi mstatus, 8",             // mstatus.MIE
+            "ret",
+            trap = in(reg) __embassy_preempt_trap as usize,
+            mie = in(reg) MIE_MSIE,
+            in("a0") interrupt_stack,
+            options(noreturn)
+        );
+    }
+}
+
+/// The stack pointer of the task being switched, kept in `mscratch` by the trap entry and [`save_task_context`].
+#[inline(always)]
+pub unsafe fn get_current_stack_pointer() -> *mut usize {
+    ::riscv::register::mscratch::read() as *mut usize
+}
+
+/// Build the initial context of a task below `stk_ref`, entering `executor_function` in machine mode with
+/// interrupts enabled.
+pub fn init_task_stack(stk_ref: NonNull<usize>, executor_function: fn()) -> NonNull<usize> {
+    scheduler_log!(trace, "init_task_stack");
+    let executor_function_ptr = executor_function as *const () as usize;
+    scheduler_log!(info, "the executor function ptr is 0x{:x}", executor_function_ptr);
+    // Get stack pointer and align to 16-byte boundary as the RISC-V calling convention requires
+    let ptos = stk_ref.as_ptr();
+    let mut ptos = ((unsafe { ptos.offset(1) } as usize) & !0xF) as *mut usize;
+    // Reserve space for the context frame
+    ptos = unsafe { ptos.offset(-(CONTEXT_STACK_SIZE as isize)) };
+
+    let frame = unsafe { core::slice::from_raw_parts_mut(ptos, CONTEXT_STACK_SIZE) };
+    // x1-x31, recognizable in a debugger
+    frame[..31].fill(0x0000_0721);
+    frame[31] = executor_function_ptr;
+    frame[32] = MSTATUS_INIT;
+
+    NonNull::new(ptos).unwrap()
+}
@@ -0,0 +1 @@
+// no board drivers yet
@@ -0,0 +1,10 @@
+//! Generic RISC-V (RV32, machine mode) architecture support
+//!
+//! - [`clint`]: the SiFive CLINT (`msip`, `mtime`, `mtimecmp`)
+//! - [`context`]: the context switch, run from the machine software interrupt
+
+pub mod chip;
+pub mod clint;
+pub mod context;
+pub mod driver;
+pub mod panic_handler;
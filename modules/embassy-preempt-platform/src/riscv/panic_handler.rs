@@ -0,0 +1,19 @@
+//! Panic handler for RISC-V platforms
+
+use core::panic::PanicInfo;
+
+#[cfg(feature = "log-base")]
+#[panic_handler]
+fn panic(_info: &PanicInfo<'_>) -> ! {
+    defmt::panic!()
+}
+
+/// On QEMU `virt`, a panic ends the emulation with a failure exit code.
+#[cfg(not(feature = "log-base"))]
+#[panic_handler]
+fn panic(_info: &PanicInfo<'_>) -> ! {
+    #[cfg(feature = "qemu-virt")]
+    crate::chip::exit(1);
+    #[cfg(not(feature = "qemu-virt"))]
+    loop {}
+}
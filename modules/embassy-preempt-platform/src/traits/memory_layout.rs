@@ -1,5 +1,7 @@
 //! Platform memory layout trait definition
 
+pub use embassy_preempt_cfg::StackBand;
+
 /// Platform memory layout information trait
 ///
 /// This trait provides platform-specific memory layout information including
@@ -40,11 +42,49 @@ pub trait PlatformMemoryLayout {
         Self::get_program_stack_size()
     }
 
+    /// Get the stack size bands of the task priorities
+    ///
+    /// Returns the bands of `stack.bands` in `embassy-preempt.toml` by default, none unless configured: every task
+    /// then gets `get_task_stack_size()`.
+    fn get_stack_bands() -> &'static [StackBand] {
+        embassy_preempt_cfg::OS_STACK_BANDS
+    }
+
+    /// Get the stack size of a task of the given priority
+    ///
+    /// Returns the size of the band the priority falls in, or `get_task_stack_size()` if it is in no band.
+    fn get_task_stack_size_for_prio(prio: u8) -> usize {
+        Self::get_stack_bands()
+            .iter()
+            .find(|band| prio <= band.max_prio)
+            .map_or(Self::get_task_stack_size(), |band| band.stack_size)
+    }
+
+    /// Get the size of the stack the scheduler starts on
+    ///
+    /// The scheduler polls the tasks on the stack it runs on, so it is at least the largest band. A task too large
+    /// for the stack the scheduler is on moves it to a stack of its own size.
+    fn get_initial_program_stack_size() -> usize {
+        Self::get_stack_bands()
+            .iter()
+            .map(|band| band.stack_size)
+            .fold(Self::get_program_stack_size(), usize::max)
+    }
+
     /// Calculate total stack size based on configuration
     ///
-    /// Calculates the total stack size as: INTERRUPT_STACK_SIZE + PROGRAM_STACK_SIZE * MAX_PROGRAMS
+    /// Without stack bands, calculates the total stack size as: INTERRUPT_STACK_SIZE + PROGRAM_STACK_SIZE * MAX_PROGRAMS.
+    /// With stack bands, it is INTERRUPT_STACK_SIZE + the initial program stack + the sum of `stack_size * max_stacks`
+    /// of the bands.
     fn calculate_stack_size() -> usize {
-        Self::get_interrupt_stack_size() + (Self::get_program_stack_size() * Self::get_max_programs())
+        let bands = Self::get_stack_bands();
+        if bands.is_empty() {
+            Self::get_interrupt_stack_size() + (Self::get_program_stack_size() * Self::get_max_programs())
+        } else {
+            Self::get_interrupt_stack_size()
+                + Self::get_initial_program_stack_size()
+                + bands.iter().map(|band| band.stack_size * band.max_stacks).sum::<usize>()
+        }
     }
 
     /// Get the heap memory start address
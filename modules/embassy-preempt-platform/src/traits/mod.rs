@@ -5,5 +5,5 @@ pub mod platform;
 pub mod timer;
 
 // Re-export for convenience
-pub use memory_layout::PlatformMemoryLayout;
+pub use memory_layout::{PlatformMemoryLayout, StackBand};
 pub use platform::Platform;
@@ -83,6 +83,16 @@ pub trait PlatformStatic {
     /// Must be called in a context where stack pointer is meaningful.
     unsafe fn get_current_stack_pointer() -> *mut usize;
 
+    /// Move the stack guard to the bottom of the stack being switched in
+    ///
+    /// Called on each context switch, first with a null pointer to lift the guard of the outgoing stack, then with the
+    /// bottom (lowest address) of the incoming stack. Any access to the guarded bottom must fault.
+    ///
+    /// Architecture-specific behavior:
+    /// - ARM Cortex-M: an MPU no-access region (with the `OS_STK_MPU_GUARD_EN` feature)
+    /// - Default: no guard
+    fn set_stack_guard(_stack_bottom: *mut u8) {}
+
 }
 
 /// Core platform functionality required by the RTOS
@@ -95,4 +105,21 @@ pub trait Platform {
     /// Returns a reference to the timer driver implementation that provides
     /// timing services for the RTOS.
     fn get_timer_driver(&'static self) -> &'static dyn crate::traits::timer::Driver;
+
+    /// Sleep until `wake_at` (in timer ticks) or the next interrupt, in the deepest low-power mode that wakes up in time
+    ///
+    /// Called by the idle task with the `OS_TICKLESS_EN` feature, inside a kernel critical section, when no task is
+    /// ready and the next timer expires at `wake_at` (`u64::MAX` if none is pending). The implementation must let the
+    /// pending interrupts wake the core despite the critical section (they are taken once it ends), and leave `now()`
+    /// of the timer driver correct: the timer keeps counting, or the time spent asleep is added back.
+    ///
+    /// Returns `false` if the platform has no low-power mode, the idle task then calls `enter_idle_state`.
+    ///
+    /// Architecture-specific behavior:
+    /// - ARM Cortex-M: WFI, with SLEEPDEEP for the chip's stop mode
+    /// - RISC-V: WFI
+    /// - Default: not supported
+    fn sleep_until(&'static self, _wake_at: u64) -> bool {
+        false
+    }
 }
\ No newline at end of file
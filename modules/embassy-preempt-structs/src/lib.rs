@@ -1,2 +1,3 @@
 #![no_std]
-pub mod cell;
\ No newline at end of file
+pub mod cell;
+pub mod name;
\ No newline at end of file
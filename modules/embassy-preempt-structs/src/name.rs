@@ -0,0 +1,65 @@
+//! Names stored inline, without allocation
+
+use core::fmt;
+
+/// A name of at most `N` bytes stored inline
+///
+/// A longer name is truncated at the last char boundary which fits.
+#[derive(Clone, Copy)]
+pub struct FixedName<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedName<N> {
+    /// Create an empty name
+    pub const fn new() -> Self {
+        Self { buf: [0; N], len: 0 }
+    }
+
+    /// The name as a string slice
+    pub fn as_str(&self) -> &str {
+        // only whole chars of a str are copied in
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// The length of the name in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the name is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<const N: usize> From<&str> for FixedName<N> {
+    fn from(name: &str) -> Self {
+        let mut len = name.len().min(N);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut buf = [0; N];
+        buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self { buf, len }
+    }
+}
+
+impl<const N: usize> Default for FixedName<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedName<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedName<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}